@@ -1,16 +1,168 @@
-use std::sync::Arc;
-use rand::Rng;
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use eframe::egui::{self, FontData, FontDefinitions, Sense, Slider, Vec2};
-use crate::sim::{GPUSim, Params};
+use eframe::egui::emath::GuiRounding;
+use crate::colormap::Colormap;
+use crate::config::{preset_from_json, preset_to_json, Config, View};
+use crate::export::{burn_text, encode_png, read_coverage, read_metadata, read_rgba, ExportError, ImageMetadata};
+use crate::fallback::MAX_ITER;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::export::encode_png_gray16;
+use crate::remote::RemoteParams;
+use crate::sim::{GPUSim, Magnet, OutputFormat, OutputReadback, Params, PendulumReadout, SurfaceCamera, MAX_MAGNETS, MAX_SETTLE_WINDOW, MAX_SOFTNESS, MAX_SUBSTEPS, MAX_ZOOM, MIN_D, MIN_MASS, MIN_TOLERANCE, MIN_ZOOM, ORBIT_BINS, UNSTABLE_D};
+use crate::sim::{C_RANGE, DT_RANGE, D_RANGE, MU_RANGE, N_RANGE, R_RANGE, VELOCITY_RANGE};
 
 pub struct GPUSimApp {
     sim: GPUSim,
     is_paused: bool,
+    reduced_motion: bool, // show each parameter set's final image without animating to it
+    static_render: bool, // the same on request, for comparing final images; see `renders_static`
+    static_params: Option<Params>, // params of the final image shown, None = not rendered yet
+    static_run: Option<StaticRun>, // the static render underway, advanced a chunk per frame
+    static_image: Option<egui::TextureHandle>, // the last static render, shown while the next one runs
     width: u32,
     height: u32,
     _scale: f32,
+    fast_preview: bool, // simulate at 1/PREVIEW_DIVISOR resolution while a drag changes params
+    continuation: bool, // new params relax the current particles instead of reseeding them
+    previewing: bool, // the grid is currently shrunk for a fast preview
+    params_dragged: bool, // params changed last frame while something was dragged
+    auto_rotate: bool, // sweep velocity_angle, reseeding every frame
+    rotate_speed: f32, // degrees per second
+    blend_output: bool, // composite the image over the panel, see `GPUSim::with_blend`
+    pixel_perfect: bool, // whole screen pixels per texel, sampled without interpolation
+    output_format: OutputFormat, // requested; the sim may fall back to Rgba8
+    show_magnifier: bool,
+    magnifier_zoom: f32,
+    probe_mode: bool, // dragging on the canvas draws the probe line
+    probe: Option<(egui::Vec2, egui::Vec2)>, // endpoints as fractions of the canvas, top left origin
+    probe_profile: Vec<f32>, // color scalar sampled along the probe
+    frames_since_probe: u32,
+    pendulum: Option<PendulumReadout>, // last pendulum clicked on the canvas
+    pendulum_pending: bool, // its readback is still mapping
+    show_counter: bool, // step, frame and time overlay for lining up recordings
+    burn_counter: bool, // also draw the counter into exported PNGs
+    frame_nr: u64, // egui pass of the current frame
+    show_polar_grid: bool,
+    show_magnets: bool,
+    magnet_marker: MagnetMarker,
+    magnet_size: f32, // marker radius in world units
+    magnet_labels: bool,
+    snap_magnets: bool, // dragged magnets land on a grid of snap_step, Shift places freely
+    snap_step: f32,
+    custom_magnets: Option<Vec<Magnet>>, // placed by hand instead of the ring of n at radius r
+    show_surface: bool, // 3D height field instead of the flat image
+    surface_camera: SurfaceCamera,
+    tracer_count: u32, // pendulums drawn with trails, 0 = none
+    trail_len: u32,
+    auto_pause: bool, // pause once the run is finished, see `GPUSim::is_finished`
+    record_history: bool, // add finished runs to the history strip
+    step_budget: u64,
+    frames_since_check: u32,
+    finished_at: Option<u64>, // step the run was found finished at; repaints idle until params change or it restarts
+    history_image: Option<(Params, OutputReadback)>, // a finished run's image on its way back
+    #[cfg(target_arch = "wasm32")]
+    saved_image: Option<(ImageMetadata, Option<String>, OutputReadback)>, // taken when Save Image was clicked
+    fps_cap: u32, // repaints per second while running, 0 = as fast as the display allows
+    render_budget: bool, // adapt steps per frame to keep compute time under budget_ms
+    budget_ms: f32,
+    compute_ms: Option<f32>, // latest measured compute time
+    diverged: Option<u32>, // last count from `GPUSim::poll_diverged_count`
+    orbit_hist: Option<Vec<[u32; ORBIT_BINS]>>, // last result of `GPUSim::orbit_histogram`
+    auto_range: bool, // fit the colormap to the scalar's current range
+    frames_since_range: u32,
+    locked_range: Option<(f32, f32)>, // colormap range held while params change, for comparisons
+    remote: Option<RemoteParams>, // external params source, see `remote.rs`
+    status: String, // result of the last save/load, shown below the buttons
+    // Files picked by "Load from Image…", "Load Mask…", "Load Config…" and
+    // "Load Reference…", which arrive asynchronously on the web
+    image_tx: mpsc::Sender<Vec<u8>>,
+    image_rx: mpsc::Receiver<Vec<u8>>,
+    config_tx: mpsc::Sender<Vec<u8>>,
+    config_rx: mpsc::Receiver<Vec<u8>>,
+    preset_tx: mpsc::Sender<Vec<u8>>,
+    preset_rx: mpsc::Receiver<Vec<u8>>,
+    mask_tx: mpsc::Sender<Vec<u8>>,
+    mask_rx: mpsc::Receiver<Vec<u8>>,
+    mask_image: Option<(u32, u32, Vec<u8>)>, // coverage of the loaded mask, see `read_coverage`
+    mask_threshold: f32, // coverage above which pendulums are simulated
+    mask_as_mass: bool, // the mask sets pendulum masses instead of which are simulated
+    reference_tx: mpsc::Sender<Vec<u8>>,
+    reference_rx: mpsc::Receiver<Vec<u8>>,
+    reference: Option<(u32, u32, Vec<u8>)>, // RGBA8 image the output is compared against
+    reference_rms: Option<f32>, // RMS difference of the output from it, colors in [0, 1]
+    show_difference: bool, // overlay |output - reference| instead of the output
+    difference_gain: f32, // the overlay's amplification
+    difference: Option<egui::TextureHandle>,
+    frames_since_compare: u32,
+    show_energy: bool,
+    energy: VecDeque<(u64, f64)>, // (step, total energy) samples of the current run, oldest first
+    history: VecDeque<HistoryEntry>, // finished runs, newest first
+    slots: [Option<Params>; PARAM_SLOTS], // stashed with Shift+1..9, recalled with 1..9
+    touring: bool, // cycle through the tour slots, easing from each to the next
+    tour_slots: [bool; PARAM_SLOTS], // visited in slot order
+    tour_hold: f32, // seconds each slot runs undisturbed
+    tour_transition: f32, // seconds of easing to the next one, reseeding every frame
+    tour_stop: usize, // index into the visited slots of the one being left
+    tour_time: f32, // seconds since arriving at it
+    fade_slots: [usize; 2], // slots A and B of the crossfade
+    fade: f32, // 0 = slot A, 1 = slot B
+    gpu_errors: mpsc::Receiver<GpuError>, // from the device's callbacks, see `watch_device`
+    device_lost: Option<String>, // why the device was lost; the sim is replaced by an error panel
 }
 
+// Reported by the wgpu device outside of any call that could return it
+enum GpuError {
+    Lost(String),
+    Uncaptured(String), // e.g. a validation error, which wgpu would otherwise panic on
+}
+
+// A static render in progress. Its image is requested once the run has finished
+struct StaticRun {
+    params: Params,
+    image: Option<OutputReadback>,
+}
+
+// A finished run in the history strip
+struct HistoryEntry {
+    params: Params,
+    thumbnail: egui::TextureHandle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MagnetMarker {
+    Disc,
+    Ring,
+    Cross,
+}
+
+const MAGNIFIER_FRAC: f32 = 0.3; // inset size relative to the canvas
+const ZOOM_PER_POINT: f32 = 0.002; // log zoom per point scrolled over the canvas
+const SETTLE_SPEED: f32 = 0.01; // auto-pause once every pendulum is slower than this
+const SETTLE_CHECK_FRAMES: u32 = 30; // each check runs a reduction over the grid
+const STATIC_CHUNK_STEPS: u64 = 256; // steps a static render advances per frame
+const MAX_STEPS_PER_FRAME: u32 = 64;
+const AUTO_RANGE_FRAMES: u32 = 10; // likewise for the color range
+const COMPARE_FRAMES: u32 = 10; // and for the reference comparison
+const PROBE_FRAMES: u32 = 10; // and for the probe profile
+const PROBE_SAMPLES: usize = 256;
+const PNG_FILTER: (&str, &[&str]) = ("PNG image", &["png"]);
+const CONFIG_FILTER: (&str, &[&str]) = ("Config", &["json"]);
+const PRESET_FILTER: (&str, &[&str]) = ("Preset", &["json"]);
+const HISTORY_LEN: usize = 12;
+const THUMBNAIL_SIZE: usize = 64;
+const IDLE_REPAINT: Duration = Duration::from_millis(250); // while paused or finished, for remote params
+const PREVIEW_DIVISOR: u32 = 4;
+const ENERGY_SAMPLES: usize = 600; // one per frame
+const RESOLUTIONS: [u32; 4] = [512, 1024, 2048, 4096]; // grid sides offered in the UI
+const PARAM_SLOTS: usize = 9;
+const SLOT_KEYS: [egui::Key; PARAM_SLOTS] = [
+    egui::Key::Num1, egui::Key::Num2, egui::Key::Num3, egui::Key::Num4, egui::Key::Num5,
+    egui::Key::Num6, egui::Key::Num7, egui::Key::Num8, egui::Key::Num9,
+];
+
 impl GPUSimApp {
     pub fn new<'a>(cc: &'a eframe::CreationContext<'a>) -> Self {
         let wgpu_render_state = cc.wgpu_render_state.as_ref().unwrap();
@@ -26,18 +178,647 @@ impl GPUSimApp {
             .unwrap().insert(0, "Inter".to_owned());
         cc.egui_ctx.set_fonts(fonts);
         cc.egui_ctx.options_mut(|o| o.screen_reader = true);
+        let (image_tx, image_rx) = mpsc::channel();
+        let (mask_tx, mask_rx) = mpsc::channel();
+        let (config_tx, config_rx) = mpsc::channel();
+        let (preset_tx, preset_rx) = mpsc::channel();
+        let (reference_tx, reference_rx) = mpsc::channel();
+        let gpu_errors = watch_device(&wgpu_render_state.device, &cc.egui_ctx);
+        let sim = GPUSim::new(wgpu_render_state, width, height, scale);
         GPUSimApp {
-            sim: GPUSim::new(wgpu_render_state, width, height, scale),
+            reduced_motion: prefers_reduced_motion() && !sim.is_fragment_only(),
+            static_render: false,
+            static_params: None,
+            static_run: None,
+            static_image: None,
+            sim,
             is_paused: true,
             width,
             height,
             _scale: scale,
+            fast_preview: false,
+            continuation: false,
+            previewing: false,
+            params_dragged: false,
+            auto_rotate: false,
+            rotate_speed: 30.0,
+            blend_output: false,
+            pixel_perfect: false,
+            output_format: OutputFormat::default(),
+            show_magnifier: false,
+            magnifier_zoom: 4.0,
+            probe_mode: false,
+            probe: None,
+            probe_profile: Vec::new(),
+            frames_since_probe: 0,
+            pendulum: None,
+            pendulum_pending: false,
+            show_counter: false,
+            burn_counter: false,
+            frame_nr: 0,
+            show_polar_grid: false,
+            show_magnets: false,
+            magnet_marker: MagnetMarker::Disc,
+            magnet_size: 0.25,
+            magnet_labels: false,
+            snap_magnets: true,
+            snap_step: 0.25,
+            custom_magnets: None,
+            show_surface: false,
+            surface_camera: SurfaceCamera::default(),
+            tracer_count: 0,
+            trail_len: 256,
+            auto_pause: false,
+            record_history: true,
+            step_budget: 20_000,
+            frames_since_check: 0,
+            finished_at: None,
+            history_image: None,
+            #[cfg(target_arch = "wasm32")]
+            saved_image: None,
+            fps_cap: 0,
+            render_budget: false,
+            budget_ms: 12.0,
+            compute_ms: None,
+            diverged: None,
+            orbit_hist: None,
+            auto_range: false,
+            frames_since_range: 0,
+            locked_range: None,
+            remote: RemoteParams::from_env(),
+            status: String::new(),
+            image_tx,
+            image_rx,
+            config_tx,
+            config_rx,
+            preset_tx,
+            preset_rx,
+            mask_tx,
+            mask_rx,
+            mask_image: None,
+            mask_threshold: 0.5,
+            mask_as_mass: false,
+            reference_tx,
+            reference_rx,
+            reference: None,
+            reference_rms: None,
+            show_difference: false,
+            difference_gain: 8.0,
+            difference: None,
+            frames_since_compare: 0,
+            show_energy: false,
+            energy: VecDeque::new(),
+            history: VecDeque::new(),
+            slots: [None; PARAM_SLOTS],
+            touring: false,
+            tour_slots: [true; PARAM_SLOTS],
+            tour_hold: 5.0,
+            tour_transition: 3.0,
+            tour_stop: 0,
+            tour_time: 0.0,
+            fade_slots: [0, 1],
+            fade: 0.0,
+            gpu_errors,
+            device_lost: None,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn encode_image(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Result<Vec<u8>, ExportError> {
+        let rgba = self.sim.read_output(wgpu_render_state).ok_or(ExportError::Readback)?;
+        encode_output(rgba, self.burned_counter(wgpu_render_state).as_deref(), &self.sim.image_metadata(wgpu_render_state))
+    }
+
+    // The counter text to burn into saved images, if any
+    fn burned_counter(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<String> {
+        (self.show_counter && self.burn_counter).then(|| self.counter_text(self.sim.step_count(wgpu_render_state)))
+    }
+
+    // Shows converged images only, for accessibility or by choice
+    fn renders_static(&self) -> bool {
+        self.reduced_motion || self.static_render
+    }
+
+    // Simulation time assumes the current dt was used throughout
+    fn counter_text(&self, steps: u64) -> String {
+        format!("step {steps}  frame {}  t {:.2}", self.frame_nr, steps as f32 * self.sim.params.dt)
+    }
+
+    // The color scalar as 16-bit grayscale, spanning the color range. The range is in
+    // the embedded params, so values can be mapped back.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn encode_scalar_field(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Result<Vec<u8>, ExportError> {
+        let field = self.sim.scalar_field(wgpu_render_state).ok_or(ExportError::Readback)?;
+        let (min, max) = (self.sim.params.color_min, self.sim.params.color_max);
+        let values: Vec<u16> = field
+            .iter()
+            .map(|s| (((s - min) / (max - min).max(1e-6)).clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+            .collect();
+        encode_png_gray16(&values, &self.sim.image_metadata(wgpu_render_state))
+    }
+
+    // Applies the parameters embedded in an exported image and restarts from them
+    fn load_from_image(&mut self, bytes: &[u8], wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        let Some(metadata) = read_metadata(bytes) else {
+            self.status = "No parameters found in image".to_owned();
+            return;
+        };
+        self.sim.params = metadata.params;
+        (self.sim.params.w, self.sim.params.h) = (self.width, self.height);
+        self.sim.params.sanitize();
+        self.sim.restart(wgpu_render_state);
+        self.is_paused = true;
+        self.status = if (metadata.width, metadata.height) == (self.width, self.height) {
+            format!("Loaded parameters; run {} steps to reproduce the image", metadata.steps)
+        } else {
+            format!(
+                "Loaded parameters, but the image was rendered at {}×{}",
+                metadata.width, metadata.height
+            )
+        };
+    }
+
+    // Thresholds the loaded mask, resampled to the grid, and restarts with it. As a
+    // mass field its coverage weights masses between the centre and edge mass instead
+    fn apply_mask(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        let coverage = self.mask_image.as_ref().map(|(mask_width, mask_height, coverage)| {
+            // Particle rows run bottom to top, image rows top to bottom
+            (0..self.width * self.height)
+                .map(|i| {
+                    let (x, y) = (i % self.width, self.height - 1 - i / self.width);
+                    let (mx, my) = (x * mask_width / self.width, y * mask_height / self.height);
+                    coverage[(mx + my * mask_width) as usize]
+                })
+                .collect::<Vec<u8>>()
+        });
+        let (mask, weights) = if self.mask_as_mass {
+            (None, coverage.map(|c| c.iter().map(|&c| c as f32 / 255.0).collect()))
+        } else {
+            let threshold = (self.mask_threshold * 255.0) as u8;
+            (coverage.map(|c| c.iter().map(|&c| c > threshold).collect()), None)
+        };
+        self.sim.set_mass_field(wgpu_render_state, weights);
+        self.sim.set_mask(wgpu_render_state, mask);
+    }
+
+    // Puts newly set params into effect: reseeds for a fresh run, or in continuation
+    // mode keeps the particles where they are so they relax into the new state
+    fn switch_params(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        if !self.continuation {
+            self.sim.restart(wgpu_render_state);
+        }
+    }
+
+    // Runs the params stashed in slot i, on the current grid
+    fn recall_slot(&mut self, i: usize, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        let Some(params) = self.slots[i] else {
+            self.status = format!("Slot {} is empty", i + 1);
+            return;
+        };
+        self.sim.params = Params { w: self.sim.params.w, h: self.sim.params.h, ..params };
+        self.switch_params(wgpu_render_state);
+        self.is_paused = false;
+        self.status = format!("Recalled slot {}", i + 1);
+    }
+
+    // Filled slots the tour visits, in order
+    fn tour_stops(&self) -> Vec<usize> {
+        (0..PARAM_SLOTS).filter(|&i| self.tour_slots[i] && self.slots[i].is_some()).collect()
+    }
+
+    // Advances the tour by dt seconds. Arriving at a slot switches to it, and each
+    // frame of a transition to the params eased towards the next one.
+    // Returns whether a transition is underway
+    fn advance_tour(&mut self, dt: f32, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> bool {
+        let stops = self.tour_stops();
+        if stops.len() < 2 {
+            return false;
+        }
+        self.tour_stop %= stops.len();
+        let from = self.slots[stops[self.tour_stop]].unwrap();
+        let to = self.slots[stops[(self.tour_stop + 1) % stops.len()]].unwrap();
+        let (w, h) = (self.sim.params.w, self.sim.params.h);
+        let grid = |params: Params| Params { w, h, ..params };
+        if self.tour_time == 0.0 {
+            self.sim.params = grid(from);
+            self.switch_params(wgpu_render_state);
+        }
+        self.tour_time += dt;
+        if self.tour_time <= self.tour_hold {
+            return false;
+        }
+        let x = ((self.tour_time - self.tour_hold) / self.tour_transition.max(1e-3)).min(1.0);
+        self.sim.params = grid(from.lerp(&to, x * x * (3.0 - 2.0 * x)));
+        self.switch_params(wgpu_render_state);
+        if x >= 1.0 {
+            self.tour_stop += 1;
+            self.tour_time = 0.0;
+        }
+        true
+    }
+
+    // Switches to the params `fade` of the way from slot A to slot B, if both are filled
+    fn apply_crossfade(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        let [Some(a), Some(b)] = self.fade_slots.map(|i| self.slots[i]) else {
+            self.status = "Store both crossfade slots first".to_owned();
+            return;
+        };
+        let (w, h) = (self.sim.params.w, self.sim.params.h);
+        self.sim.params = Params { w, h, ..a.lerp(&b, self.fade) };
+        self.switch_params(wgpu_render_state);
+    }
+
+    fn is_newest_in_history(&self) -> bool {
+        self.history.front().is_some_and(|entry| bytemuck::bytes_of(&entry.params) == bytemuck::bytes_of(&self.sim.params))
+    }
+
+    // Adds a thumbnail of `rgba`, a full-size readback of the output, to the history
+    fn push_history(&mut self, ctx: &egui::Context, rgba: &[u8]) {
+        // Preview runs are throwaway low resolution versions of the next full one
+        if self.previewing || self.is_newest_in_history() {
+            return;
+        }
+        let (w, h) = (self.width as usize, self.height as usize);
+        let pixels: Vec<u8> = (0..THUMBNAIL_SIZE * THUMBNAIL_SIZE)
+            .flat_map(|i| {
+                let (x, y) = (i % THUMBNAIL_SIZE * w / THUMBNAIL_SIZE, i / THUMBNAIL_SIZE * h / THUMBNAIL_SIZE);
+                rgba[(x + y * w) * 4..][..4].iter().copied()
+            })
+            .collect();
+        let image = egui::ColorImage::from_rgba_unmultiplied([THUMBNAIL_SIZE; 2], &pixels);
+        let thumbnail = ctx.load_texture("history", image, egui::TextureOptions::LINEAR);
+        self.history.push_front(HistoryEntry { params: self.sim.params, thumbnail });
+        self.history.truncate(HISTORY_LEN);
+    }
+
+    // Steps the static render by a chunk and checks whether it has finished, without
+    // blocking on either. A finished run's image goes to the history and stays on the
+    // canvas while the next run converges
+    fn advance_static_run(&mut self, ctx: &egui::Context, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        let Some(run) = &mut self.static_run else {
+            return;
+        };
+        ctx.request_repaint();
+        let Some(image) = &run.image else {
+            let remaining = self.step_budget.saturating_sub(self.sim.step_count(wgpu_render_state));
+            if remaining > 0 {
+                let mut chunk = self.sim;
+                chunk.steps_per_frame = remaining.min(STATIC_CHUNK_STEPS) as u32;
+                chunk.advance(wgpu_render_state, 1);
+            }
+            self.sim.request_max_speed(wgpu_render_state);
+            if let Some((steps, max_speed)) = self.sim.poll_max_speed(wgpu_render_state) {
+                let settled = !run.params.is_conservative() && max_speed < SETTLE_SPEED;
+                if settled || steps >= self.step_budget {
+                    self.status = if settled {
+                        format!("Settled after {steps} steps")
+                    } else {
+                        format!("Stopped at the {steps} step budget before settling")
+                    };
+                    run.image = self.sim.request_output(wgpu_render_state);
+                    if run.image.is_none() {
+                        self.static_run = None;
+                    }
+                }
+            }
+            return;
+        };
+        let std::task::Poll::Ready(rgba) = image.poll(wgpu_render_state) else {
+            return;
+        };
+        let current = bytemuck::bytes_of(&run.params) == bytemuck::bytes_of(&self.sim.params);
+        self.static_run = None;
+        if let Some(rgba) = rgba {
+            if current {
+                self.push_history(ctx, &rgba);
+            }
+            let image = egui::ColorImage::from_rgba_unmultiplied([self.width as usize, self.height as usize], &rgba);
+            self.static_image = Some(ctx.load_texture("static render", image, egui::TextureOptions::LINEAR));
+        }
+    }
+
+    fn export_preset(&mut self) {
+        let json = match preset_to_json(&self.sim.params) {
+            Ok(json) => json,
+            Err(e) => {
+                self.status = format!("Failed to save preset: {e}");
+                return;
+            }
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter(PRESET_FILTER.0, PRESET_FILTER.1)
+            .set_file_name("magnetic-pendulum-preset.json")
+            .save_file()
+        {
+            self.status = match std::fs::write(&path, json) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(e) => format!("Failed to save preset: {e}"),
+            };
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let dialog = rfd::AsyncFileDialog::new()
+                .add_filter(PRESET_FILTER.0, PRESET_FILTER.1)
+                .set_file_name("magnetic-pendulum-preset.json");
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Some(file) = dialog.save_file().await {
+                    if let Err(e) = file.write(json.as_bytes()).await {
+                        log::warn!("Failed to save preset: {e}");
+                    }
+                }
+            });
+        }
+    }
+
+    // Opens a file dialog for a file of type `filter` and sends its bytes to `tx`
+    fn pick_file(&mut self, filter: (&str, &[&str]), tx: mpsc::Sender<Vec<u8>>, ctx: &egui::Context) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = rfd::FileDialog::new().add_filter(filter.0, filter.1).pick_file() {
+            match std::fs::read(&path) {
+                Ok(bytes) => {
+                    let _ = tx.send(bytes);
+                    ctx.request_repaint(); // picked up next frame, even when idle
+                }
+                Err(e) => self.status = format!("Failed to read {}: {e}", path.display()),
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let dialog = rfd::AsyncFileDialog::new().add_filter(filter.0, filter.1);
+            let ctx = ctx.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Some(file) = dialog.pick_file().await {
+                    let _ = tx.send(file.read().await);
+                    ctx.request_repaint();
+                }
+            });
+        }
+    }
+
+    fn view(&self) -> View {
+        View { width: self.width, height: self.height, scale: self._scale }
+    }
+
+    // Applies the params and magnet layout of a saved config, of any version, and
+    // restarts from them
+    fn load_config(&mut self, bytes: &[u8], wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        let config = match Config::from_json(&String::from_utf8_lossy(bytes)) {
+            Ok(config) => config,
+            Err(e) => {
+                self.status = e.to_string();
+                return;
+            }
+        };
+        self.sim.params = config.params;
+        (self.sim.params.w, self.sim.params.h) = (self.width, self.height);
+        self.sim.params.sanitize();
+        self.custom_magnets = config.magnets().filter(|magnets| !magnets.is_empty()).map(|mut magnets| {
+            magnets.truncate(MAX_MAGNETS);
+            magnets
+        });
+        self.sync_magnets(wgpu_render_state);
+        self.sim.restart(wgpu_render_state);
+        self.is_paused = true;
+        self.status = if config.view == self.view() {
+            "Loaded config".to_owned()
+        } else {
+            let View { width, height, scale } = config.view;
+            format!("Loaded config, but it was saved for a {width}×{height} grid at scale {scale}")
+        };
+    }
+
+    // Applies a preset like the preset buttons do, so the running field changes live
+    fn load_preset(&mut self, bytes: &[u8]) {
+        match preset_from_json(&String::from_utf8_lossy(bytes)) {
+            Ok(mut params) => {
+                (params.w, params.h) = (self.width, self.height);
+                params.sanitize();
+                self.sim.params = params;
+                self.status = "Loaded preset".to_owned();
+            }
+            Err(e) => self.status = e.to_string(),
+        }
+    }
+
+    // Updates the RMS difference from the reference image, and the overlay if it's shown
+    fn compare_to_reference(&mut self, ctx: &egui::Context, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        let Some((width, height, reference)) = &self.reference else {
+            return;
+        };
+        if (*width, *height) != (self.sim.params.w, self.sim.params.h) {
+            self.reference_rms = None;
+            self.difference = None;
+            return;
+        }
+        let Some(output) = self.sim.read_output(wgpu_render_state) else {
+            return;
+        };
+        self.reference_rms = Some(rms_difference(&output, reference));
+        self.difference = self.show_difference.then(|| {
+            let pixels: Vec<u8> = output
+                .chunks_exact(4)
+                .zip(reference.chunks_exact(4))
+                .flat_map(|(a, b)| {
+                    let diff = |c: usize| (a[c].abs_diff(b[c]) as f32 * self.difference_gain).min(255.0) as u8;
+                    [diff(0), diff(1), diff(2), 255]
+                })
+                .collect();
+            let image = egui::ColorImage::from_rgba_unmultiplied([*width as usize, *height as usize], &pixels);
+            ctx.load_texture("difference", image, egui::TextureOptions::NEAREST)
+        });
+    }
+
+    // Recreates the sim with its output texture in `format`, keeping the settings
+    fn set_output_format(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState, format: OutputFormat) {
+        let mut sim = GPUSim::with_output_format(wgpu_render_state, self.width, self.height, self._scale, format);
+        sim.params = self.sim.params;
+        sim.steps_per_frame = self.sim.steps_per_frame;
+        self.sim = sim;
+        self.sim.set_tracers(wgpu_render_state, self.tracer_count, self.trail_len);
+        self.sim.set_magnets(wgpu_render_state, self.custom_magnets.clone());
+        self.apply_mask(wgpu_render_state);
+        self.output_format = self.sim.output_format(wgpu_render_state).unwrap_or_default();
+        if self.output_format != format {
+            self.status = format!("{format:?} output is not supported here");
+        }
+    }
+
+    // The magnets as the shader sees them: the custom layout, or else the ring
+    fn magnets(&self) -> Vec<Magnet> {
+        self.custom_magnets.clone().unwrap_or_else(|| self.sim.params.ring_magnets())
+    }
+
+    // Marker and legend color of a magnet, matching its basin in the image
+    fn marker_color(&self, magnet: &Magnet) -> egui::Color32 {
+        let color = if self.custom_magnets.is_some() && self.sim.params.color_mode == 4 {
+            magnet.color
+        } else {
+            self.sim.params.rest_color(magnet.position)
+        };
+        let [r, g, b, a] = color.map(|c| (c * 255.0).round() as u8);
+        egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+
+    // Sends the custom layout to the shader, which then ignores n and r
+    fn sync_magnets(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        if let Some(magnets) = &self.custom_magnets {
+            self.sim.params.n = magnets.len() as u32;
         }
+        self.sim.set_magnets(wgpu_render_state, self.custom_magnets.clone());
+    }
+
+    fn rng(&self) -> StdRng {
+        // All randomness derives from the seed, so results only change when it does
+        StdRng::seed_from_u64(self.sim.params.seed as u64)
     }
 }
 
 impl eframe::App for GPUSimApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.frame_nr = ctx.cumulative_pass_nr();
+        while let Ok(error) = self.gpu_errors.try_recv() {
+            match error {
+                GpuError::Lost(message) => self.device_lost = Some(message),
+                GpuError::Uncaptured(message) => self.status = format!("GPU error: {message}"),
+            }
+        }
+        // A lost device stays lost, and eframe offers no way to request another
+        if let Some(message) = &self.device_lost {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.heading("The GPU device was lost");
+                ui.label(message);
+                ui.label("This happens when the graphics driver resets, the system switches GPUs or the browser reclaims the context. Reload the page or restart the app to get a new device.");
+            });
+            return;
+        }
+        if let Some(remote) = &mut self.remote {
+            if remote.apply(&mut self.sim.params) {
+                self.sim.params.sanitize();
+            }
+        }
+        if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+            while let Ok(bytes) = self.image_rx.try_recv() {
+                self.load_from_image(&bytes, wgpu_render_state);
+            }
+            while let Ok(bytes) = self.config_rx.try_recv() {
+                self.load_config(&bytes, wgpu_render_state);
+            }
+            while let Ok(bytes) = self.preset_rx.try_recv() {
+                self.load_preset(&bytes);
+            }
+            while let Ok(bytes) = self.mask_rx.try_recv() {
+                self.mask_image = read_coverage(&bytes);
+                if self.mask_image.is_none() {
+                    self.status = "Could not decode the mask image".to_owned();
+                }
+                self.apply_mask(wgpu_render_state);
+            }
+            while let Ok(bytes) = self.reference_rx.try_recv() {
+                self.reference = read_rgba(&bytes);
+                self.frames_since_compare = COMPARE_FRAMES;
+                if self.reference.is_none() {
+                    self.status = "Could not decode the reference image".to_owned();
+                }
+            }
+            // Slot hotkeys, unless typing into a field
+            if !ctx.wants_keyboard_input() {
+                match ctx.input_mut(consume_slot_key) {
+                    Some((i, true)) => {
+                        self.slots[i] = Some(self.sim.params);
+                        self.status = format!("Stored slot {}", i + 1);
+                    }
+                    Some((i, false)) => self.recall_slot(i, wgpu_render_state),
+                    None => {}
+                }
+            }
+            if let Some(diverged) = self.sim.poll_diverged_count(wgpu_render_state) {
+                self.diverged = Some(diverged);
+            }
+            if self.pendulum_pending {
+                match self.sim.poll_pendulum(wgpu_render_state) {
+                    Some(pendulum) => {
+                        self.pendulum = Some(pendulum);
+                        self.pendulum_pending = false;
+                    }
+                    // Keep polling while paused, when frames are rare
+                    None => ctx.request_repaint(),
+                }
+            }
+            if let Some((a, b)) = self.probe.filter(|_| !self.sim.is_fragment_only()) {
+                self.frames_since_probe += 1;
+                if self.frames_since_probe >= PROBE_FRAMES {
+                    self.frames_since_probe = 0;
+                    self.sim.request_scalars(wgpu_render_state, &line_cells(self.sim.params.w, self.sim.params.h, a, b));
+                }
+                if let Some(profile) = self.sim.poll_scalars(wgpu_render_state) {
+                    self.probe_profile = profile;
+                }
+            }
+            if self.show_energy {
+                let step = self.sim.step_count(wgpu_render_state);
+                let last = self.energy.back().map(|&(step, _)| step);
+                // A new run starts a new plot
+                if last.is_some_and(|last| step < last) {
+                    self.energy.clear();
+                }
+                if last != Some(step) {
+                    if let Some(energy) = self.sim.total_energy(wgpu_render_state) {
+                        self.energy.push_back((step, energy));
+                        if self.energy.len() > ENERGY_SAMPLES {
+                            self.energy.pop_front();
+                        }
+                    }
+                }
+            }
+            if self.reference.is_some() {
+                self.frames_since_compare += 1;
+                if self.frames_since_compare >= COMPARE_FRAMES {
+                    self.frames_since_compare = 0;
+                    self.compare_to_reference(ctx, wgpu_render_state);
+                }
+            }
+        }
+        // Fast preview shrinks the grid once a drag starts changing params, or while the
+        // velocity rotates, and restores it afterwards. Both switches restart the run
+        let rotating = self.auto_rotate && !self.is_paused;
+        if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+            let touring = self.touring && !self.is_paused && self.advance_tour(ctx.input(|i| i.stable_dt), wgpu_render_state);
+            let dragging = ctx.dragged_id().is_some();
+            let interacting = (dragging && (self.params_dragged || self.previewing)) || rotating || touring;
+            // A preview grid would throw away the state a continuation carries over
+            if self.fast_preview && !self.continuation && !self.previewing && interacting {
+                let (w, h) = ((self.width / PREVIEW_DIVISOR).max(1), (self.height / PREVIEW_DIVISOR).max(1));
+                self.sim.resize(wgpu_render_state, w, h);
+                self.previewing = true;
+            } else if self.previewing && (!interacting || !self.fast_preview || self.continuation) {
+                self.sim.resize(wgpu_render_state, self.width, self.height);
+                self.previewing = false;
+            }
+            if rotating {
+                let step = (self.rotate_speed * ctx.input(|i| i.stable_dt)).to_radians();
+                self.sim.params.velocity_angle = (self.sim.params.velocity_angle + step).rem_euclid(std::f32::consts::TAU);
+                self.sim.restart(wgpu_render_state);
+            }
+        }
+        let params_before = self.sim.params;
+
+        // Static rendering runs each new parameter set to the end, a chunk of steps per
+        // frame, and shows the previous result until then. Params changed by a drag, of
+        // the canvas or a slider, start their run on the frame the drag stops rather than
+        // one per frame of it. egui ends the drag (`drag_stopped`) before this pass runs
+        if let Some(wgpu_render_state) = frame.wgpu_render_state().filter(|_| self.renders_static()) {
+            let params = self.sim.params;
+            let changed = self.static_params.is_none_or(|p| bytemuck::bytes_of(&p) != bytemuck::bytes_of(&params));
+            if changed && ctx.dragged_id().is_none() {
+                self.switch_params(wgpu_render_state);
+                self.static_params = Some(params);
+                // With dt = 0 the run would never reach its budget
+                self.static_run = (params.dt != 0.0).then_some(StaticRun { params, image: None });
+            }
+            self.advance_static_run(ctx, wgpu_render_state);
+        }
+
         egui::SidePanel::left("Settings").show(ctx, |ui| {
             ui.heading("GPU Magnetic Pendulum Simulation");
             ui.separator();
@@ -50,46 +831,371 @@ impl eframe::App for GPUSimApp {
                 }
                 ui.label(if self.is_paused { "Simulation Paused" } else { "Simulation Running" });
             });
+            let toggled = ui
+                .add_enabled(!self.sim.is_fragment_only(), egui::Checkbox::new(&mut self.reduced_motion, "Reduced motion"))
+                .on_hover_text("Shows only the final image of each parameter set instead of animating towards it")
+                .on_disabled_hover_text("The fragment-only path can only compute frame by frame")
+                .changed();
+            let toggled = toggled
+                | ui.add_enabled(!self.sim.is_fragment_only(), egui::Checkbox::new(&mut self.static_render, "Static render"))
+                    .on_hover_text("Renders each parameter change to convergence once the drag ends and shows only the result, with no animation in between")
+                    .on_disabled_hover_text("The fragment-only path can only compute frame by frame")
+                    .changed();
+            if toggled {
+                self.static_params = None;
+                (self.static_run, self.static_image) = (None, None);
+            }
+            if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                let mut side = self.width;
+                ui.add_enabled_ui(!self.sim.is_fragment_only(), |ui| ui.horizontal(|ui| {
+                    ui.label("Resolution:");
+                    egui::ComboBox::from_id_salt("resolution")
+                        .selected_text(format!("{}×{}", self.width, self.height))
+                        .show_ui(ui, |ui| {
+                            for option in RESOLUTIONS {
+                                ui.add_enabled_ui(GPUSim::fits_device(wgpu_render_state, option, option), |ui| {
+                                    ui.selectable_value(&mut side, option, format!("{option}×{option}"))
+                                        .on_disabled_hover_text("Exceeds this device's texture or buffer size limits");
+                                });
+                            }
+                        })
+                        .response
+                        .on_hover_text("Pendulums per side. Smaller grids trade detail for speed. Restarts the run.")
+                        .on_disabled_hover_text("The fragment-only path always renders at the window's resolution");
+                }));
+                if side != self.width {
+                    (self.width, self.height) = (side, side);
+                    self.sim.resize(wgpu_render_state, side, side);
+                    self.previewing = false;
+                    self.static_params = None;
+                    self.static_run = None;
+                    // The mask was resampled to the old grid
+                    if self.mask_image.is_some() {
+                        self.apply_mask(wgpu_render_state);
+                    }
+                }
+                ui.horizontal(|ui| {
+                    let params = &mut self.sim.params;
+                    ui.label(format!("View: {:.1}× at ({:.3}, {:.3})", params.zoom, params.center_x, params.center_y))
+                        .on_hover_text("Scroll over the image to zoom toward the cursor and drag it to pan. The grid is reseeded over the new region.");
+                    let moved = params.zoom != 1.0 || params.view_center() != glam::Vec2::ZERO;
+                    if ui.add_enabled(moved, egui::Button::new("Reset View")).clicked() {
+                        (params.center_x, params.center_y, params.zoom) = (0.0, 0.0, 1.0);
+                        if !self.renders_static() {
+                            self.sim.restart(wgpu_render_state);
+                        }
+                    }
+                });
+            }
+            ui.add_enabled(!self.sim.is_fragment_only(), egui::Checkbox::new(&mut self.fast_preview, "Fast preview while dragging"))
+                .on_hover_text(format!(
+                    "Simulates at 1/{PREVIEW_DIVISOR} resolution while a slider or magnet is dragged, then restarts at full resolution"
+                ))
+                .on_disabled_hover_text("The fragment-only path always renders at the window's resolution");
+            ui.add_enabled_ui(!self.sim.is_fragment_only(), |ui| ui.horizontal(|ui| {
+                ui.label("New params:");
+                ui.selectable_value(&mut self.continuation, false, "Re-seed")
+                    .on_hover_text("Recalled slots, history entries, tours and static renders start over from the initial grid");
+                ui.selectable_value(&mut self.continuation, true, "Continue from current state")
+                    .on_hover_text("Keeps the particles where they are, so the system relaxes from the previous result into the new one and sweeps morph smoothly. Disables fast preview.");
+            }));
+
+            if self.sim.is_fragment_only() {
+                ui.label(format!(
+                    "No compute shaders on this device: every frame recomputes each pixel from the start, up to {MAX_ITER} steps"
+                ));
+            }
+
+            // Auto-pause
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.auto_pause, "Auto-pause after")
+                    .on_hover_text("Pauses once every pendulum has settled, or after the step budget in conservative mode");
+                ui.add(egui::DragValue::new(&mut self.step_budget).range(1..=10_000_000).suffix(" steps"));
+            });
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("convergence_metric")
+                    .selected_text(match self.sim.params.convergence_metric {
+                        1 => "Drift",
+                        _ => "Speed",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.sim.params.convergence_metric, 0, "Speed")
+                            .on_hover_text("Settled once slower than a threshold");
+                        ui.selectable_value(&mut self.sim.params.convergence_metric, 1, "Drift")
+                            .on_hover_text("Settled once the net displacement over a window of steps is small, ignoring fast oscillation in place");
+                    });
+                ui.label("Settled by");
+                if self.sim.params.convergence_metric == 1 {
+                    ui.add(egui::DragValue::new(&mut self.sim.params.settle_window).range(1..=MAX_SETTLE_WINDOW).suffix(" step window"));
+                }
+            });
+            if self.sim.params.is_conservative() {
+                ui.label("Conservative mode (no convergence): friction is zero, so energy is conserved and runs stop on the step budget");
+            }
+            ui.checkbox(&mut self.record_history, "Record history")
+                .on_hover_text("Adds a thumbnail of each finished run to the strip along the bottom");
+            // Finished runs go into the history, pause if asked to and let repaints idle.
+            // Static renders check their own runs
+            if let Some(wgpu_render_state) = frame.wgpu_render_state().filter(|_| !self.is_paused && !self.renders_static()) {
+                self.frames_since_check += 1;
+                if self.frames_since_check >= SETTLE_CHECK_FRAMES {
+                    self.frames_since_check = 0;
+                    self.sim.request_max_speed(wgpu_render_state);
+                }
+                if self.sim.poll_finished(wgpu_render_state, SETTLE_SPEED, self.step_budget) == Some(true) {
+                    self.finished_at = Some(self.sim.step_count(wgpu_render_state));
+                    self.is_paused |= self.auto_pause;
+                    if self.record_history && self.history_image.is_none() && !self.is_newest_in_history() {
+                        self.history_image = self.sim.request_output(wgpu_render_state).map(|image| (self.sim.params, image));
+                    }
+                }
+            }
+            // Only added while the run it shows is still the current one
+            if let (Some((params, image)), Some(wgpu_render_state)) = (&self.history_image, frame.wgpu_render_state()) {
+                match image.poll(wgpu_render_state) {
+                    std::task::Poll::Ready(rgba) => {
+                        let current = bytemuck::bytes_of(params) == bytemuck::bytes_of(&self.sim.params);
+                        self.history_image = None;
+                        if let Some(rgba) = rgba.filter(|_| current) {
+                            self.push_history(ui.ctx(), &rgba);
+                        }
+                    }
+                    // Keep polling once auto-paused, when frames are rare
+                    std::task::Poll::Pending => ui.ctx().request_repaint(),
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.add(Slider::new(&mut self.fps_cap, 0..=240).custom_formatter(|fps, _| {
+                    if fps == 0.0 { "unlimited".to_owned() } else { format!("{fps}") }
+                }));
+                ui.label("Frame rate cap").on_hover_text("Limits repaints per second to save power. While paused it only repaints on input");
+            });
+            // Steps per frame, set by hand unless the compute budget adapts it
+            ui.horizontal(|ui| {
+                ui.add_enabled(!self.render_budget, Slider::new(&mut self.sim.steps_per_frame, 1..=MAX_STEPS_PER_FRAME))
+                    .on_hover_text("Time steps of dt run between repaints. Fast-forwards at a small, accurate dt; each frame costs that many steps of GPU time")
+                    .on_disabled_hover_text("Set by the compute budget");
+                ui.label("Steps per frame");
+            });
+            // Render budget
+            let has_timer = frame.wgpu_render_state().is_some_and(|rs| self.sim.has_compute_timer(rs));
+            ui.horizontal(|ui| {
+                let toggled = ui.add_enabled(has_timer, egui::Checkbox::new(&mut self.render_budget, "Compute budget"))
+                    .on_hover_text("Adjusts the steps run per frame to keep the GPU time of the simulation under this budget")
+                    .on_disabled_hover_text("Needs GPU timestamp queries, which this device doesn't support")
+                    .changed();
+                if toggled && !self.render_budget {
+                    self.sim.steps_per_frame = 1;
+                }
+                ui.add_enabled(self.render_budget, Slider::new(&mut self.budget_ms, 2.0..=50.0).suffix(" ms"));
+            });
+            if self.render_budget {
+                if let Some(ms) = frame.wgpu_render_state().and_then(|rs| self.sim.take_compute_time(rs)) {
+                    self.compute_ms = Some(ms);
+                    // Paused frames are a single no-op step, so they say nothing about the cost
+                    if !self.is_paused {
+                        let steps = self.sim.steps_per_frame;
+                        if ms > self.budget_ms {
+                            // Over budget: cut back in proportion right away
+                            self.sim.steps_per_frame = ((steps as f32 * self.budget_ms / ms) as u32).max(1);
+                        } else if ms < 0.8 * self.budget_ms {
+                            // Headroom: ramp up one step at a time
+                            self.sim.steps_per_frame = (steps + 1).min(MAX_STEPS_PER_FRAME);
+                        }
+                    }
+                }
+                ui.label(format!(
+                    "{} steps per frame, {}",
+                    self.sim.steps_per_frame,
+                    self.compute_ms.map_or("measuring…".to_owned(), |ms| format!("{ms:.1} ms")),
+                ));
+            }
             ui.separator();
             
             ui.label("Simulation Parameters:");
             ui.add_space(10.0);
             
-            // Number of magnets
+            // Number of magnets, unless they are placed by hand
+            let ring = self.custom_magnets.is_none();
             ui.horizontal(|ui| {
-                ui.add(Slider::new(&mut self.sim.params.n, 3..=10));
+                ui.add_enabled(ring, Slider::new(&mut self.sim.params.n, N_RANGE));
                 ui.label("Number of magnets");
             });
             
             // Magnet radius from center
             ui.horizontal(|ui| {
-                ui.add(Slider::new(&mut self.sim.params.r, 1.0..=10.0).step_by(0.1));
+                ui.add_enabled(ring, Slider::new(&mut self.sim.params.r, R_RANGE).step_by(0.1));
                 ui.label("Magnet radius from center");
             });
+
+            // Per-magnet position, strength and color
+            egui::CollapsingHeader::new("Magnet layout").show(ui, |ui| {
+                let mut changed = false;
+                let mut custom = self.custom_magnets.is_some();
+                if ui.checkbox(&mut custom, "Custom layout")
+                    .on_hover_text("Place each magnet and set its strength and color, starting from the current ring")
+                    .changed() {
+                    self.custom_magnets = custom.then(|| self.sim.params.ring_magnets());
+                    changed = true;
+                }
+                if let Some(magnets) = &mut self.custom_magnets {
+                    let mut remove = None;
+                    let count = magnets.len();
+                    for (i, magnet) in magnets.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            changed |= ui.color_edit_button_rgba_unmultiplied(&mut magnet.color).changed();
+                            changed |= ui.add(egui::DragValue::new(&mut magnet.position.x).speed(0.01).range(-10.0..=10.0).prefix("x ")).changed();
+                            changed |= ui.add(egui::DragValue::new(&mut magnet.position.y).speed(0.01).range(-10.0..=10.0).prefix("y ")).changed();
+                            changed |= ui.add(Slider::new(&mut magnet.strength, 0.0..=4.0).text("strength")).changed();
+                            if ui.add_enabled(count > 1, egui::Button::new("✖")).on_hover_text("Remove this magnet").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = remove {
+                        magnets.remove(i);
+                        changed = true;
+                    }
+                    if ui.add_enabled(count < MAX_MAGNETS, egui::Button::new("Add magnet")).clicked() {
+                        let color = self.sim.params.rest_color(glam::Vec2::ZERO);
+                        magnets.push(Magnet::new(glam::Vec2::ZERO, 1.0, color));
+                        changed = true;
+                    }
+                }
+                if changed {
+                    if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                        self.sync_magnets(wgpu_render_state);
+                    }
+                }
+            });
             
             // Distance parameter
             ui.horizontal(|ui| {
-                ui.add(Slider::new(&mut self.sim.params.d, 0.1..=2.0).step_by(0.01));
+                ui.add(Slider::new(&mut self.sim.params.d, D_RANGE).step_by(0.01));
                 ui.label("Distance parameter");
             });
+            ui.horizontal(|ui| {
+                ui.add(Slider::new(&mut self.sim.params.softness, 1.0..=MAX_SOFTNESS).step_by(0.05))
+                    .on_hover_text("How sharply the force rises near a magnet: it falls off as 1 / (dist² + d²)^(softness / 2). 3 is the inverse square law");
+                ui.label("Softness exponent");
+            });
+            if self.sim.params.d < UNSTABLE_D {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("⚠ d is close to zero (at least {MIN_D} is enforced): the force near each magnet is nearly singular, so results are numerically unstable"),
+                );
+            }
             
             // Friction coefficient
             ui.horizontal(|ui| {
-                ui.add(Slider::new(&mut self.sim.params.mu, 0.0..=1.0).step_by(0.01));
+                ui.add(Slider::new(&mut self.sim.params.mu, MU_RANGE).step_by(0.01));
                 ui.label("Friction coefficient");
             });
             
-            // Spring constant
+            // Spring constant, optionally per axis
+            let mut linked = self.sim.params.anisotropic_spring == 0;
             ui.horizontal(|ui| {
-                ui.add(Slider::new(&mut self.sim.params.c, 0.0..=1.0).step_by(0.01));
+                ui.add_enabled(linked, Slider::new(&mut self.sim.params.c, C_RANGE).step_by(0.01));
                 ui.label("Spring constant");
+                if ui.checkbox(&mut linked, "Link x/y")
+                    .on_hover_text("Unlink to give the spring separate constants along x and y, which stretches the basins")
+                    .changed() {
+                    self.sim.params.anisotropic_spring = (!linked) as u32;
+                    (self.sim.params.cx, self.sim.params.cy) = (self.sim.params.c, self.sim.params.c);
+                }
+            });
+            if !linked {
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.sim.params.cx, C_RANGE).step_by(0.01));
+                    ui.label("Spring constant x");
+                });
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.sim.params.cy, C_RANGE).step_by(0.01));
+                    ui.label("Spring constant y");
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.add(Slider::new(&mut self.sim.params.spring_cubic, 0.0..=0.5).step_by(0.001))
+                    .on_hover_text("Adds a cubic term to the spring, c·(|u| + k|u|³); 0 is a linear spring");
+                ui.label("Spring cubic term");
+            });
+
+            // Seeded mass, from the centre out or from the mask
+            ui.horizontal(|ui| {
+                ui.add(Slider::new(&mut self.sim.params.mass_min, MIN_MASS..=10.0).logarithmic(true))
+                    .on_hover_text("Mass of pendulums at the centre, or where the mass field is transparent. Takes effect on restart.");
+                ui.label("Mass (centre)");
+            });
+            ui.horizontal(|ui| {
+                ui.add(Slider::new(&mut self.sim.params.mass_max, MIN_MASS..=10.0).logarithmic(true))
+                    .on_hover_text("Mass of pendulums at the edge of the view, or where the mass field is opaque. Takes effect on restart.");
+                ui.label("Mass (edge)");
             });
             
             // Time step
             ui.horizontal(|ui| {
-                ui.add(Slider::new(&mut self.sim.params.dt, 0.001..=0.05).step_by(0.001));
+                ui.add(Slider::new(&mut self.sim.params.dt, DT_RANGE).step_by(0.001));
                 ui.label("Time step (dt)");
             });
+
+            // Magnet cutoff radius
+            ui.horizontal(|ui| {
+                ui.add(Slider::new(&mut self.sim.params.cutoff, 0.0..=10.0).step_by(0.1))
+                    .on_hover_text("Magnets further than this from a pendulum are skipped, which speeds up large magnet counts but slightly changes the result. 0 disables the cutoff.");
+                ui.label("Magnet cutoff radius");
+            });
+
+            // Integrator
+            ui.horizontal(|ui| {
+                ui.label("Integrator:");
+                egui::ComboBox::from_id_salt("integrator")
+                    .selected_text(match self.sim.params.integrator {
+                        0 => "Euler",
+                        1 => "RK4",
+                        _ => "Adaptive RK45",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.sim.params.integrator, 0, "Euler - semi-implicit, first order");
+                        ui.selectable_value(&mut self.sim.params.integrator, 1, "RK4 - classic fourth order")
+                            .on_hover_text("About 4x the cost of an Euler step, but stays stable at roughly 2-3x larger dt and keeps the basin boundaries sharp near the top of the dt range");
+                        ui.selectable_value(&mut self.sim.params.integrator, 2, "Adaptive RK45 - Dormand–Prince with error control");
+                    });
+            });
+            if self.sim.params.integrator == 2 {
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.sim.params.tolerance, MIN_TOLERANCE..=1e-1).logarithmic(true))
+                        .on_hover_text("Local error allowed per substep, relative to the pendulum's position and speed. Pendulums in violent regions split dt into more substeps to meet it.");
+                    ui.label("Tolerance");
+                });
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.sim.params.max_substeps, 1..=MAX_SUBSTEPS))
+                        .on_hover_text("Caps the substeps per time step, bounding GPU time; the last one takes what's left of dt whatever its error. Each costs about two RK4 steps.");
+                    ui.label("Max substeps");
+                });
+            }
+
+            // Integrator comparison
+            let mut compare = self.sim.params.compare_integrators != 0;
+            if ui.checkbox(&mut compare, "Compare integrators")
+                .on_hover_text("Splits the canvas: Euler on the left, RK4 on the right, with identical parameters and starting state. Drag the divider to move it.")
+                .changed() {
+                self.sim.params.compare_integrators = compare as u32;
+            }
+
+            // Energy over the run, to check the integrators
+            ui.add_enabled_ui(!self.sim.is_fragment_only(), |ui| {
+                if ui.checkbox(&mut self.show_energy, "Plot total energy")
+                    .on_hover_text("Kinetic plus potential energy summed over all pendulums, each frame. Without friction it should stay constant, and any drift is integrator error; with friction it should only decrease.")
+                    .changed() {
+                    self.energy.clear();
+                }
+            });
+            if self.show_energy {
+                if let (Some(&(_, first)), Some(&(_, last))) = (self.energy.front(), self.energy.back()) {
+                    ui.label(format!("E = {last:.4e}, drift {:+.3e} ({:+.2e} relative)", last - first, (last - first) / first.abs().max(1e-12)));
+                }
+                plot_series(ui, self.energy.iter().map(|&(_, energy)| energy));
+            }
             
             ui.separator();
             ui.label("Initial Velocity Settings:");
@@ -97,7 +1203,7 @@ impl eframe::App for GPUSimApp {
             
             // Velocity magnitude
             ui.horizontal(|ui| {
-                ui.add(Slider::new(&mut self.sim.params.velocity_magnitude, 0.0..=10.0)
+                ui.add(Slider::new(&mut self.sim.params.velocity_magnitude, VELOCITY_RANGE)
                     .step_by(0.1)
                     .text("magnitude"))
                     .on_hover_text("Controls how fast particles start moving");
@@ -116,6 +1222,11 @@ impl eframe::App for GPUSimApp {
                 }
                 ui.label("Velocity angle (°)");
             });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.auto_rotate, "Auto-rotate")
+                    .on_hover_text("Turns the velocity angle continuously, restarting from the new seed every frame");
+                ui.add_enabled(self.auto_rotate, Slider::new(&mut self.rotate_speed, -180.0..=180.0).suffix("°/s"));
+            });
             
             // Velocity pattern
             ui.horizontal(|ui| {
@@ -136,6 +1247,41 @@ impl eframe::App for GPUSimApp {
                     });
             });
             
+            // Initial position jitter
+            ui.horizontal(|ui| {
+                ui.label("Position jitter:")
+                    .on_hover_text("Sub-pixel offset of each particle's starting point. Stepping through samples and averaging supersamples the image; low-discrepancy patterns converge faster.");
+                egui::ComboBox::from_id_salt("jitter_pattern")
+                    .selected_text(match self.sim.params.jitter_pattern {
+                        0 => "None",
+                        1 => "White noise",
+                        2 => "Halton",
+                        _ => "Gradient noise",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.sim.params.jitter_pattern, 0, "None - pixel corners");
+                        ui.selectable_value(&mut self.sim.params.jitter_pattern, 1, "White noise - independent random offsets");
+                        ui.selectable_value(&mut self.sim.params.jitter_pattern, 2, "Halton - low-discrepancy sequence");
+                        ui.selectable_value(&mut self.sim.params.jitter_pattern, 3, "Gradient noise - interleaved gradient noise, a cheap stand-in for blue noise");
+                    });
+                ui.add_enabled(
+                    self.sim.params.jitter_pattern != 0,
+                    egui::DragValue::new(&mut self.sim.params.jitter_sample).prefix("sample "),
+                );
+            });
+
+            // Seeding coordinates
+            ui.horizontal(|ui| {
+                ui.label("Seed grid:")
+                    .on_hover_text("Polar unrolls the plane: columns sweep the angle and rows the radius out to the view edge. Takes effect on restart.");
+                egui::ComboBox::from_id_salt("seed_coords")
+                    .selected_text(if self.sim.params.seed_coords == 1 { "Polar" } else { "Cartesian" })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.sim.params.seed_coords, 0, "Cartesian - pixels map to x, y");
+                        ui.selectable_value(&mut self.sim.params.seed_coords, 1, "Polar - columns are angles, rows radii");
+                    });
+            });
+
             // Visual indicator for velocity pattern
             ui.horizontal(|ui| {
                 ui.label("Preview:");
@@ -168,6 +1314,301 @@ impl eframe::App for GPUSimApp {
                 }
             });
             
+            ui.separator();
+            ui.label("Display Settings:");
+            ui.add_space(5.0);
+
+            // Color scalar and its range
+            ui.horizontal(|ui| {
+                let before = self.sim.params.color_mode;
+                egui::ComboBox::from_id_salt("color_mode")
+                    .selected_text(match self.sim.params.color_mode {
+                        1 => "Speed",
+                        2 => "Settling time",
+                        3 => "Activity",
+                        4 => "Magnet",
+                        _ => "Final angle",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.sim.params.color_mode, 0, "Final angle");
+                        ui.selectable_value(&mut self.sim.params.color_mode, 1, "Speed");
+                        ui.selectable_value(&mut self.sim.params.color_mode, 2, "Settling time")
+                            .on_hover_text("Simulated time until each pendulum last moved faster than a settled one, comparable across time steps");
+                        ui.selectable_value(&mut self.sim.params.color_mode, 3, "Activity")
+                            .on_hover_text("Distance each pendulum moved during the last frame: bright where motion continues, dark where it has settled");
+                        ui.selectable_value(&mut self.sim.params.color_mode, 4, "Magnet")
+                            .on_hover_text("Color of the magnet nearest each pendulum, as set under Magnet layout");
+                    });
+                if self.sim.params.color_mode != before {
+                    (self.sim.params.color_min, self.sim.params.color_max) = self.sim.params.default_color_range();
+                    // Settling times grow with the run and activity fades, so no fixed range fits them
+                    self.auto_range |= matches!(self.sim.params.color_mode, 2 | 3) && !self.sim.is_fragment_only();
+                    // A range of the old scalar means nothing for the new one
+                    self.locked_range = None;
+                }
+                ui.label("Color by");
+            });
+            ui.add_enabled(!self.sim.is_fragment_only(), egui::Checkbox::new(&mut self.auto_range, "Auto range"))
+                .on_hover_text("Stretches the colormap over the smallest to largest value currently shown")
+                .on_disabled_hover_text("The fragment-only path keeps no state to read the range back from");
+            ui.horizontal(|ui| {
+                let mut locked = self.locked_range.is_some();
+                if ui.checkbox(&mut locked, "Lock color range")
+                    .on_hover_text("Holds the current range while parameters change, so a color means the same value before and after")
+                    .changed() {
+                    self.locked_range = locked.then_some((self.sim.params.color_min, self.sim.params.color_max));
+                }
+                if locked && ui.button("Re-range")
+                    .on_hover_text("Locks the range of the values currently shown instead")
+                    .clicked() {
+                    if let Some(range) = frame.wgpu_render_state().and_then(|rs| self.sim.scalar_range(rs)) {
+                        self.locked_range = Some(range);
+                    }
+                }
+            });
+            if let Some(wgpu_render_state) = frame.wgpu_render_state().filter(|_| self.auto_range && self.locked_range.is_none()) {
+                self.frames_since_range += 1;
+                if self.frames_since_range >= AUTO_RANGE_FRAMES {
+                    self.frames_since_range = 0;
+                    self.sim.request_scalar_range(wgpu_render_state);
+                }
+                if let Some((min, max)) = self.sim.poll_scalar_range(wgpu_render_state) {
+                    (self.sim.params.color_min, self.sim.params.color_max) = (min, max);
+                }
+            }
+            ui.add_enabled_ui(!self.auto_range && self.locked_range.is_none(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.sim.params.color_min).speed(0.01).prefix("min "));
+                    ui.add(egui::DragValue::new(&mut self.sim.params.color_max).speed(0.01).prefix("max "));
+                    ui.label("Color range");
+                });
+            });
+            ui.horizontal(|ui| {
+                ui.add(Slider::new(&mut self.sim.params.banding, 0..=32))
+                    .on_hover_text("Quantizes the colormap into this many flat bands for contour plots; 0 or 1 keeps it smooth");
+                ui.label("Color bands");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Colormap:");
+                egui::ComboBox::from_id_salt("colormap")
+                    .selected_text(Colormap::from_index(self.sim.params.colormap).name())
+                    .show_ui(ui, |ui| {
+                        for map in Colormap::ALL {
+                            ui.selectable_value(&mut self.sim.params.colormap, map.index(), map.name());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Viridis, Inferno and Magma are perceptually uniform, so equal steps in the scalar look equally different");
+            });
+            let mut invert = self.sim.params.invert_colormap != 0;
+            if ui.checkbox(&mut invert, "Invert colormap").changed() {
+                self.sim.params.invert_colormap = invert as u32;
+            }
+            let mut compose = self.sim.params.compose_colormaps != 0;
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut compose, "Second colormap")
+                    .on_hover_text("Switches to another colormap above the split, e.g. for slow pendulums in settling time mode")
+                    .changed() {
+                    self.sim.params.compose_colormaps = compose as u32;
+                }
+                let secondary = Colormap::from_index(self.sim.params.secondary_colormap);
+                ui.add_enabled_ui(compose, |ui| {
+                    egui::ComboBox::from_id_salt("secondary_colormap")
+                        .selected_text(secondary.name())
+                        .show_ui(ui, |ui| {
+                            for map in Colormap::ALL {
+                                ui.selectable_value(&mut self.sim.params.secondary_colormap, map.index(), map.name());
+                            }
+                        });
+                });
+            });
+            ui.add_enabled_ui(compose, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.sim.params.colormap_split, 0.0..=1.0).step_by(0.01));
+                    ui.label("Split");
+                });
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.sim.params.colormap_blend, 0.0..=1.0).step_by(0.01))
+                        .on_hover_text("Width of the crossfade between the two maps; 0 is a hard edge");
+                    ui.label("Blend");
+                });
+            });
+
+            // Dithering
+            let mut dither = self.sim.params.dither != 0;
+            if ui.checkbox(&mut dither, "Dither output")
+                .on_hover_text("Adds a faint ordered pattern before 8-bit quantization to hide color banding")
+                .changed() {
+                self.sim.params.dither = dither as u32;
+            }
+            // An Rgba8 texture is dithered as it's stored, so there's no dither on
+            // screen left to animate
+            let dithers_on_screen = frame.wgpu_render_state()
+                .is_none_or(|rs| self.sim.output_format(rs) != Some(OutputFormat::Rgba8));
+            let mut temporal = self.sim.params.temporal_dither != 0;
+            if ui.add_enabled(dithers_on_screen, egui::Checkbox::new(&mut temporal, "Animate dither"))
+                .on_hover_text("Shifts the dither pattern every frame on screen, so slow gradients don't crawl in bands while the image changes. Saved images keep the fixed pattern.")
+                .on_disabled_hover_text("Needs the Rgba16Float output format, since Rgba8 output is dithered as it's stored")
+                .changed() {
+                self.sim.params.temporal_dither = temporal as u32;
+            }
+            ui.add_enabled(!self.sim.is_fragment_only(), egui::Checkbox::new(&mut self.blend_output, "Transparent diverged pixels"))
+                .on_hover_text("Blends the image over the background, so pendulums that diverged show it through");
+            ui.add_enabled(!self.sim.is_fragment_only(), egui::Checkbox::new(&mut self.pixel_perfect, "Pixel-perfect"))
+                .on_hover_text("Sizes the image to a whole multiple of the grid and samples it without interpolation, so each pendulum covers a crisp block of screen pixels. Letterboxes the rest of the window; a window smaller than the grid shows its middle at 1:1.");
+            let mut alpha_mask = self.sim.params.alpha_mask != 0;
+            if ui.checkbox(&mut alpha_mask, "Alpha from convergence")
+                .on_hover_text("Makes pendulums that are still moving transparent and settled ones opaque, in the image and in exported PNGs, so the finished regions can be keyed over other footage. Turn on transparency above to see it here.")
+                .changed() {
+                self.sim.params.alpha_mask = alpha_mask as u32;
+            }
+            if let Some(wgpu_render_state) = frame.wgpu_render_state().filter(|_| !self.sim.is_fragment_only()) {
+                ui.horizontal(|ui| {
+                    let mut format = self.output_format;
+                    egui::ComboBox::from_id_salt("output_format")
+                        .selected_text(format!("{format:?}"))
+                        .show_ui(ui, |ui| {
+                            for f in [OutputFormat::Rgba8, OutputFormat::Rgba16Float] {
+                                ui.add_enabled_ui(f.is_supported(&wgpu_render_state.adapter), |ui| {
+                                    ui.selectable_value(&mut format, f, format!("{f:?}"));
+                                });
+                            }
+                        });
+                    ui.label("Output format")
+                        .on_hover_text("Rgba16Float stores the image in half floats, without 8-bit banding. Switching restarts the run");
+                    if format != self.output_format {
+                        self.set_output_format(wgpu_render_state, format);
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                let mut highlight = self.sim.params.highlight_diverged != 0;
+                if ui.checkbox(&mut highlight, "Highlight diverged")
+                    .on_hover_text("Draws pendulums whose state became infinite or NaN in magenta")
+                    .changed() {
+                    self.sim.params.highlight_diverged = highlight as u32;
+                }
+                if let Some(diverged) = self.diverged {
+                    let total = (self.sim.params.w * self.sim.params.h).max(1);
+                    ui.label(format!("diverged: {diverged} ({:.2}%)", diverged as f32 / total as f32 * 100.0));
+                }
+            });
+
+            // Magnifier
+            ui.checkbox(&mut self.show_magnifier, "Magnifier")
+                .on_hover_text("Shows a zoomed inset of the region under the cursor");
+            ui.add_enabled_ui(self.show_magnifier, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.magnifier_zoom, 2.0..=16.0).logarithmic(true));
+                    ui.label("Magnifier zoom");
+                });
+            });
+
+            // Cross-section of the color scalar
+            ui.add_enabled_ui(!self.sim.is_fragment_only(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.probe_mode, "Probe line")
+                        .on_hover_text("Drag across the canvas to plot the color scalar along a line");
+                    if self.probe.is_some() && ui.button("Clear").clicked() {
+                        self.probe = None;
+                        self.probe_profile.clear();
+                    }
+                });
+            });
+            if self.probe.is_some() && !self.probe_profile.is_empty() {
+                let (min, max) = self.probe_profile.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+                ui.label(format!("Along the probe: {min:.4} to {max:.4}"));
+                plot_series(ui, self.probe_profile.iter().map(|&v| v as f64));
+            }
+
+            // Outcome of the last pendulum clicked on the canvas
+            if self.pendulum_pending {
+                ui.label("Reading pendulum…");
+            } else if let Some(p) = self.pendulum {
+                let outcome = p.magnet.map_or("no magnet (masked out or diverged)".to_owned(), |i| format!("magnet {i}"));
+                ui.label(format!(
+                    "Pixel ({}, {}): at ({:.3}, {:.3}), speed {:.3}, nearest {outcome}",
+                    p.cell[0], p.cell[1], p.position.x, p.position.y, p.velocity.length(),
+                ))
+                .on_hover_text("Click the canvas to read back another pendulum. Rows count from the bottom");
+            }
+
+            // Frame counter
+            ui.checkbox(&mut self.show_counter, "Frame counter")
+                .on_hover_text("Shows the step, frame and simulation time in a corner, for lining up recordings");
+            ui.add_enabled_ui(self.show_counter, |ui| {
+                ui.checkbox(&mut self.burn_counter, "Burn into export")
+                    .on_hover_text("Draws the counter into the top left corner of exported PNGs");
+            });
+
+            // 3D surface
+            ui.add_enabled_ui(!self.sim.is_fragment_only(), |ui| {
+                ui.checkbox(&mut self.show_surface, "3D surface")
+                    .on_hover_text("Draws the scalar the image is colored by as a height field, spanning the color range. Drag the image to orbit.");
+            });
+            ui.add_enabled_ui(self.show_surface, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.surface_camera.height_scale, 0.0..=1.0).step_by(0.01));
+                    ui.label("Surface height");
+                });
+            });
+
+            // Magnet markers
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.show_magnets, "Magnets")
+                    .on_hover_text("Marks each magnet in the color of its basin");
+                ui.add_enabled_ui(self.show_magnets, |ui| {
+                    egui::ComboBox::from_id_salt("magnet_marker")
+                        .selected_text(format!("{:?}", self.magnet_marker))
+                        .show_ui(ui, |ui| {
+                            for marker in [MagnetMarker::Disc, MagnetMarker::Ring, MagnetMarker::Cross] {
+                                ui.selectable_value(&mut self.magnet_marker, marker, format!("{marker:?}"));
+                            }
+                        });
+                    ui.checkbox(&mut self.magnet_labels, "Labels");
+                });
+            });
+            ui.add_enabled_ui(self.show_magnets, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(Slider::new(&mut self.magnet_size, 0.05..=1.0).step_by(0.01));
+                    ui.label("Marker radius").on_hover_text("In world units, like the magnet radius");
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.snap_magnets, "Snap to grid")
+                        .on_hover_text("Drag a magnet to resize the ring. Hold Shift to place it freely");
+                    ui.add_enabled(
+                        self.snap_magnets,
+                        egui::DragValue::new(&mut self.snap_step).range(0.05..=2.0).speed(0.01).prefix("step "),
+                    );
+                });
+            });
+
+            ui.checkbox(&mut self.show_polar_grid, "Polar grid")
+                .on_hover_text("Circles at integer radii and a spoke through each magnet");
+
+            // Tracers
+            let mut tracers_changed = false;
+            let cartesian = self.sim.params.seed_coords != 1;
+            ui.horizontal(|ui| {
+                tracers_changed |= ui.add_enabled(cartesian, Slider::new(&mut self.tracer_count, 0..=1024))
+                    .on_disabled_hover_text("Paths are drawn in x and y, which a polar canvas isn't laid out in")
+                    .changed();
+                ui.label("Tracers").on_hover_text(
+                    "Number of pendulums, spread evenly over the canvas, whose paths are drawn over the image (0 = off)",
+                );
+            });
+            ui.add_enabled_ui(cartesian && self.tracer_count > 0, |ui| {
+                ui.horizontal(|ui| {
+                    tracers_changed |= ui.add(Slider::new(&mut self.trail_len, 16..=2048).logarithmic(true)).changed();
+                    ui.label("Trail length").on_hover_text("Number of recent steps drawn for each tracer");
+                });
+            });
+            if tracers_changed {
+                if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                    self.sim.set_tracers(wgpu_render_state, self.tracer_count, self.trail_len);
+                }
+            }
+
             ui.separator();
             ui.add_space(10.0);
             
@@ -188,14 +1629,290 @@ impl eframe::App for GPUSimApp {
             
             // Randomize velocity button
             if ui.button("Randomize Velocity").clicked() {
-                let mut rng = rand::rng();
+                self.sim.params.seed = self.sim.params.seed.wrapping_add(1);
+                let mut rng = self.rng();
                 self.sim.params.velocity_magnitude = rng.random_range(0.5..8.0);
                 self.sim.params.velocity_angle = rng.random_range(0.0..std::f32::consts::TAU);
                 self.sim.params.velocity_pattern = rng.random_range(0..4);
             }
             
+            // Image export with embedded parameters
+            ui.horizontal(|ui| {
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Save Image…").clicked() {
+                    if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                        let path = rfd::FileDialog::new()
+                            .add_filter("PNG image", &["png"])
+                            .set_file_name("magnetic-pendulum.png")
+                            .save_file();
+                        if let Some(path) = path {
+                            let result = self.encode_image(wgpu_render_state)
+                                .and_then(|png| Ok(std::fs::write(&path, png)?));
+                            self.status = match result {
+                                Ok(()) => format!("Saved {}", path.display()),
+                                Err(e) => e.to_string(),
+                            };
+                        }
+                    }
+                }
+
+                // Browsers can't be handed a path, so the image is offered as a download.
+                // WebGPU can't map a buffer synchronously, so the image arrives a few
+                // frames after the click.
+                #[cfg(target_arch = "wasm32")]
+                if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                    let button = ui.add_enabled(self.saved_image.is_none(), egui::Button::new("Save Image…"));
+                    if button.clicked() {
+                        let metadata = self.sim.image_metadata(wgpu_render_state);
+                        let counter = self.burned_counter(wgpu_render_state);
+                        match self.sim.request_output(wgpu_render_state) {
+                            Some(image) => self.saved_image = Some((metadata, counter, image)),
+                            None => self.status = ExportError::Readback.to_string(),
+                        }
+                    }
+                    if let Some((metadata, counter, image)) = &self.saved_image {
+                        match image.poll(wgpu_render_state) {
+                            std::task::Poll::Ready(rgba) => {
+                                let png = rgba.ok_or(ExportError::Readback)
+                                    .and_then(|rgba| encode_output(rgba, counter.as_deref(), metadata));
+                                self.saved_image = None;
+                                match png {
+                                    Ok(png) => {
+                                        let dialog = rfd::AsyncFileDialog::new()
+                                            .add_filter("PNG image", &["png"])
+                                            .set_file_name("magnetic-pendulum.png");
+                                        wasm_bindgen_futures::spawn_local(async move {
+                                            if let Some(file) = dialog.save_file().await {
+                                                if let Err(e) = file.write(&png).await {
+                                                    log::warn!("Failed to save image: {e}");
+                                                }
+                                            }
+                                        });
+                                    }
+                                    Err(e) => self.status = e.to_string(),
+                                }
+                            }
+                            std::task::Poll::Pending => ui.ctx().request_repaint(),
+                        }
+                    }
+                }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Save Scalar Field…")
+                    .on_hover_text("Saves the value behind the colors as a 16-bit grayscale PNG, from the color range's min (black) to max (white)")
+                    .clicked() {
+                    if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                        let path = rfd::FileDialog::new()
+                            .add_filter("PNG image", &["png"])
+                            .set_file_name("magnetic-pendulum-scalar.png")
+                            .save_file();
+                        if let Some(path) = path {
+                            let result = self.encode_scalar_field(wgpu_render_state)
+                                .and_then(|png| Ok(std::fs::write(&path, png)?));
+                            self.status = match result {
+                                Ok(()) => format!("Saved {}", path.display()),
+                                Err(e) => e.to_string(),
+                            };
+                        }
+                    }
+                }
+
+                if ui.button("Load from Image…")
+                    .on_hover_text("Restores the parameters embedded in an image saved by this app")
+                    .clicked() {
+                    self.pick_file(PNG_FILTER, self.image_tx.clone(), ui.ctx());
+                }
+            });
+
+            // Versioned settings files
+            ui.horizontal(|ui| {
+                #[cfg(not(target_arch = "wasm32"))]
+                if ui.button("Save Config…").clicked() {
+                    let path = rfd::FileDialog::new()
+                        .add_filter(CONFIG_FILTER.0, CONFIG_FILTER.1)
+                        .set_file_name("magnetic-pendulum.json")
+                        .save_file();
+                    if let Some(path) = path {
+                        let result = Config::new(self.sim.params, self.view(), self.custom_magnets.as_deref())
+                            .to_json()
+                            .map_err(|e| e.to_string())
+                            .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+                        self.status = match result {
+                            Ok(()) => format!("Saved {}", path.display()),
+                            Err(e) => format!("Failed to save config: {e}"),
+                        };
+                    }
+                }
+                if ui.button("Load Config…")
+                    .on_hover_text("Loads a config saved by this or an earlier build of the app")
+                    .clicked() {
+                    self.pick_file(CONFIG_FILTER, self.config_tx.clone(), ui.ctx());
+                }
+            });
+
+            // In-memory slots, for switching quickly during a demo
+            ui.horizontal(|ui| {
+                ui.label("Slots");
+                for i in 0..PARAM_SLOTS {
+                    let filled = self.slots[i].is_some();
+                    let hover = self.slots[i].map_or("Empty".to_owned(), |params| params.to_string());
+                    let button = ui
+                        .add(egui::Button::new((i + 1).to_string()).selected(filled))
+                        .on_hover_text(format!("{hover}\n\nClick or {0} to recall, Shift+click or Shift+{0} to store", i + 1));
+                    if button.clicked() {
+                        if ui.input(|input| input.modifiers.shift) {
+                            self.slots[i] = Some(self.sim.params);
+                            self.status = format!("Stored slot {}", i + 1);
+                        } else if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                            self.recall_slot(i, wgpu_render_state);
+                        }
+                    }
+                }
+            });
+
+            // Tour through the slots, for unattended demos
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut self.touring, "Tour")
+                    .on_hover_text("Runs each ticked slot in turn, easing from one to the next, and loops")
+                    .changed() {
+                    (self.tour_stop, self.tour_time) = (0, 0.0);
+                }
+                for i in 0..PARAM_SLOTS {
+                    ui.add_enabled(self.slots[i].is_some(), egui::Checkbox::without_text(&mut self.tour_slots[i]))
+                        .on_hover_text(format!("Visit slot {}", i + 1));
+                }
+            });
+            if self.touring {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.tour_hold).range(0.0..=600.0).speed(0.1).prefix("hold ").suffix(" s"));
+                    ui.add(egui::DragValue::new(&mut self.tour_transition).range(0.0..=600.0).speed(0.1).prefix("transition ").suffix(" s"));
+                });
+                let stops = self.tour_stops();
+                if stops.len() < 2 {
+                    ui.label("Store at least two ticked slots to tour");
+                } else {
+                    let stop = self.tour_stop % stops.len();
+                    let (from, to) = (stops[stop] + 1, stops[(stop + 1) % stops.len()] + 1);
+                    if self.tour_time <= self.tour_hold {
+                        ui.label(format!("Slot {from}, moving on to {to} in {:.1} s", self.tour_hold - self.tour_time));
+                    } else {
+                        let progress = (self.tour_time - self.tour_hold) / self.tour_transition.max(1e-3);
+                        ui.label(format!("Slot {from} → {to}: {:.0}%", progress.min(1.0) * 100.0));
+                    }
+                }
+            }
+
+            // Manual crossfade between two slots, for live demos
+            ui.horizontal(|ui| {
+                let mut changed = false;
+                for (k, name) in ["A", "B"].into_iter().enumerate() {
+                    egui::ComboBox::from_id_salt(("fade_slot", k))
+                        .width(40.0)
+                        .selected_text(format!("{name}: {}", self.fade_slots[k] + 1))
+                        .show_ui(ui, |ui| {
+                            for i in 0..PARAM_SLOTS {
+                                let label = if self.slots[i].is_some() { (i + 1).to_string() } else { format!("{} (empty)", i + 1) };
+                                changed |= ui.selectable_value(&mut self.fade_slots[k], i, label).changed();
+                            }
+                        });
+                }
+                changed |= ui.add(Slider::new(&mut self.fade, 0.0..=1.0).step_by(0.001))
+                    .on_hover_text("Interpolates the params from slot A to slot B, restarting from the blend (or continuing, see New params) as you drag. Counts and modes switch halfway.")
+                    .changed();
+                if let Some(wgpu_render_state) = frame.wgpu_render_state().filter(|_| changed) {
+                    self.apply_crossfade(wgpu_render_state);
+                }
+            });
+
+            // Seed only inside a shape
+            ui.add_enabled_ui(!self.sim.is_fragment_only(), |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Load Mask…")
+                        .on_hover_text("Simulates only where the image is opaque (or bright, without transparency) and leaves the rest as background")
+                        .clicked() {
+                        self.pick_file(PNG_FILTER, self.mask_tx.clone(), ui.ctx());
+                    }
+                    if self.mask_image.is_some() && ui.button("Clear Mask").clicked() {
+                        self.mask_image = None;
+                        if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                            self.apply_mask(wgpu_render_state);
+                        }
+                    }
+                });
+                ui.add_enabled_ui(self.mask_image.is_some(), |ui| {
+                    let changed = ui.checkbox(&mut self.mask_as_mass, "Use as mass field")
+                        .on_hover_text("Simulates every pendulum and seeds its mass from the image instead, from the centre mass where transparent to the edge mass where opaque")
+                        .changed();
+                    if let Some(wgpu_render_state) = frame.wgpu_render_state().filter(|_| changed) {
+                        self.apply_mask(wgpu_render_state);
+                    }
+                    ui.add_enabled_ui(!self.mask_as_mass, |ui| ui.horizontal(|ui| {
+                        let changed = ui.add(Slider::new(&mut self.mask_threshold, 0.0..=1.0).step_by(0.01)).changed();
+                        if let Some(wgpu_render_state) = frame.wgpu_render_state().filter(|_| changed) {
+                            self.apply_mask(wgpu_render_state);
+                        }
+                        ui.label("Mask threshold");
+                    }));
+                });
+            });
+            // Compare against an earlier export
+            ui.add_enabled_ui(!self.sim.is_fragment_only(), |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Load Reference…")
+                        .on_hover_text("Compares the output against an exported PNG of the same size")
+                        .clicked() {
+                        self.pick_file(PNG_FILTER, self.reference_tx.clone(), ui.ctx());
+                    }
+                    if self.reference.is_some() && ui.button("Clear Reference").clicked() {
+                        self.reference = None;
+                        self.reference_rms = None;
+                        self.difference = None;
+                    }
+                });
+                if let Some((width, height, _)) = &self.reference {
+                    match self.reference_rms {
+                        Some(rms) => ui.label(format!("RMS difference from reference: {rms:.5}")),
+                        None => ui.label(format!("The reference is {width}×{height}, the output is not")),
+                    };
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.show_difference, "Show difference");
+                        ui.add_enabled(self.show_difference, Slider::new(&mut self.difference_gain, 1.0..=64.0).logarithmic(true))
+                            .on_hover_text("Amplification of |output - reference|");
+                        ui.label("Gain");
+                    });
+                }
+            });
+            if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+
+            // Random seed
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.sim.params.seed))
+                    .on_hover_text("Randomness is derived from this seed, so it stays fixed while other parameters change. Only the randomize buttons advance it.");
+                ui.label("Random seed");
+            });
+
             ui.separator();
-            ui.label("Presets:");
+            ui.horizontal(|ui| {
+                ui.label("Presets:");
+                if ui.button("Copy as Rust")
+                    .on_hover_text("Copies the current parameters as assignments like the preset buttons below use")
+                    .clicked() {
+                    ui.ctx().copy_text(self.sim.params.to_rust("self.sim.params"));
+                    self.status = "Copied the parameters as Rust".to_owned();
+                }
+                if ui.button("Export Preset…")
+                    .on_hover_text("Saves just the parameters as JSON, to load into any grid size")
+                    .clicked() {
+                    self.export_preset();
+                }
+                if ui.button("Import Preset…")
+                    .on_hover_text("Applies saved parameters without restarting. Values outside the sliders' ranges are clamped.")
+                    .clicked() {
+                    self.pick_file(PRESET_FILTER, self.preset_tx.clone(), ui.ctx());
+                }
+            });
             ui.horizontal(|ui| {
                 if ui.button("Chaotic").clicked() {
                     self.sim.params.n = 3;
@@ -254,14 +1971,100 @@ impl eframe::App for GPUSimApp {
             ui.label("• Higher friction (μ) creates smoother patterns");
             ui.label("• Lower friction creates more chaotic behavior");
             ui.label("• Spring constant (c) affects restoring force");
+            ui.label("• Spring cubic term (k) makes the spring stiffen with distance");
             ui.label("• Distance parameter (d) controls singularity smoothing");
             ui.label("• Velocity patterns:");
             ui.label("  - Radial: velocities point outward from center");
             ui.label("  - Tangential: velocities perpendicular to position");
             ui.label("  - Uniform: all particles have same direction");
             ui.label("  - Zero: particles start at rest");
+
+            // Near passes before capture, per magnet
+            ui.separator();
+            egui::CollapsingHeader::new("Orbit captures").show(ui, |ui| {
+                if ui.button("Count passes")
+                    .on_hover_text("For the pendulums nearest each magnet, how many times they came within d of a magnet. 1 is a direct capture, the last bar is 16 or more")
+                    .clicked() {
+                    self.orbit_hist = frame.wgpu_render_state().and_then(|rs| self.sim.orbit_histogram(rs));
+                }
+                for (i, bins) in self.orbit_hist.iter().flatten().enumerate() {
+                    ui.horizontal(|ui| {
+                        let color = self.magnets().get(i).map_or(egui::Color32::GRAY, |m| self.marker_color(m));
+                        let (rect, _) = ui.allocate_exact_size(egui::vec2(ORBIT_BINS as f32 * 8.0, 24.0), Sense::hover());
+                        let max = bins.iter().copied().max().unwrap_or(0).max(1);
+                        for (k, &count) in bins.iter().enumerate() {
+                            let height = rect.height() * count as f32 / max as f32;
+                            let left = rect.left() + k as f32 * 8.0;
+                            let bar = egui::Rect::from_min_max(egui::pos2(left, rect.bottom() - height), egui::pos2(left + 7.0, rect.bottom()));
+                            ui.painter().rect_filled(bar, 0.0, color);
+                        }
+                        ui.label(format!("Magnet {i}: {}", bins.iter().sum::<u32>()));
+                    });
+                }
+            });
+
+            // GPU memory, recomputed while open so it follows resolution and mode changes
+            ui.separator();
+            egui::CollapsingHeader::new("Diagnostics").show(ui, |ui| {
+                let usage = frame.wgpu_render_state().map_or(vec![], |rs| self.sim.memory_usage(rs));
+                egui::Grid::new("memory_usage").num_columns(2).show(ui, |ui| {
+                    for (name, bytes) in &usage {
+                        ui.label(*name);
+                        ui.monospace(format_bytes(*bytes));
+                        ui.end_row();
+                    }
+                    ui.strong("Total");
+                    ui.monospace(format_bytes(usage.iter().map(|(_, bytes)| bytes).sum()));
+                    ui.end_row();
+                });
+            });
+
+            // Current params as one copyable line
+            ui.separator();
+            let summary = self.sim.params.to_string();
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut summary.as_str())
+                    .font(egui::TextStyle::Monospace)
+                    .desired_width(ui.available_width() - 50.0));
+                if ui.button("Copy").on_hover_text("Copy the current parameters as text").clicked() {
+                    ui.ctx().copy_text(summary.clone());
+                }
+            });
         });
 
+        // Recently finished runs; clicking one runs it again
+        if !self.history.is_empty() {
+            egui::TopBottomPanel::bottom("history").show(ctx, |ui| {
+                egui::ScrollArea::horizontal().show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        let mut picked = None;
+                        for (i, entry) in self.history.iter().enumerate() {
+                            let image = egui::Image::new(&entry.thumbnail)
+                                .fit_to_exact_size(egui::Vec2::splat(THUMBNAIL_SIZE as f32));
+                            if ui.add(egui::Button::image(image)).on_hover_text(entry.params.to_string()).clicked() {
+                                picked = Some(i);
+                            }
+                        }
+                        if let (Some(i), Some(wgpu_render_state)) = (picked, frame.wgpu_render_state()) {
+                            // Moved to the front, so finishing the run again doesn't add a copy
+                            let entry = self.history.remove(i).unwrap();
+                            self.sim.params = entry.params;
+                            self.history.push_front(entry);
+                            self.switch_params(wgpu_render_state);
+                            self.is_paused = false;
+                        }
+                    });
+                });
+            });
+        }
+
+        // After everything that may have loaded other params
+        if let Some(range) = self.locked_range {
+            (self.sim.params.color_min, self.sim.params.color_max) = range;
+        }
+        let counter = self
+            .show_counter
+            .then(|| self.counter_text(frame.wgpu_render_state().map_or(0, |rs| self.sim.step_count(rs))));
         egui::CentralPanel::default()
             .frame(egui::Frame::NONE.inner_margin(15.0)) // Remove default frame styling
             .show(ctx, |ui| {
@@ -271,23 +2074,430 @@ impl eframe::App for GPUSimApp {
                 
                 // Make it square and use the smaller dimension to fit properly
                 let min_dimension = available_size.x.min(available_size.y).max(200.0); // Minimum size of 200px
-                let canvas_size = egui::vec2(min_dimension, min_dimension);
                 let (resp, ptr) = ui.allocate_painter(available_size, Sense::focusable_noninteractive());
-                let canv_rect = egui::Rect::from_center_size(resp.rect.center(), canvas_size);
-
-                // Only update simulation if not paused
-                if !self.is_paused {
-                    ptr.add(eframe::egui_wgpu::Callback::new_paint_callback(canv_rect, self.sim));
+                let canv_rect = if self.pixel_perfect && !self.show_surface {
+                    let ppp = ui.ctx().pixels_per_point();
+                    let canvas_size = pixel_perfect_size(available_size, ppp, self.sim.params.w, self.sim.params.h);
+                    // Texels only land on whole pixels from a pixel-aligned corner
+                    let min = (resp.rect.center() - canvas_size / 2.0).round_to_pixels(ppp);
+                    egui::Rect::from_min_size(min, canvas_size)
                 } else {
+                    egui::Rect::from_center_size(resp.rect.center(), egui::vec2(min_dimension, min_dimension))
+                };
+
+                let mut sim = self
+                    .sim
+                    .with_surface(self.show_surface.then_some(self.surface_camera))
+                    .with_blend(self.blend_output)
+                    .with_nearest(self.pixel_perfect);
+                if self.is_paused || self.renders_static() {
                     // When paused, still render the current state but don't update
-                    let mut paused_sim = self.sim;
-                    paused_sim.params.dt = 0.0; // Set dt to 0 to prevent updates
-                    ptr.add(eframe::egui_wgpu::Callback::new_paint_callback(canv_rect, paused_sim));
+                    sim.params.dt = 0.0;
+                }
+                ptr.add(eframe::egui_wgpu::Callback::new_paint_callback(canv_rect, sim));
+                // Hides the static render's transient behind the last result
+                if self.static_run.is_some() && !self.show_surface {
+                    match &self.static_image {
+                        Some(image) => {
+                            let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                            ptr.image(image.id(), canv_rect, uv, egui::Color32::WHITE);
+                        }
+                        None => {
+                            ptr.rect_filled(canv_rect, 0.0, egui::Color32::BLACK);
+                        }
+                    }
+                    ptr.text(canv_rect.center(), egui::Align2::CENTER_CENTER, "Rendering…", egui::FontId::proportional(16.0), egui::Color32::WHITE);
+                }
+                if let Some(difference) = self.difference.as_ref().filter(|_| self.show_difference && !self.show_surface) {
+                    let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                    ptr.image(difference.id(), canv_rect, uv, egui::Color32::WHITE);
+                }
+                if let Some(counter) = counter {
+                    let galley = ptr.layout_no_wrap(counter, egui::FontId::monospace(12.0), egui::Color32::WHITE);
+                    let text_rect = egui::Rect::from_min_size(canv_rect.min + egui::vec2(6.0, 6.0), galley.size());
+                    ptr.rect_filled(text_rect.expand(3.0), 2.0, egui::Color32::from_black_alpha(160));
+                    ptr.galley(text_rect.min, galley, egui::Color32::WHITE);
+                }
+
+                // Orbit the surface by dragging
+                if self.show_surface {
+                    let drag = ui.interact(canv_rect, ui.id().with("surface_orbit"), Sense::drag());
+                    let delta = drag.drag_delta();
+                    self.surface_camera.yaw -= delta.x * 0.01;
+                    self.surface_camera.pitch = (self.surface_camera.pitch + delta.y * 0.01).clamp(0.05, 1.5);
+                    return; // the overlays below are placed in flat image coordinates
+                }
+
+                // Scrolling zooms toward the cursor and dragging pans. Either reseeds the
+                // grid over the new region, so the basins are recomputed at the finer
+                // spacing instead of the old image being magnified
+                let view_before = (self.sim.params.view_center(), self.sim.params.zoom);
+                let pan = ui.interact(canv_rect, ui.id().with("pan"), Sense::click_and_drag());
+                if pan.dragged() && !self.probe_mode {
+                    let delta = pan.drag_delta() / canv_rect.size() * self.sim.params.view_span(self._scale);
+                    self.sim.params.center_x -= delta.x;
+                    self.sim.params.center_y += delta.y; // screen y runs down
+                }
+                // Clicking reads back the pendulum under the cursor. Its grid cell comes
+                // from the canvas alone, so clicks off the image are dropped
+                if let Some(p) = pan.interact_pointer_pos().filter(|p| pan.clicked() && canv_rect.contains(*p)) {
+                    let (w, h) = (self.sim.params.w, self.sim.params.h);
+                    let x = (((p.x - canv_rect.left()) / canv_rect.width() * w as f32) as u32).min(w - 1);
+                    let y = (((canv_rect.bottom() - p.y) / canv_rect.height() * h as f32) as u32).min(h - 1);
+                    if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                        self.pendulum_pending = self.sim.request_pendulum(wgpu_render_state, x, y);
+                    }
+                }
+                let (scroll, pinch) = ui.input(|i| (i.smooth_scroll_delta.y, i.zoom_delta()));
+                let hover_pos = ui.input(|i| i.pointer.hover_pos()).filter(|p| canv_rect.contains(*p));
+                if let Some(pos) = hover_pos.filter(|_| scroll != 0.0 || pinch != 1.0) {
+                    let params = &mut self.sim.params;
+                    let cell = glam::vec2(
+                        (pos.x - canv_rect.left()) / canv_rect.width(),
+                        (canv_rect.bottom() - pos.y) / canv_rect.height(),
+                    );
+                    let anchor = params.seed_position(cell, self._scale);
+                    let zoom = (params.zoom * pinch * (scroll * ZOOM_PER_POINT).exp()).clamp(MIN_ZOOM, MAX_ZOOM);
+                    // Keeps the world point under the cursor in place
+                    let center = anchor - (anchor - params.view_center()) * params.zoom / zoom;
+                    (params.center_x, params.center_y, params.zoom) = (center.x, center.y, zoom);
+                }
+                // Static rendering reseeds on its own once the pointer is released
+                if (self.sim.params.view_center(), self.sim.params.zoom) != view_before && !self.renders_static() {
+                    if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                        self.sim.restart(wgpu_render_state);
+                    }
+                }
+
+                // World to screen through the seeding grid. The quad maps texture v = 0
+                // to the bottom of the canvas
+                let seeding = self.sim.params;
+                let polar = seeding.seed_coords == 1;
+                let to_screen = |u: glam::Vec2| {
+                    let cell = seeding.seed_cell(u, self._scale);
+                    egui::pos2(
+                        canv_rect.left() + cell.x * canv_rect.width(),
+                        canv_rect.bottom() - cell.y * canv_rect.height(),
+                    )
+                };
+                let span = seeding.view_span(self._scale);
+                let px_per_unit = canv_rect.width() / span;
+
+                // Polar grid centred on the magnet ring
+                if self.show_polar_grid {
+                    let grid = ptr.with_clip_rect(canv_rect);
+                    let stroke = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(80));
+                    let center = to_screen(glam::Vec2::ZERO);
+                    let max_radius = seeding.view_center().length() + span / std::f32::consts::SQRT_2; // reaches the corners
+                    for radius in 1..=max_radius as u32 {
+                        if polar {
+                            // Unrolled, circles are rows
+                            grid.hline(canv_rect.x_range(), to_screen(glam::vec2(radius as f32, 0.0)).y, stroke);
+                        } else {
+                            grid.circle_stroke(center, radius as f32 * px_per_unit, stroke);
+                        }
+                    }
+                    // Spokes through the magnets, which sit at angles i·2π/n
+                    let n = self.sim.params.n;
+                    for i in 0..n {
+                        let spoke = glam::Vec2::from_angle(i as f32 * std::f32::consts::TAU / n as f32);
+                        if polar {
+                            grid.vline(to_screen(spoke).x, canv_rect.y_range(), stroke);
+                        } else {
+                            grid.line_segment([center, to_screen(spoke * max_radius)], stroke);
+                        }
+                    }
+                }
+
+                // Probe line, drawn by dragging from one end to the other
+                let to_canvas = |p: egui::Pos2| ((p - canv_rect.min) / canv_rect.size()).clamp(egui::Vec2::ZERO, egui::Vec2::splat(1.0));
+                if self.probe_mode {
+                    let drag = ui.interact(canv_rect, ui.id().with("probe"), Sense::drag())
+                        .on_hover_cursor(egui::CursorIcon::Crosshair);
+                    if let Some(p) = drag.interact_pointer_pos() {
+                        if drag.drag_started() {
+                            self.probe = Some((to_canvas(p), to_canvas(p)));
+                        } else if let Some((start, _)) = self.probe.filter(|_| drag.dragged()) {
+                            self.probe = Some((start, to_canvas(p)));
+                        }
+                        // Sample the new line on the next frame
+                        self.frames_since_probe = PROBE_FRAMES;
+                    }
+                }
+                if let Some((a, b)) = self.probe {
+                    let line = [canv_rect.min + a * canv_rect.size(), canv_rect.min + b * canv_rect.size()];
+                    ptr.line_segment(line, egui::Stroke::new(3.0, egui::Color32::from_black_alpha(160)));
+                    ptr.line_segment(line, egui::Stroke::new(1.5, egui::Color32::WHITE));
+                    ptr.circle_filled(line[0], 3.0, egui::Color32::WHITE);
+                }
+
+                // Dragging a magnet moves it along its spoke while the magnets stay on a
+                // ring of radius r, and anywhere in a custom layout
+                if self.show_magnets {
+                    let from_screen = |p: egui::Pos2| {
+                        let cell = glam::vec2(
+                            (p.x - canv_rect.left()) / canv_rect.width(),
+                            (canv_rect.bottom() - p.y) / canv_rect.height(),
+                        );
+                        seeding.seed_position(cell, self._scale)
+                    };
+                    let grab_radius = (self.magnet_size * px_per_unit).max(6.0);
+                    let mut dragging = false;
+                    for (i, magnet) in self.magnets().iter().enumerate() {
+                        let pos = to_screen(magnet.position);
+                        let handle = egui::Rect::from_center_size(pos, egui::Vec2::splat(2.0 * grab_radius));
+                        let drag = ui.interact(handle, ui.id().with(("magnet", i)), Sense::drag())
+                            .on_hover_cursor(egui::CursorIcon::Grab);
+                        if let Some(p) = drag.interact_pointer_pos().filter(|_| drag.dragged()) {
+                            let mut u = from_screen(p);
+                            if self.snap_magnets && !ui.input(|i| i.modifiers.shift) {
+                                u = (u / self.snap_step).round() * self.snap_step;
+                            }
+                            if let Some(magnets) = &mut self.custom_magnets {
+                                magnets[i].position = u.clamp(glam::Vec2::splat(-10.0), glam::Vec2::splat(10.0));
+                                if let Some(wgpu_render_state) = frame.wgpu_render_state() {
+                                    self.sim.set_magnets(wgpu_render_state, Some(magnets.clone()));
+                                }
+                            } else {
+                                self.sim.params.r = u.length().min(10.0);
+                            }
+                            dragging = true;
+                        }
+                    }
+
+                    // Faint snap grid while dragging
+                    if dragging && self.snap_magnets && !polar {
+                        let grid = ptr.with_clip_rect(canv_rect);
+                        let stroke = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(30));
+                        let steps = |c: f32| ((c - 0.5 * span) / self.snap_step).floor() as i32..=((c + 0.5 * span) / self.snap_step).ceil() as i32;
+                        for k in steps(seeding.center_x) {
+                            grid.vline(to_screen(glam::vec2(k as f32 * self.snap_step, seeding.center_y)).x, canv_rect.y_range(), stroke);
+                        }
+                        for k in steps(seeding.center_y) {
+                            grid.hline(canv_rect.x_range(), to_screen(glam::vec2(seeding.center_x, k as f32 * self.snap_step)).y, stroke);
+                        }
+                    }
+                }
+
+                // Magnet markers, sized in world units
+                if self.show_magnets {
+                    let markers = ptr.with_clip_rect(canv_rect);
+                    let radius = self.magnet_size * px_per_unit;
+                    let outline = egui::Stroke::new(1.5, egui::Color32::from_black_alpha(200));
+                    for (i, magnet) in self.magnets().iter().enumerate() {
+                        let pos = to_screen(magnet.position);
+                        let color = self.marker_color(magnet);
+                        match self.magnet_marker {
+                            MagnetMarker::Disc => {
+                                markers.circle(pos, radius, color, outline);
+                            }
+                            MagnetMarker::Ring => {
+                                markers.circle_stroke(pos, radius, egui::Stroke::new(radius * 0.5 + 1.5, outline.color));
+                                markers.circle_stroke(pos, radius, egui::Stroke::new(radius * 0.5, color));
+                            }
+                            MagnetMarker::Cross => {
+                                for d in [egui::vec2(1.0, 1.0), egui::vec2(1.0, -1.0)] {
+                                    let arm = d * radius * std::f32::consts::FRAC_1_SQRT_2;
+                                    markers.line_segment([pos - arm, pos + arm], egui::Stroke::new(4.0, outline.color));
+                                    markers.line_segment([pos - arm, pos + arm], egui::Stroke::new(2.5, color));
+                                }
+                            }
+                        }
+                        if self.magnet_labels {
+                            let label_pos = pos + egui::vec2(radius + 3.0, -radius - 3.0);
+                            let font = egui::FontId::proportional(14.0);
+                            markers.text(label_pos + egui::vec2(1.0, 1.0), egui::Align2::LEFT_BOTTOM, i.to_string(), font.clone(), outline.color);
+                            markers.text(label_pos, egui::Align2::LEFT_BOTTOM, i.to_string(), font, egui::Color32::WHITE);
+                        }
+                    }
+                }
+
+                // Divider between the Euler and RK4 halves
+                if self.sim.params.compare_integrators != 0 {
+                    let x = canv_rect.left() + self.sim.params.compare_split * canv_rect.width();
+                    let handle = egui::Rect::from_x_y_ranges(x - 4.0..=x + 4.0, canv_rect.y_range());
+                    let drag = ui.interact(handle, ui.id().with("compare_divider"), Sense::drag())
+                        .on_hover_cursor(egui::CursorIcon::ResizeHorizontal);
+                    if let Some(p) = drag.interact_pointer_pos().filter(|_| drag.dragged()) {
+                        self.sim.params.compare_split = ((p.x - canv_rect.left()) / canv_rect.width()).clamp(0.0, 1.0);
+                    }
+
+                    let color = egui::Color32::WHITE;
+                    let font = egui::FontId::proportional(14.0);
+                    ptr.vline(x, canv_rect.y_range(), egui::Stroke::new(2.0, color));
+                    let label_y = canv_rect.top() + 6.0;
+                    ptr.text(egui::pos2(x - 6.0, label_y), egui::Align2::RIGHT_TOP, "Euler", font.clone(), color);
+                    ptr.text(egui::pos2(x + 6.0, label_y), egui::Align2::LEFT_TOP, "RK4", font, color);
+                }
+
+                // Magnified inset of the region under the cursor
+                let hover_pos = ui.input(|i| i.pointer.hover_pos());
+                if let Some(pos) = hover_pos.filter(|p| self.show_magnifier && canv_rect.contains(*p)) {
+                    // The quad maps texture v = 0 to the bottom of the canvas
+                    let center = glam::vec2(
+                        (pos.x - canv_rect.left()) / canv_rect.width(),
+                        (canv_rect.bottom() - pos.y) / canv_rect.height(),
+                    );
+                    let inset_size = canv_rect.size() * MAGNIFIER_FRAC;
+                    let inset_rect = egui::Rect::from_min_size(
+                        egui::pos2(canv_rect.right() - inset_size.x - 8.0, canv_rect.top() + 8.0),
+                        inset_size,
+                    );
+                    let magnifier = self.sim.magnifier(center, self.magnifier_zoom, MAGNIFIER_FRAC);
+                    ptr.add(eframe::egui_wgpu::Callback::new_paint_callback(inset_rect, magnifier));
+
+                    let stroke = egui::Stroke::new(1.0, ui.style().visuals.strong_text_color());
+                    let source_rect = egui::Rect::from_center_size(pos, inset_size / self.magnifier_zoom);
+                    ptr.rect_stroke(inset_rect, 0.0, stroke, egui::StrokeKind::Outside);
+                    ptr.rect_stroke(source_rect, 0.0, stroke, egui::StrokeKind::Outside);
                 }
             });
         });
 
-        // Only request repaint if not paused, or always for UI updates
+        let params_changed = bytemuck::bytes_of(&params_before) != bytemuck::bytes_of(&self.sim.params);
+        self.params_dragged = ctx.dragged_id().is_some() && params_changed;
+        // A restart counts steps from 0 again, so it ends the idling too
+        if params_changed
+            || frame.wgpu_render_state().zip(self.finished_at).is_some_and(|(rs, step)| self.sim.step_count(rs) < step)
+        {
+            self.finished_at = None;
+        }
+
+        // Static rendering only redraws on input and while a run converges; otherwise keep
+        // animating, capped to the frame rate limit. egui repaints on input anyway, so
+        // paused and finished runs can idle, and only poll slowly when remote params may arrive
+        if !self.renders_static() {
+            if self.is_paused || self.finished_at.is_some() {
+                if self.remote.is_some() {
+                    ctx.request_repaint_after(IDLE_REPAINT);
+                }
+            } else if self.fps_cap == 0 {
+                ctx.request_repaint();
+            } else {
+                ctx.request_repaint_after(Duration::from_secs_f32(1.0 / self.fps_cap as f32));
+            }
+        }
+    }
+}
+
+// Points spanned by the largest whole multiple of the w×h grid in screen pixels that
+// fits in `available`, and 1:1 when even that doesn't
+fn pixel_perfect_size(available: egui::Vec2, pixels_per_point: f32, w: u32, h: u32) -> egui::Vec2 {
+    let grid = egui::vec2(w as f32, h as f32);
+    let multiple = (available * pixels_per_point / grid).min_elem().floor().max(1.0);
+    grid * multiple / pixels_per_point
+}
+
+// Line plot of `values` across the available width, scaled to their range
+fn plot_series(ui: &mut egui::Ui, values: impl ExactSizeIterator<Item = f64> + Clone) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 60.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    let (min, max) = values.clone().fold((f64::MAX, f64::MIN), |(min, max), v| (min.min(v), max.max(v)));
+    if values.len() < 2 {
+        return;
+    }
+    let last = (values.len() - 1) as f32;
+    let span = (max - min).max(1e-12);
+    let points = values
+        .enumerate()
+        .map(|(i, v)| egui::pos2(
+            egui::lerp(rect.left()..=rect.right(), i as f32 / last),
+            egui::lerp(rect.bottom() - 2.0..=rect.top() + 2.0, ((v - min) / span) as f32),
+        ))
+        .collect();
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, ui.visuals().text_color())));
+}
+
+// Consumes the first press of a slot key, returning its slot and whether shift was
+// held. Shift turns the digits into symbols on most layouts, so this goes by the
+// physical key rather than consuming the logical one
+fn consume_slot_key(input: &mut egui::InputState) -> Option<(usize, bool)> {
+    let mut slot = None;
+    input.events.retain(|event| match event {
+        egui::Event::Key { key, physical_key, pressed: true, modifiers, .. } if slot.is_none() => {
+            let key = physical_key.unwrap_or(*key);
+            match SLOT_KEYS.iter().position(|&k| k == key) {
+                Some(i) if modifiers.is_none() || modifiers.shift_only() => {
+                    slot = Some((i, modifiers.shift));
+                    false
+                }
+                _ => true,
+            }
+        }
+        _ => true,
+    });
+    slot
+}
+
+// The grid cells of PROBE_SAMPLES nearest-pixel samples of a width×height image from a
+// to b, given as fractions of the image from its top left
+fn line_cells(width: u32, height: u32, a: egui::Vec2, b: egui::Vec2) -> Vec<[u32; 2]> {
+    (0..PROBE_SAMPLES)
+        .map(|i| {
+            let p = a + (b - a) * (i as f32 / (PROBE_SAMPLES - 1) as f32);
+            let x = ((p.x * width as f32) as u32).min(width - 1);
+            let y = ((p.y * height as f32) as u32).min(height - 1);
+            // The grid's rows run from the bottom
+            [x, height - 1 - y]
+        })
+        .collect()
+}
+
+// Root mean square difference of the color channels of two RGBA8 images, in [0, 1]
+fn rms_difference(a: &[u8], b: &[u8]) -> f32 {
+    let (sum, count) = a
+        .chunks_exact(4)
+        .zip(b.chunks_exact(4))
+        .flat_map(|(a, b)| (0..3).map(move |c| (a[c] as f32 - b[c] as f32) / 255.0))
+        .fold((0.0, 0), |(sum, count), d| (sum + d * d, count + 1));
+    (sum / count.max(1) as f32).sqrt()
+}
+
+// Routes device loss and uncaptured errors to the app instead of letting wgpu's default
+// handler panic
+fn watch_device(device: &eframe::wgpu::Device, ctx: &egui::Context) -> mpsc::Receiver<GpuError> {
+    let (tx, rx) = mpsc::channel();
+    let (lost_tx, lost_ctx) = (tx.clone(), ctx.clone());
+    device.set_device_lost_callback(move |reason, message| {
+        log::error!("GPU device lost ({reason:?}): {message}");
+        let _ = lost_tx.send(GpuError::Lost(message));
+        lost_ctx.request_repaint();
+    });
+    let ctx = ctx.clone();
+    device.on_uncaptured_error(Box::new(move |error| {
+        log::error!("Uncaptured GPU error: {error}");
+        let _ = tx.send(GpuError::Uncaptured(error.to_string()));
         ctx.request_repaint();
+    }));
+    rx
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
     }
 }
+
+// A saved image as PNG, with the counter text burned in if given
+fn encode_output(mut rgba: Vec<u8>, counter: Option<&str>, metadata: &ImageMetadata) -> Result<Vec<u8>, ExportError> {
+    if let Some(text) = counter {
+        burn_text(&mut rgba, metadata.width, metadata.height, text, (metadata.width / 256).max(1));
+    }
+    encode_png(&rgba, metadata)
+}
+
+// The OS asks for less animation. Only browsers expose this setting to us
+#[cfg(target_arch = "wasm32")]
+fn prefers_reduced_motion() -> bool {
+    web_sys::window()
+        .and_then(|window| window.match_media("(prefers-reduced-motion: reduce)").ok().flatten())
+        .is_some_and(|query| query.matches())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn prefers_reduced_motion() -> bool {
+    false
+}
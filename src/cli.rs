@@ -0,0 +1,195 @@
+// Batch rendering from the command line, without a window:
+//
+//     gpusim render --width 2048 --mu 0.1 --d 0.3 --out frame.png
+//
+// The run goes through `GPUSim` on a headless device and ends in
+// `GPUSim::export_png`, so seeding, stepping and readback are the GUI's. Every
+// numeric `Params` field is a flag defaulting to its `Params::default` value, spelled
+// either as the field (`--velocity_magnitude`) or in kebab case (`--velocity-magnitude`).
+
+use std::fmt;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use crate::config::View;
+use crate::export::ExportError;
+use crate::sim::{headless_render_state, GPUSim, Params};
+
+const DEFAULT_STEPS: u64 = 20_000;
+// Steps per queue submission, so long runs don't hold the GPU in one huge submit
+const STEPS_PER_SUBMIT: u32 = 100;
+
+pub struct RenderArgs {
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32, // world units across the image
+    pub steps: u64,
+    pub out: PathBuf,
+    pub params: Params,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Usage(String),
+    NoAdapter,
+    NoCompute,
+    TooLarge(u32, u32),
+    Export(ExportError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(e) => write!(f, "{e} (see `gpusim render --help`)"),
+            CliError::NoAdapter => write!(f, "no GPU adapter available"),
+            CliError::NoCompute => write!(f, "the GPU adapter has no compute shaders"),
+            CliError::TooLarge(w, h) => write!(f, "a {w}x{h} grid exceeds the device's limits"),
+            CliError::Export(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<ExportError> for CliError {
+    fn from(e: ExportError) -> Self {
+        CliError::Export(e)
+    }
+}
+
+/// Runs `gpusim render` with the arguments after the subcommand.
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), CliError> {
+    let Some(args) = parse_render_args(args)? else {
+        println!("{}", usage());
+        return Ok(());
+    };
+    let rs = headless_render_state().ok_or(CliError::NoAdapter)?;
+    if !GPUSim::fits_device(&rs, args.width, args.height) {
+        return Err(CliError::TooLarge(args.width, args.height));
+    }
+    let mut sim = GPUSim::new(&rs, args.width, args.height, args.scale);
+    if sim.is_fragment_only() {
+        return Err(CliError::NoCompute);
+    }
+    sim.params = args.params;
+    sim.restart(&rs);
+
+    sim.steps_per_frame = STEPS_PER_SUBMIT;
+    sim.advance(&rs, (args.steps / STEPS_PER_SUBMIT as u64) as u32);
+    let rest = (args.steps % STEPS_PER_SUBMIT as u64) as u32;
+    if rest > 0 {
+        sim.steps_per_frame = rest;
+        sim.advance(&rs, 1);
+    }
+    sim.export_png(&rs, &args.out)?;
+    println!("wrote {} ({}x{}, {} steps)", args.out.display(), args.width, args.height, args.steps);
+    Ok(())
+}
+
+/// Parses the flags of `gpusim render`. `None` means `--help` was given.
+pub fn parse_render_args(args: impl IntoIterator<Item = String>) -> Result<Option<RenderArgs>, CliError> {
+    let view = View::default();
+    let (mut width, mut height, mut scale) = (view.width, None, view.scale);
+    let (mut steps, mut out) = (DEFAULT_STEPS, PathBuf::from("render.png"));
+    let mut fields = default_fields();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok(None);
+        }
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(CliError::Usage(format!("unexpected argument {arg:?}")));
+        };
+        let (flag, value) = match flag.split_once('=') {
+            Some((flag, value)) => (flag.to_owned(), value.to_owned()),
+            None => {
+                let value = args.next().ok_or_else(|| CliError::Usage(format!("--{flag} needs a value")))?;
+                (flag.to_owned(), value)
+            }
+        };
+        let invalid = || CliError::Usage(format!("invalid value {value:?} for --{flag}"));
+        match flag.as_str() {
+            "width" => width = value.parse().map_err(|_| invalid())?,
+            "height" => height = Some(value.parse().map_err(|_| invalid())?),
+            "scale" => scale = value.parse().map_err(|_| invalid())?,
+            "steps" => steps = value.parse().map_err(|_| invalid())?,
+            "out" => out = PathBuf::from(&value),
+            _ => {
+                let key = flag.replace('-', "_");
+                let Some(field) = fields.get_mut(&key) else {
+                    return Err(CliError::Usage(format!("unknown flag --{flag}")));
+                };
+                // Keep integers as integers, so they still deserialize into u32 fields
+                *field = serde_json::from_str::<Value>(&value).ok().filter(Value::is_number).ok_or_else(invalid)?;
+            }
+        }
+    }
+    let height = height.unwrap_or(width);
+    if width == 0 || height == 0 || !scale.is_finite() || scale <= 0.0 {
+        return Err(CliError::Usage("the image size and scale must be positive".to_owned()));
+    }
+
+    let mut params: Params = serde_json::from_value(Value::Object(fields))
+        .map_err(|e| CliError::Usage(format!("invalid params: {e}")))?;
+    (params.w, params.h) = (width, height);
+    params.sanitize();
+    Ok(Some(RenderArgs { width, height, scale, steps, out, params }))
+}
+
+// The numeric fields of the default params by name, which double as the param flags
+fn default_fields() -> serde_json::Map<String, Value> {
+    match serde_json::to_value(Params::default(0, 0)) {
+        Ok(Value::Object(fields)) => fields.into_iter().filter(|(_, v)| v.is_number()).collect(),
+        _ => serde_json::Map::new(),
+    }
+}
+
+fn usage() -> String {
+    let view = View::default();
+    let mut text = format!(
+        "Renders a simulation without a window and saves it as a PNG.\n\n\
+         Usage: gpusim render [--width N] [--height N] [--scale UNITS] [--steps N] [--out FILE] [--PARAM VALUE]...\n\n  \
+         --width N        image width in pixels ({})\n  \
+         --height N       image height in pixels (the width)\n  \
+         --scale UNITS    world units across the image ({})\n  \
+         --steps N        simulation steps before the readback ({DEFAULT_STEPS})\n  \
+         --out FILE       PNG to write (render.png)\n\n\
+         Params, with their defaults:\n",
+        view.width, view.scale,
+    );
+    for (name, default) in default_fields() {
+        text += &format!("  --{:<22} {default}\n", name.replace('_', "-"));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<RenderArgs>, CliError> {
+        parse_render_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn flags_override_the_default_params() {
+        let args = parse(&["--width", "64", "--mu=0.1", "--d", "0.3", "--velocity-magnitude", "2", "--n", "3"])
+            .unwrap()
+            .unwrap();
+        assert_eq!((args.width, args.height), (64, 64));
+        let expected = Params { mu: 0.1, d: 0.3, velocity_magnitude: 2.0, n: 3, ..Params::default(64, 64) };
+        assert_eq!(serde_json::to_value(args.params).unwrap(), serde_json::to_value(expected).unwrap());
+        assert_eq!(args.steps, DEFAULT_STEPS);
+    }
+
+    #[test]
+    fn bad_flags_are_rejected() {
+        assert!(matches!(parse(&["--nonsense", "1"]), Err(CliError::Usage(_))));
+        assert!(matches!(parse(&["--mu", "fast"]), Err(CliError::Usage(_))));
+        assert!(matches!(parse(&["--n", "2.5"]), Err(CliError::Usage(_))));
+        assert!(matches!(parse(&["--width"]), Err(CliError::Usage(_))));
+        assert!(matches!(parse(&["--help"]), Ok(None)));
+    }
+}
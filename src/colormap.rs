@@ -0,0 +1,138 @@
+// Colormaps the output can be drawn with. Every table is resampled to COLORMAP_LEN
+// entries, so any of them fits a slot of the colormap buffer.
+
+use crate::twilight::TWILIGHT_MAP;
+
+/// Entries in each colormap table, and in each slot of the colormap buffer. The
+/// shader's `COLORMAP_LEN` matches.
+pub const COLORMAP_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Colormap {
+    #[default]
+    Twilight,
+    TwilightShifted, // twilight rotated by half a turn, dark in the middle
+    Grayscale,
+    Viridis,
+    Inferno,
+    Magma,
+}
+
+impl Colormap {
+    // New maps go at the end, since saved params refer to maps by index
+    pub const ALL: [Colormap; 6] = [
+        Colormap::Twilight,
+        Colormap::TwilightShifted,
+        Colormap::Grayscale,
+        Colormap::Viridis,
+        Colormap::Inferno,
+        Colormap::Magma,
+    ];
+
+    /// The map stored as `index` in `Params`, falling back to twilight.
+    pub fn from_index(index: u32) -> Self {
+        Self::ALL.get(index as usize).copied().unwrap_or_default()
+    }
+
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    /// Lowercase name, as recorded in configs and image metadata.
+    pub fn key(self) -> String {
+        self.name().to_lowercase()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Twilight => "Twilight",
+            Colormap::TwilightShifted => "Twilight shifted",
+            Colormap::Grayscale => "Grayscale",
+            Colormap::Viridis => "Viridis",
+            Colormap::Inferno => "Inferno",
+            Colormap::Magma => "Magma",
+        }
+    }
+
+    /// COLORMAP_LEN RGBA entries from the start of the map to its end, gamma encoded
+    /// like the output texture.
+    pub fn table(self) -> Vec<[f32; 4]> {
+        match self {
+            Colormap::Twilight => resample(&TWILIGHT_MAP),
+            Colormap::TwilightShifted => {
+                let mut table = resample(&TWILIGHT_MAP);
+                table.rotate_left(COLORMAP_LEN / 2);
+                table
+            }
+            Colormap::Grayscale => (0..COLORMAP_LEN)
+                .map(|i| {
+                    let v = i as f32 / (COLORMAP_LEN - 1) as f32;
+                    [v, v, v, 1.0]
+                })
+                .collect(),
+            Colormap::Viridis => resample(&VIRIDIS),
+            Colormap::Inferno => resample(&INFERNO),
+            Colormap::Magma => resample(&MAGMA),
+        }
+    }
+}
+
+// Matplotlib's perceptually uniform maps, sampled at 11 evenly spaced points. They
+// change smoothly enough for linear interpolation between them to be indistinguishable
+const VIRIDIS: [[f32; 4]; 11] = [
+    [0.2667, 0.0039, 0.3294, 1.0],
+    [0.2824, 0.1412, 0.4588, 1.0],
+    [0.2549, 0.2667, 0.5294, 1.0],
+    [0.2078, 0.3725, 0.5529, 1.0],
+    [0.1647, 0.4706, 0.5569, 1.0],
+    [0.1294, 0.5686, 0.5490, 1.0],
+    [0.1333, 0.6588, 0.5176, 1.0],
+    [0.2667, 0.7490, 0.4392, 1.0],
+    [0.4784, 0.8196, 0.3176, 1.0],
+    [0.7412, 0.8745, 0.1490, 1.0],
+    [0.9922, 0.9059, 0.1451, 1.0],
+];
+
+const INFERNO: [[f32; 4]; 11] = [
+    [0.0000, 0.0000, 0.0157, 1.0],
+    [0.0863, 0.0431, 0.2235, 1.0],
+    [0.2588, 0.0392, 0.4078, 1.0],
+    [0.4157, 0.0902, 0.4314, 1.0],
+    [0.5765, 0.1490, 0.4039, 1.0],
+    [0.7373, 0.2157, 0.3294, 1.0],
+    [0.8667, 0.3176, 0.2275, 1.0],
+    [0.9529, 0.4706, 0.0980, 1.0],
+    [0.9882, 0.6471, 0.0392, 1.0],
+    [0.9647, 0.8431, 0.2745, 1.0],
+    [0.9882, 1.0000, 0.6431, 1.0],
+];
+
+const MAGMA: [[f32; 4]; 11] = [
+    [0.0000, 0.0000, 0.0157, 1.0],
+    [0.0784, 0.0549, 0.2118, 1.0],
+    [0.2314, 0.0588, 0.4392, 1.0],
+    [0.3922, 0.1020, 0.5020, 1.0],
+    [0.5490, 0.1608, 0.5059, 1.0],
+    [0.7176, 0.2157, 0.4745, 1.0],
+    [0.8706, 0.2863, 0.4078, 1.0],
+    [0.9686, 0.4392, 0.3608, 1.0],
+    [0.9961, 0.6235, 0.4275, 1.0],
+    [0.9961, 0.8118, 0.5725, 1.0],
+    [0.9882, 0.9922, 0.7490, 1.0],
+];
+
+
+/// `table`, of at least two entries, linearly resampled to COLORMAP_LEN entries with
+/// the same first and last ones.
+pub fn resample(table: &[[f32; 4]]) -> Vec<[f32; 4]> {
+    (0..COLORMAP_LEN).map(|i| sample(table, i as f32 / (COLORMAP_LEN - 1) as f32)).collect()
+}
+
+// Mirrors `sample_map` in the shader
+pub(crate) fn sample(table: &[[f32; 4]], t: f32) -> [f32; 4] {
+    let last = table.len() - 1;
+    let x = t.clamp(0.0, 1.0) * last as f32;
+    let i = (x as usize).min(last - 1);
+    let f = x - i as f32;
+    std::array::from_fn(|c| table[i][c] * (1.0 - f) + table[i + 1][c] * f)
+}
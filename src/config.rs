@@ -0,0 +1,220 @@
+// Versioned settings files. Each file records the layout version it was written with.
+// Fields added since a file was written load as their defaults, so saved configs keep
+// loading as `Params` and friends gain fields; changes beyond that bump the version
+// and add a step to `migrate`.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::colormap::Colormap;
+use crate::sim::{Magnet, Params};
+
+/// Layout version written by this build.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub version: u32,
+    pub params: Params,
+    pub view: View,
+    pub colormap: String,
+    pub magnets: Option<Vec<SavedMagnet>>, // a custom layout, None for the ring of params.n at radius params.r
+}
+
+/// A magnet of a custom layout as a config records it, see `GPUSim::set_magnets`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SavedMagnet {
+    pub position: [f32; 2],
+    pub strength: f32,
+    pub color: [f32; 4],
+}
+
+impl From<&Magnet> for SavedMagnet {
+    fn from(magnet: &Magnet) -> Self {
+        Self { position: magnet.position.to_array(), strength: magnet.strength, color: magnet.color }
+    }
+}
+
+impl From<&SavedMagnet> for Magnet {
+    fn from(magnet: &SavedMagnet) -> Self {
+        Magnet::new(magnet.position.into(), magnet.strength, magnet.color)
+    }
+}
+
+/// Grid and world size of the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self { width: 1024, height: 1024, scale: 25.0 }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Json(serde_json::Error),
+    NoVersion,
+    TooNew(u32), // written by a newer build
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Json(e) => write!(f, "invalid config: {e}"),
+            ConfigError::NoVersion => write!(f, "invalid config: no version"),
+            ConfigError::TooNew(version) => {
+                write!(f, "config version {version} is newer than the supported {CONFIG_VERSION}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Json(e)
+    }
+}
+
+impl Config {
+    pub fn new(params: Params, view: View, magnets: Option<&[Magnet]>) -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            params,
+            view,
+            colormap: Colormap::from_index(params.colormap).key(),
+            magnets: magnets.map(|magnets| magnets.iter().map(SavedMagnet::from).collect()),
+        }
+    }
+
+    /// The custom magnet layout, if the config has one.
+    pub fn magnets(&self) -> Option<Vec<Magnet>> {
+        self.magnets.as_ref().map(|magnets| magnets.iter().map(Magnet::from).collect())
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Parses a config of any version up to [`CONFIG_VERSION`], filling fields added
+    /// since it was written with their defaults. The params are not sanitized.
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        let mut config: Value = serde_json::from_str(json)?;
+        migrate(&mut config)?;
+        let view = View::default();
+        let defaults = Config::new(Params::default(view.width, view.height), view, None);
+        fill_defaults(&mut config, &serde_json::to_value(defaults)?);
+        Ok(serde_json::from_value(config)?)
+    }
+}
+
+/// Serializes `params` alone as a preset, without the view a config records.
+pub fn preset_to_json(params: &Params) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(params)
+}
+
+/// Parses a preset, filling missing fields with their defaults and clamping the core
+/// parameters into their sliders' ranges. The grid size is left at 0 for the caller.
+pub fn preset_from_json(json: &str) -> Result<Params, ConfigError> {
+    let mut preset: Value = serde_json::from_str(json)?;
+    fill_defaults(&mut preset, &serde_json::to_value(Params::default(0, 0))?);
+    let mut params: Params = serde_json::from_value(preset)?;
+    params.clamp_to_slider_ranges();
+    Ok(params)
+}
+
+// Upgrades `config` in place to the current layout. Version 1 is the first, so there
+// is nothing to upgrade yet
+fn migrate(config: &mut Value) -> Result<(), ConfigError> {
+    let version = config.get("version").and_then(Value::as_u64).ok_or(ConfigError::NoVersion)? as u32;
+    if version > CONFIG_VERSION {
+        return Err(ConfigError::TooNew(version));
+    }
+    config["version"] = CONFIG_VERSION.into();
+    Ok(())
+}
+
+// Adds the fields of `defaults` that `value` lacks, recursing into objects both have
+fn fill_defaults(value: &mut Value, defaults: &Value) {
+    if let (Value::Object(fields), Value::Object(default_fields)) = (value, defaults) {
+        for (key, default) in default_fields {
+            match fields.get_mut(key) {
+                Some(field) => fill_defaults(field, default),
+                None => {
+                    fields.insert(key.clone(), default.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A config lacking most params and every other field, as if written before they
+    // were added
+    const SPARSE: &str = r#"{
+        "version": 1,
+        "params": {
+            "n": 3, "r": 2.5, "d": 0.2, "mu": 0.05, "c": 0.1, "dt": 0.008,
+            "velocity_magnitude": 6.0, "velocity_angle": 0.5, "velocity_pattern": 0
+        }
+    }"#;
+
+    #[test]
+    fn missing_fields_load_as_their_defaults() {
+        let config = Config::from_json(SPARSE).unwrap();
+        let defaults = Params::default(1, 1);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!((config.params.n, config.params.r, config.params.mu), (3, 2.5, 0.05));
+        assert_eq!((config.params.velocity_angle, config.params.velocity_pattern), (0.5, 0));
+        assert_eq!(config.params.integrator, defaults.integrator);
+        assert_eq!((config.params.color_min, config.params.color_max), (defaults.color_min, defaults.color_max));
+        assert_eq!(config.view, View::default());
+        assert_eq!(config.colormap, "twilight");
+        assert_eq!(config.magnets, None);
+    }
+
+    #[test]
+    fn current_config_round_trips() {
+        let params = Params { n: 4, mu: 0.3, color_mode: 1, ..Params::default(1, 1) };
+        let view = View { width: 512, height: 256, scale: 10.0 };
+        let magnets = [Magnet::new(glam::vec2(1.0, -2.0), 2.5, [1.0, 0.0, 0.5, 1.0]), Magnet::new(glam::Vec2::ZERO, 0.5, [0.0; 4])];
+        let json = Config::new(params, view, Some(&magnets)).to_json().unwrap();
+        let config = Config::from_json(&json).unwrap();
+        assert_eq!((config.params.n, config.params.mu, config.params.color_mode), (4, 0.3, 1));
+        assert_eq!(config.view, view);
+        assert_eq!(config.magnets(), Some(magnets.to_vec()));
+
+        let json = Config::new(params, view, None).to_json().unwrap();
+        assert_eq!(Config::from_json(&json).unwrap().magnets(), None);
+    }
+
+    #[test]
+    fn newer_and_unversioned_configs_are_rejected() {
+        let newer = format!(r#"{{ "version": {} }}"#, CONFIG_VERSION + 1);
+        assert!(matches!(Config::from_json(&newer), Err(ConfigError::TooNew(_))));
+        assert!(matches!(Config::from_json(r#"{ "params": {} }"#), Err(ConfigError::NoVersion)));
+    }
+
+    #[test]
+    fn presets_are_clamped_to_the_sliders() {
+        let params = preset_from_json(r#"{ "n": 40, "dt": 1.0, "mu": -2.0, "r": 2.0 }"#).unwrap();
+        assert_eq!((params.n, params.dt, params.mu, params.r), (10, 0.05, 0.0, 2.0));
+        assert_eq!(params.integrator, Params::default(0, 0).integrator);
+
+        let params = Params { n: 4, mu: 0.3, ..Params::default(0, 0) };
+        let loaded = preset_from_json(&preset_to_json(&params).unwrap()).unwrap();
+        assert_eq!((loaded.n, loaded.mu), (4, 0.3));
+        assert!(preset_from_json("[1, 2]").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+// PNG export. The parameters that produced an image are embedded in it as an iTXt
+// chunk, so any saved image can be loaded back and regenerated exactly.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sim::Params;
+
+const METADATA_KEYWORD: &str = "gpusim";
+
+/// Everything needed to reproduce an exported image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    pub params: Params,
+    pub width: u32,
+    pub height: u32,
+    pub steps: u64,
+    pub colormap: String,
+    pub seed: u64,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Readback, // the GPU copy could not be mapped synchronously
+    Encode(png::EncodingError),
+    Io(std::io::Error),
+    Metadata(serde_json::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Readback => write!(f, "failed to read the image back from the GPU"),
+            ExportError::Encode(e) => write!(f, "failed to encode PNG: {e}"),
+            ExportError::Io(e) => write!(f, "failed to write image: {e}"),
+            ExportError::Metadata(e) => write!(f, "failed to serialize parameters: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<png::EncodingError> for ExportError {
+    fn from(e: png::EncodingError) -> Self {
+        ExportError::Encode(e)
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        ExportError::Metadata(e)
+    }
+}
+
+/// Encodes tightly packed RGBA8 rows as a PNG with `metadata` embedded.
+pub fn encode_png(rgba: &[u8], metadata: &ImageMetadata) -> Result<Vec<u8>, ExportError> {
+    encode(rgba, png::ColorType::Rgba, png::BitDepth::Eight, metadata)
+}
+
+/// Encodes 16-bit grayscale rows as a PNG with `metadata` embedded, for data that
+/// needs more precision than a colored image holds.
+pub fn encode_png_gray16(values: &[u16], metadata: &ImageMetadata) -> Result<Vec<u8>, ExportError> {
+    // PNG samples are big-endian
+    let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+    encode(&data, png::ColorType::Grayscale, png::BitDepth::Sixteen, metadata)
+}
+
+fn encode(
+    data: &[u8],
+    color: png::ColorType,
+    depth: png::BitDepth,
+    metadata: &ImageMetadata,
+) -> Result<Vec<u8>, ExportError> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, metadata.width, metadata.height);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    encoder.add_itxt_chunk(METADATA_KEYWORD.to_owned(), serde_json::to_string(metadata)?)?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    Ok(bytes)
+}
+
+// 3×5 pixel glyphs for burned-in overlays, one row of bits per entry, top first.
+// Letters are drawn the same in either case. The canvas draws the counter with egui's
+// painter, but that only reaches the screen: its glyphs are rasterized into egui's
+// font atlas on the GPU. Burning the counter into an exported image means writing
+// pixels into the RGBA rows read back here. The counter only needs these few
+// characters, so a table is simpler than adding a font rasterizer dependency
+const GLYPHS: &[(char, [u8; 5])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('a', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('e', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('f', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('m', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('p', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('r', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('s', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('t', [0b111, 0b010, 0b010, 0b010, 0b010]),
+];
+
+/// Draws `text` in white on a black box into the top left corner of tightly packed
+/// RGBA8 rows, each glyph pixel `scale` pixels wide. Only digits, '.' and the letters
+/// of "step", "frame" and "t" have glyphs; anything else is a space.
+pub fn burn_text(rgba: &mut [u8], width: u32, height: u32, text: &str, scale: u32) {
+    let (width, height, scale) = (width as usize, height as usize, scale.max(1) as usize);
+    let box_width = (text.chars().count() * 4 + 1) * scale;
+    let box_height = 7 * scale;
+    let mut fill = |x: usize, y: usize, value: u8| {
+        if x < width && y < height {
+            rgba[(x + y * width) * 4..][..4].copy_from_slice(&[value, value, value, 255]);
+        }
+    };
+    for y in 0..box_height {
+        for x in 0..box_width {
+            fill(x, y, 0);
+        }
+    }
+    for (i, c) in text.chars().enumerate() {
+        let Some((_, rows)) = GLYPHS.iter().find(|(g, _)| *g == c.to_ascii_lowercase()) else {
+            continue;
+        };
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                let (x0, y0) = ((1 + i * 4 + col) * scale, (1 + row) * scale);
+                for y in y0..y0 + scale {
+                    for x in x0..x0 + scale {
+                        fill(x, y, 255);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Width, height and per-pixel coverage of a PNG, rows top first: its alpha, or its
+/// brightness if it has no alpha channel. For shaping the seeded region.
+pub fn read_coverage(png_bytes: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(png_bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let pixels = buf[..info.buffer_size()].chunks_exact(info.color_type.samples());
+    let coverage = match info.color_type {
+        png::ColorType::GrayscaleAlpha | png::ColorType::Rgba => pixels.map(|px| px[px.len() - 1]).collect(),
+        png::ColorType::Rgb => pixels.map(|px| ((px[0] as u32 + px[1] as u32 + px[2] as u32) / 3) as u8).collect(),
+        _ => pixels.map(|px| px[0]).collect(),
+    };
+    Some((info.width, info.height, coverage))
+}
+
+/// Width, height and RGBA8 pixels of a PNG, rows top first. Grayscale is expanded
+/// and a missing alpha channel is opaque.
+pub fn read_rgba(png_bytes: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(png_bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let pixels = buf[..info.buffer_size()].chunks_exact(info.color_type.samples());
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.flatten().copied().collect(),
+        png::ColorType::Rgb => pixels.flat_map(|px| [px[0], px[1], px[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.flat_map(|px| [px[0], px[0], px[0], px[1]]).collect(),
+        _ => pixels.flat_map(|px| [px[0], px[0], px[0], 255]).collect(),
+    };
+    Some((info.width, info.height, rgba))
+}
+
+/// Extracts the metadata embedded by [`encode_png`], if there is any.
+pub fn read_metadata(png_bytes: &[u8]) -> Option<ImageMetadata> {
+    let reader = png::Decoder::new(png_bytes).read_info().ok()?;
+    let chunk = reader.info().utf8_text.iter().find(|c| c.keyword == METADATA_KEYWORD)?;
+    serde_json::from_str(&chunk.get_text().ok()?).ok()
+}
@@ -0,0 +1,231 @@
+// Fragment-shader-only simulation for backends without compute shaders, i.e. WebGL2.
+//
+// There is no particle state: each frame every pixel integrates its pendulum from the
+// start for the current number of steps, so the cost per frame grows with the step
+// count and is capped at `MAX_ITER`.
+
+use bytemuck::{Pod, Zeroable};
+use eframe::wgpu;
+use wgpu::{include_wgsl, util::DeviceExt};
+
+use crate::colormap::{Colormap, COLORMAP_LEN};
+use crate::sim::{colormap_slot, colormap_tables, magnet_binding, param_buffer_contents, upload_magnets, Magnet, Params, COLORMAP_SLOTS, MAGNET_OFFSET};
+
+/// Steps after which the fragment-only path stops advancing.
+pub const MAX_ITER: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct FallbackInfo {
+    steps: u32,
+    scale: f32,
+    _padding: [u32; 2],
+}
+
+pub(crate) struct FallbackResources {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    param_buffer: wgpu::Buffer,
+    info_buf: wgpu::Buffer,
+    colormap_tex: wgpu::Texture,
+    primary_colormap: Colormap, // map in the colormap texture's first row
+    secondary_colormap: Colormap, // and in its second
+    pub magnets: Option<Vec<Magnet>>, // replace the ring, see `GPUSim::set_magnets`
+    magnet_table: Vec<Magnet>, // as last uploaded
+    scale: f32,
+    pub step: u64, // steps shown, at most MAX_ITER
+    frames: u32, // frames drawn, wrapping; keys the temporal dither
+}
+
+impl FallbackResources {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target_format: wgpu::TextureFormat,
+        params: &Params,
+        scale: f32,
+    ) -> Self {
+        let param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: &param_buffer_contents(params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let info_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fallback info"),
+            contents: bytemuck::bytes_of(&FallbackInfo { steps: 0, scale, _padding: [0; 2] }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // WebGL2 has no storage buffers, so the colormap is a texture here
+        let colormap_tex = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("colormap texture"),
+                size: wgpu::Extent3d {
+                    width: COLORMAP_LEN as u32,
+                    height: COLORMAP_SLOTS as u32,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&colormap_tables(params)),
+        );
+        let colormap_view = colormap_tex.create_view(&Default::default());
+
+        let uniform = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fallback Layout"),
+            entries: &[
+                uniform(0), // simulation parameters
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                uniform(9), // fallback info
+                uniform(13), // magnet table
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bg_layout,
+            label: Some("Resources described by the fallback layout"),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(&colormap_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: info_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: magnet_binding(&param_buffer),
+                },
+            ],
+        });
+
+        let shader_module = device.create_shader_module(include_wgsl!("shader.wgsl"));
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("fallback pipeline layout"),
+            bind_group_layouts: &[&bg_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Fallback pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_fallback"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_fallback"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            multisample: wgpu::MultisampleState::default(),
+            depth_stencil: None,
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+            param_buffer,
+            info_buf,
+            colormap_tex,
+            primary_colormap: Colormap::from_index(params.colormap),
+            secondary_colormap: Colormap::from_index(params.secondary_colormap),
+            magnets: None,
+            magnet_table: Vec::new(), // uploaded with the first frame
+            scale,
+            step: 0,
+            frames: 0,
+        }
+    }
+
+    pub fn memory_usage(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("Colormap", (COLORMAP_SLOTS * COLORMAP_LEN * std::mem::size_of::<[f32; 4]>()) as u64),
+            ("Uniforms", self.param_buffer.size() + self.info_buf.size()),
+        ]
+    }
+
+    pub fn prepare(&mut self, queue: &wgpu::Queue, params: &Params, steps: u32) {
+        if params.dt != 0.0 {
+            self.step = (self.step + steps as u64).min(MAX_ITER as u64);
+        }
+        let info = FallbackInfo {
+            steps: self.step as u32,
+            scale: self.scale,
+            _padding: [0; 2],
+        };
+        self.frames = self.frames.wrapping_add(1);
+        let mut params = Params { frame: self.frames, scale: self.scale, ..*params };
+        upload_magnets(queue, &self.param_buffer, MAGNET_OFFSET, &mut params, self.magnets.as_deref(), &mut self.magnet_table);
+        queue.write_buffer(&self.param_buffer, 0, bytemuck::bytes_of(&params));
+        queue.write_buffer(&self.info_buf, 0, bytemuck::bytes_of(&info));
+
+        let maps = [params.colormap, params.secondary_colormap].map(Colormap::from_index);
+        for (row, (map, uploaded)) in maps.into_iter().zip([&mut self.primary_colormap, &mut self.secondary_colormap]).enumerate() {
+            if map == *uploaded {
+                continue;
+            }
+            let row_bytes = (COLORMAP_LEN * std::mem::size_of::<[f32; 4]>()) as u32;
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &self.colormap_tex,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: row as u32, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(&colormap_slot(map)),
+                wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(row_bytes), rows_per_image: None },
+                wgpu::Extent3d { width: COLORMAP_LEN as u32, height: 1, depth_or_array_layers: 1 },
+            );
+            *uploaded = map;
+        }
+    }
+
+    pub fn paint(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..4, 0..1);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod app;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
+pub mod colormap;
+pub mod config;
+pub mod export;
+mod fallback;
+mod remote;
+pub mod sim;
+mod twilight;
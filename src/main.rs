@@ -1,18 +1,24 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
-mod twilight;
-mod sim;
-mod app;
-use app::GPUSimApp;
+use gpusim::app::GPUSimApp;
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`)
+    // `gpusim render ...` renders a PNG without opening a window
+    if std::env::args().nth(1).as_deref() == Some("render") {
+        if let Err(e) = gpusim::cli::run(std::env::args().skip(2)) {
+            eprintln!("gpusim render: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     let native_options = eframe::NativeOptions {
         renderer: eframe::Renderer::Wgpu,
         viewport: eframe::egui::ViewportBuilder::default()
             .with_inner_size([1200.0, 800.0])
             .with_min_inner_size([800.0, 600.0])
             .with_resizable(true),
+        wgpu_options: gpusim::sim::wgpu_configuration(),
         ..Default::default()
     };
     eframe::run_native(
@@ -28,7 +34,10 @@ fn main() {
     use eframe::wasm_bindgen::JsCast as _;
     // Redirect `log` message to `console.log` and friends:
     eframe::WebLogger::init(log::LevelFilter::Debug).ok();
-    let web_options = eframe::WebOptions::default();
+    let web_options = eframe::WebOptions {
+        wgpu_options: gpusim::sim::wgpu_configuration(),
+        ..Default::default()
+    };
     wasm_bindgen_futures::spawn_local(async {
         let document = web_sys::window()
             .expect("No window")
@@ -54,7 +63,7 @@ fn main() {
                 }
                 Err(e) => {
                     loading_text.set_inner_html(
-                        "<p> WebGPU is not available in your browser.</br>Please try a different browser or device. </p>",
+                        "<p> Neither WebGPU nor WebGL2 is available in your browser.</br>Please try a different browser or device. </p>",
                     );
                     log::error!("Failed to start eframe: {e:?}");
                     panic!("Failed to start eframe: {e:?}");
@@ -0,0 +1,115 @@
+// Live parameter control from external tools.
+//
+// Each message is a JSON object holding any subset of the `Params` fields, e.g.
+// `{"mu": 0.1, "velocity_angle": 1.57}`, and is merged into the current params.
+// Natively this is a TCP server reading newline-delimited messages, enabled by setting
+// `GPUSIM_LISTEN=127.0.0.1:9000`. On the web it is a WebSocket client, enabled with
+// a `?ws=ws://localhost:9001` query parameter.
+
+use crate::sim::Params;
+
+pub struct RemoteParams {
+    #[cfg(not(target_arch = "wasm32"))]
+    rx: std::sync::mpsc::Receiver<String>,
+    #[cfg(target_arch = "wasm32")]
+    queue: std::rc::Rc<std::cell::RefCell<std::collections::VecDeque<String>>>,
+    #[cfg(target_arch = "wasm32")]
+    _socket: web_sys::WebSocket,
+}
+
+impl RemoteParams {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_env() -> Option<Self> {
+        use std::io::BufRead;
+
+        let addr = std::env::var("GPUSIM_LISTEN").ok()?;
+        let listener = match std::net::TcpListener::bind(&addr) {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::error!("Failed to listen for params on {addr}: {e}");
+                return None;
+            }
+        };
+        log::info!("Listening for params on {addr}");
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    for line in std::io::BufReader::new(stream).lines().map_while(Result::ok) {
+                        if tx.send(line).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        Some(Self { rx })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_env() -> Option<Self> {
+        use eframe::wasm_bindgen::{closure::Closure, JsCast as _};
+
+        let search = web_sys::window()?.location().search().ok()?;
+        let url = web_sys::UrlSearchParams::new_with_str(&search).ok()?.get("ws")?;
+        let socket = match web_sys::WebSocket::new(&url) {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::error!("Failed to connect to {url}: {e:?}");
+                return None;
+            }
+        };
+
+        let queue = std::rc::Rc::new(std::cell::RefCell::new(std::collections::VecDeque::new()));
+        let sink = queue.clone();
+        let on_message = Closure::<dyn FnMut(_)>::new(move |e: web_sys::MessageEvent| {
+            if let Some(text) = e.data().as_string() {
+                sink.borrow_mut().extend(text.lines().map(str::to_owned));
+            }
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+        Some(Self { queue, _socket: socket })
+    }
+
+    /// Merges every message received since the last call into `params`.
+    /// Returns true if anything changed.
+    pub fn apply(&mut self, params: &mut Params) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        let messages: Vec<String> = self.rx.try_iter().collect();
+        #[cfg(target_arch = "wasm32")]
+        let messages: Vec<String> = self.queue.borrow_mut().drain(..).collect();
+
+        let mut changed = false;
+        for message in messages.iter().filter(|m| !m.trim().is_empty()) {
+            match apply_delta(params, message) {
+                Ok(()) => changed = true,
+                Err(e) => log::warn!("Ignoring params message {message:?}: {e}"),
+            }
+        }
+        changed
+    }
+}
+
+fn apply_delta(params: &mut Params, message: &str) -> Result<(), serde_json::Error> {
+    let serde_json::Value::Object(delta) = serde_json::from_str(message)? else {
+        return Err(serde::de::Error::custom("expected a JSON object"));
+    };
+    let mut merged = serde_json::to_value(*params)?;
+    if let serde_json::Value::Object(fields) = &mut merged {
+        for (key, value) in delta {
+            if fields.contains_key(&key) {
+                fields.insert(key, value);
+            } else {
+                log::warn!("Unknown param {key:?}");
+            }
+        }
+    }
+    // The grid size isn't serialized, so carry it over from the running sim
+    let (w, h) = (params.w, params.h);
+    *params = serde_json::from_value(merged)?;
+    (params.w, params.h) = (w, h);
+    Ok(())
+}
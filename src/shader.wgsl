@@ -8,8 +8,22 @@ fn length2(v: vec2f) -> f32 { return v.x * v.x + v.y * v.y; }
 struct Particle {
     u: vec2f,
     du: vec2f,
+    passes: u32, // entries within d of a magnet, with NEAR_BIT set while inside
+    flags: u32,
+    age: f32, // simulated time since the start
+    settled_at: f32, // age when last faster than SETTLED_SPEED
+    anchor: vec2f, // position at the start of the current drift window
+    anchor_age: f32,
+    drift: f32, // net displacement over the last complete window per unit time
+    prev_u: vec2f, // position at the start of the last frame
+    mass: f32, // inertia the forces are divided by
 };
 
+const NEAR_BIT: u32 = 0x80000000u;
+const INACTIVE: u32 = 1u; // flag of pendulums left out by a mask
+const SETTLED_SPEED: f32 = 0.01; // the app's SETTLE_SPEED
+const ORBIT_BINS: u32 = 16u;
+
 struct Params {
     n: u32,
     r: f32, // radius of the magnets from centre
@@ -19,6 +33,79 @@ struct Params {
     dt: f32,
     w: u32,
     h: u32,
+    velocity_magnitude: f32,
+    velocity_angle: f32,
+    velocity_pattern: u32,
+    dither: u32,
+    integrator: u32, // 0 = semi-implicit Euler, 1 = RK4
+    compare_integrators: u32, // nonzero: Euler left of `compare_split`, RK4 right of it
+    compare_split: f32, // fraction of the width
+    cutoff: f32, // magnets further than this contribute nothing, 0 = no cutoff
+    jitter_pattern: u32,
+    jitter_sample: u32,
+    seed: u32,
+    color_mode: u32, // 0 = final angle, 1 = speed, 2 = settling time, 3 = activity, 4 = nearest magnet
+    color_min: f32, // scalar mapped to the start of the colormap
+    color_max: f32, // and to its end
+    invert_colormap: u32,
+    highlight_diverged: u32,
+    banding: u32, // bands the colormap is quantized to, below 2 = smooth
+    spring_cubic: f32, // cubic coefficient of the spring, 0 = Hooke
+    compose_colormaps: u32, // nonzero: the secondary map takes over above colormap_split
+    secondary_colormap: u32, // only read on the CPU, which uploads the map
+    colormap_split: f32,
+    colormap_blend: f32, // width of the crossfade around the split
+    anisotropic_spring: u32, // nonzero: spring constants cx along x and cy along y instead of c
+    cx: f32,
+    cy: f32,
+    softness: f32, // force falls off as 1 / (dist² + d²)^(softness / 2)
+    convergence_metric: u32, // 0 = speed, 1 = drift
+    settle_window: u32, // steps the drift is measured over
+    temporal_dither: u32, // nonzero: the displayed dither pattern moves with `frame`, where it's dithered on display
+    seed_coords: u32, // 0 = Cartesian grid, 1 = polar: columns are angles, rows radii
+    mass_min: f32, // seeded mass at the centre
+    mass_max: f32, // and at the edge of the view
+    tolerance: f32, // relative local error per adaptive substep
+    max_substeps: u32, // adaptive substeps per dt at most
+    alpha_mask: u32, // nonzero: only settled pendulums are opaque
+    colormap: u32, // only read on the CPU, like secondary_colormap
+    center_x: f32, // world position seeded at the middle of the canvas
+    center_y: f32,
+    zoom: f32,
+    frame: u32, // frames drawn, wrapping
+    scale: f32, // world units spanned by the canvas
+}
+
+// 4x4 Bayer matrix, normalized to [0, 1)
+fn bayer4(p: vec2u) -> f32 {
+    let m = array<u32, 16>(0u, 8u, 2u, 10u, 12u, 4u, 14u, 6u, 3u, 11u, 1u, 9u, 15u, 7u, 13u, 5u);
+    return f32(m[(p.x & 3u) + (p.y & 3u) * 4u]) / 16.0;
+}
+
+// Offsets a color by under one 8-bit step so gradients break up into a fine pattern
+// instead of bands. Colors are already gamma encoded, so the dither is perceptually even.
+fn dither8(col: vec4f, p: vec2u) -> vec4f {
+    return vec4f(col.rgb + (bayer4(p) - 0.5) / 255.0, col.a);
+}
+
+// Where the compute path dithers. An rgba8unorm texture quantizes on store, so the
+// dither has to go in before it there; float textures keep full precision until
+// fs_main quantizes for the screen. The sim sets this per output format, so the
+// image is dithered exactly once. fs_fallback draws straight to the screen and
+// always dithers there
+const DITHER_AT_DISPLAY = false;
+
+// Offset of the dither pattern on screen. With temporal dithering it walks the 4x4
+// matrix every frame drawn, so each pixel cycles through all thresholds and bands
+// average out in motion. It's keyed on frames rather than steps, which can advance by
+// a multiple of 16 per frame. Only dithering at display time moves: a dither baked
+// into an rgba8unorm texture stays fixed, so exports don't get the noise and the two
+// never stack
+fn dither_shift(p: Params) -> vec2u {
+    if (p.temporal_dither == 0u) {
+        return vec2u(0u);
+    }
+    return vec2u(p.frame & 3u, (p.frame >> 2u) & 3u);
 }
 
 @group(0) @binding(0)
@@ -30,44 +117,571 @@ var<storage, read_write> particles: array<Particle>;
 @group(0) @binding(2) 
 var tex: texture_storage_2d<rgba8unorm, write>;
 
+// The primary colormap, then the secondary one
 @group(0) @binding(3)
 var<storage, read> colormap: array<vec4<f32>>;
 
+// Reduction results hold the bits of non-negative f32s, which order the same as the u32s
+struct Stats {
+    max_speed: atomic<u32>,
+    scalar_min: atomic<u32>,
+    scalar_max: atomic<u32>,
+    orbit_hist: array<atomic<u32>, 1024>, // plain counts, ORBIT_BINS for each of up to 64 magnets
+    diverged: atomic<u32>, // pendulums that went non-finite, kept across reductions
+    energy: array<f32>, // per-workgroup sums of reduce_energy, summed on the CPU
+}
+
+@group(0) @binding(4)
+var<storage, read_write> stats: Stats;
+
+const COLORMAP_LEN: u32 = 256u; // entries per map, the app's COLORMAP_LEN
+
+// Linearly interpolates between adjacent entries of colormap `map`, t in [0, 1]
+fn sample_map(map: u32, t: f32) -> vec4f {
+    let last = COLORMAP_LEN - 1u;
+    let x = saturate(t) * f32(last);
+    let i = min(u32(x), last - 1u); // keeps i + 1 in the map at t = 1
+    let j = min(map, 1u) * COLORMAP_LEN + i;
+    return mix(colormap[j], colormap[j + 1u], x - f32(i));
+}
+
+// How much of the secondary colormap shows at t
+fn secondary_weight(t: f32) -> f32 {
+    if (params.compose_colormaps == 0u) { return 0.0; }
+    let half = params.colormap_blend * 0.5;
+    if (half <= 0.0) { return step(params.colormap_split, t); }
+    return smoothstep(params.colormap_split - half, params.colormap_split + half, t);
+}
+
+fn sample_colormap(t: f32) -> vec4f {
+    let w = secondary_weight(t);
+    if (w <= 0.0) { return sample_map(0u, t); }
+    return mix(sample_map(0u, t), sample_map(1u, t), w);
+}
+
+fn is_finite(p: Particle) -> bool {
+    // An all-ones exponent is infinity or NaN
+    let bits = bitcast<vec4u>(vec4f(p.u, p.du)) & vec4u(0x7f800000u);
+    return all(bits != vec4u(0x7f800000u));
+}
+
+// With the alpha mask on, pendulums still moving faster than SETTLED_SPEED are
+// transparent, so the settled regions can be keyed. Colors are premultiplied, so the
+// whole color goes to zero, not just its alpha
+fn mask_alpha(col: vec4f, p: Particle) -> vec4f {
+    if (params.alpha_mask == 0u) { return col; }
+    return col * select(0.0, 1.0, settle_speed(p) <= SETTLED_SPEED);
+}
+
+// Diverged pendulums are left transparent, premultiplied, unless highlighted
+fn diverged_color() -> vec4f {
+    return select(vec4f(0.0), vec4f(1.0, 0.0, 1.0, 1.0), params.highlight_diverged != 0u);
+}
+
+// The scalar the colormap shows, always non-negative
+fn color_scalar(p: Particle) -> f32 {
+    if (params.color_mode == 1u) {
+        return length(p.du);
+    }
+    if (params.color_mode == 2u) {
+        return p.settled_at;
+    }
+    if (params.color_mode == 3u) {
+        return length(p.u - p.prev_u);
+    }
+    return saturate((atan2(p.u.y, p.u.x) + pi) / tau);
+}
+
+fn scalar_color(p: Particle) -> vec4f {
+    if (params.color_mode == 4u) {
+        return magnets[nearest_magnet(p.u)].color;
+    }
+    return sample_colormap(colormap_t(p));
+}
+
+// Position along the colormap for a particle, in [0, 1]
+fn colormap_t(p: Particle) -> f32 {
+    var t = saturate((color_scalar(p) - params.color_min) / max(params.color_max - params.color_min, 1e-6));
+    if (params.banding >= 2u) {
+        // Bands span the whole map, first and last color included
+        let bands = f32(params.banding);
+        t = min(floor(t * bands), bands - 1.0) / (bands - 1.0);
+    }
+    return select(t, 1.0 - t, params.invert_colormap != 0u);
+}
+
+// The ring of params.n magnets at radius params.r unless the app replaced it, see
+// `GPUSim::set_magnets`
+struct Magnet {
+    position: vec2f,
+    strength: f32, // multiplies the magnet's force
+    color: vec4f, // shown in color mode 4
+}
+
+const MAX_MAGNETS: u32 = 64u;
+
+// A read-only storage buffer on the compute path, which `GPUSim::with_output_format`
+// swaps in. WebGL2 has no storage buffers, so the fragment-only path keeps it uniform
+@group(0) @binding(13)
+var<uniform> magnets: array<Magnet, MAX_MAGNETS>;
+
+fn magnet_pos(i: u32) -> vec2f {
+    return magnets[i].position;
+}
+
+fn nearest_magnet(u: vec2f) -> u32 {
+    var nearest = 0u;
+    var nearest_dist2 = length2(magnet_pos(0u) - u);
+    for (var i: u32 = 1u; i < params.n; i++) {
+        let dist2 = length2(magnet_pos(i) - u);
+        if (dist2 < nearest_dist2) {
+            nearest = i;
+            nearest_dist2 = dist2;
+        }
+    }
+    return nearest;
+}
+
+// Pull of a unit magnet at offset diff, diff / (|diff|² + d²)^(softness / 2). The
+// default softness of 3 keeps the original inverse-cube form, since pow rounds
+// differently and would change every existing render
+fn magnet_pull(diff: vec2f, d2: f32) -> vec2f {
+    if (params.softness == 3.0) {
+        let sq = sqrt(length2(diff) + d2);
+        return diff / (sq * sq * sq);
+    }
+    return diff * pow(length2(diff) + d2, -0.5 * params.softness);
+}
+
+// Acceleration of a pendulum of mass m from the magnets, friction and the central spring
+fn accel(u: vec2f, du: vec2f, m: f32) -> vec2f {
+    var ddu = vec2f(0.0, 0.0);
+
+    let d2 = params.d * params.d;
+    let cutoff2 = params.cutoff * params.cutoff;
+    for (var i: u32 = 0; i < params.n; i++) {
+        let diff = magnet_pos(i) - u;
+        if (cutoff2 > 0.0 && length2(diff) > cutoff2) { continue; }
+        // Unit strengths and masses skip their multiply and divide, so the default
+        // setup steps exactly as it did before either existed
+        let strength = magnets[i].strength;
+        if (strength == 1.0) {
+            ddu += magnet_pull(diff, d2);
+        } else {
+            ddu += strength * magnet_pull(diff, d2);
+        }
+    }
+    // c * (|u| + k|u|³) towards the centre, c per axis if anisotropic
+    let c = select(vec2f(params.c), vec2f(params.cx, params.cy), params.anisotropic_spring != 0u);
+    ddu -= params.mu * du + c * (1.0 + params.spring_cubic * length2(u)) * u;
+    if (m == 1.0) { return ddu; }
+    return ddu / m;
+}
+
+// How fast the particle still moves by the selected convergence metric
+fn settle_speed(p: Particle) -> f32 {
+    return select(length(p.du), p.drift, params.convergence_metric == 1u);
+}
+
+// Potential whose negative gradient is the conservative part of accel. The spring
+// part is exact unless the spring is both anisotropic and cubic, which isn't conservative
+fn potential(u: vec2f) -> f32 {
+    var v = 0.0;
+    let d2 = params.d * params.d;
+    let cutoff2 = params.cutoff * params.cutoff;
+    let s = params.softness;
+    for (var i: u32 = 0; i < params.n; i++) {
+        let diff = magnet_pos(i) - u;
+        if (cutoff2 > 0.0 && length2(diff) > cutoff2) { continue; }
+        let g = length2(diff) + d2;
+        if (abs(s - 2.0) < 1e-4) {
+            v += magnets[i].strength * 0.5 * log(g);
+        } else {
+            v -= magnets[i].strength * pow(g, 1.0 - 0.5 * s) / (s - 2.0);
+        }
+    }
+    let c = select(vec2f(params.c), vec2f(params.cx, params.cy), params.anisotropic_spring != 0u);
+    v += 0.5 * dot(c, u * u) * (1.0 + 0.5 * params.spring_cubic * length2(u));
+    return v;
+}
+
+// Advances the particle's clock by one step of dt, closing the drift window once it
+// spans settle_window steps. Settling time is measured in simulated time rather than
+// steps, so it compares across time steps
+fn tick(p: Particle, dt: f32) -> Particle {
+    var next = p;
+    next.age += dt;
+    let span = next.age - p.anchor_age;
+    if (dt > 0.0 && span >= (f32(params.settle_window) - 0.5) * dt) {
+        next.drift = length(next.u - next.anchor) / span;
+        next.anchor = next.u;
+        next.anchor_age = next.age;
+    }
+    if (settle_speed(next) > SETTLED_SPEED) {
+        next.settled_at = next.age;
+    }
+    return next;
+}
+
+fn step_euler(p: Particle, dt: f32) -> Particle {
+    var q = p;
+    q.du += accel(p.u, p.du, p.mass) * dt;
+    q.u += q.du * dt;
+    return q;
+}
+
+fn step_rk4(p: Particle, dt: f32) -> Particle {
+    let k1u = p.du;
+    let k1v = accel(p.u, p.du, p.mass);
+    let k2u = p.du + 0.5 * dt * k1v;
+    let k2v = accel(p.u + 0.5 * dt * k1u, k2u, p.mass);
+    let k3u = p.du + 0.5 * dt * k2v;
+    let k3v = accel(p.u + 0.5 * dt * k2u, k3u, p.mass);
+    let k4u = p.du + dt * k3v;
+    let k4v = accel(p.u + dt * k3u, k4u, p.mass);
+
+    var q = p;
+    q.u += dt / 6.0 * (k1u + 2.0 * k2u + 2.0 * k3u + k4u);
+    q.du += dt / 6.0 * (k1v + 2.0 * k2v + 2.0 * k3v + k4v);
+    return q;
+}
+
+struct DopriStep {
+    u: vec2f,
+    du: vec2f,
+    err: f32, // local error estimate relative to the state's size
+}
+
+// One Dormand–Prince 5(4) step of size h. The error estimate is the difference to
+// the embedded fourth order solution, and the seventh stage doubles as the
+// derivative at the new state
+fn dopri_step(u: vec2f, du: vec2f, m: f32, h: f32) -> DopriStep {
+    let k1u = du;
+    let k1v = accel(u, du, m);
+    let k2u = du + h * (1.0 / 5.0 * k1v);
+    let k2v = accel(u + h * (1.0 / 5.0 * k1u), k2u, m);
+    let k3u = du + h * (3.0 / 40.0 * k1v + 9.0 / 40.0 * k2v);
+    let k3v = accel(u + h * (3.0 / 40.0 * k1u + 9.0 / 40.0 * k2u), k3u, m);
+    let k4u = du + h * (44.0 / 45.0 * k1v - 56.0 / 15.0 * k2v + 32.0 / 9.0 * k3v);
+    let k4v = accel(u + h * (44.0 / 45.0 * k1u - 56.0 / 15.0 * k2u + 32.0 / 9.0 * k3u), k4u, m);
+    let k5u = du + h * (19372.0 / 6561.0 * k1v - 25360.0 / 2187.0 * k2v + 64448.0 / 6561.0 * k3v - 212.0 / 729.0 * k4v);
+    let k5v = accel(u + h * (19372.0 / 6561.0 * k1u - 25360.0 / 2187.0 * k2u + 64448.0 / 6561.0 * k3u - 212.0 / 729.0 * k4u), k5u, m);
+    let k6u = du + h * (9017.0 / 3168.0 * k1v - 355.0 / 33.0 * k2v + 46732.0 / 5247.0 * k3v + 49.0 / 176.0 * k4v - 5103.0 / 18656.0 * k5v);
+    let k6v = accel(u + h * (9017.0 / 3168.0 * k1u - 355.0 / 33.0 * k2u + 46732.0 / 5247.0 * k3u + 49.0 / 176.0 * k4u - 5103.0 / 18656.0 * k5u), k6u, m);
+
+    var s: DopriStep;
+    s.u = u + h * (35.0 / 384.0 * k1u + 500.0 / 1113.0 * k3u + 125.0 / 192.0 * k4u - 2187.0 / 6784.0 * k5u + 11.0 / 84.0 * k6u);
+    s.du = du + h * (35.0 / 384.0 * k1v + 500.0 / 1113.0 * k3v + 125.0 / 192.0 * k4v - 2187.0 / 6784.0 * k5v + 11.0 / 84.0 * k6v);
+    let k7u = s.du;
+    let k7v = accel(s.u, s.du, m);
+    let eu = h * (71.0 / 57600.0 * k1u - 71.0 / 16695.0 * k3u + 71.0 / 1920.0 * k4u - 17253.0 / 339200.0 * k5u + 22.0 / 525.0 * k6u - 1.0 / 40.0 * k7u);
+    let ev = h * (71.0 / 57600.0 * k1v - 71.0 / 16695.0 * k3v + 71.0 / 1920.0 * k4v - 17253.0 / 339200.0 * k5v + 22.0 / 525.0 * k6v - 1.0 / 40.0 * k7v);
+    s.err = max(length(eu), length(ev)) / (1.0 + max(length(u), length(du)));
+    return s;
+}
+
+// Covers dt in substeps sized to keep each one's error below the tolerance. The last
+// allowed substep takes whatever is left, so the GPU time stays bounded
+fn step_adaptive(p: Particle, dt: f32) -> Particle {
+    var q = p;
+    var remaining = dt;
+    var h = dt;
+    for (var i: u32 = 0u; i < params.max_substeps && remaining > 0.0; i++) {
+        let last = i + 1u == params.max_substeps;
+        h = select(min(h, remaining), remaining, last);
+        let s = dopri_step(q.u, q.du, q.mass, h);
+        if (s.err <= params.tolerance || last) {
+            q.u = s.u;
+            q.du = s.du;
+            remaining -= h;
+        }
+        h *= clamp(0.9 * pow(params.tolerance / max(s.err, 1e-30), 0.2), 0.2, 5.0);
+    }
+    return q;
+}
+
+fn integrate(p: Particle, dt: f32, integrator: u32) -> Particle {
+    switch integrator {
+        case 1u: { return step_rk4(p, dt); }
+        case 2u: { return step_adaptive(p, dt); }
+        default: { return step_euler(p, dt); }
+    }
+}
+
 @compute @workgroup_size(16, 16, 1)  // PARTICLES PER GROUP: 256
 fn comp_main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     if (global_id.x >= params.w || global_id.y >= params.h) { return; }
     let globalidx = global_id.x + global_id.y * params.w; 
 
-    var p = particles[globalidx];
-    var ddu = vec2f(0.0, 0.0);
+    var integrator = params.integrator;
+    if (params.compare_integrators != 0u) {
+        integrator = select(0u, 1u, f32(global_id.x) >= params.compare_split * f32(params.w));
+    }
 
-    let d2 = params.d * params.d;
-    for (var i: u32 = 0; i < params.n; i++) {
-        let mag = params.r * dir(f32(i)*tau/f32(params.n));
-        let diff = mag-p.u;
-        let sq = sqrt(length2(diff)+d2);
-        ddu += diff / (sq*sq*sq);
+    var p = particles[globalidx];
+    // Masked out pendulums stay put and show as transparent background
+    if ((p.flags & INACTIVE) != 0u) {
+        textureStore(tex, vec2i(global_id.xy), vec4f(0.0));
+        return;
     }
-    ddu -= params.mu * p.du + params.c * p.u;
+    let was_finite = is_finite(p);
+    p = tick(integrate(p, params.dt, integrator), params.dt);
 
-    p.du += ddu * params.dt;
-    p.u += p.du * params.dt;
+    // Count entries into the d-neighbourhood of the magnets
+    let near = length2(magnet_pos(nearest_magnet(p.u)) - p.u) < params.d * params.d;
+    if (near && (p.passes & NEAR_BIT) == 0u) {
+        p.passes = (p.passes + 1u) | NEAR_BIT;
+    } else if (!near) {
+        p.passes &= ~NEAR_BIT;
+    }
 
     particles[globalidx] = p;
 
-    let a = atan2(p.u.y, p.u.x);
-    let frac = saturate((a+pi) / tau);
-    let col = colormap[u32(floor(frac * 510.0))];
+    var col = mask_alpha(scalar_color(p), p);
+    if (!DITHER_AT_DISPLAY && params.dither != 0u) {
+        col = dither8(col, global_id.xy);
+    }
+    if (!is_finite(p)) {
+        col = diverged_color();
+        if (was_finite) {
+            atomicAdd(&stats.diverged, 1u);
+        }
+    }
 
     textureStore(tex, vec2i(global_id.xy), col);
 }
 
+// Records where each pendulum starts the frame, so the activity view can show how
+// far it moved during it
+@compute @workgroup_size(16, 16, 1)
+fn mark_frame(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x >= params.w || global_id.y >= params.h) { return; }
+    let i = global_id.x + global_id.y * params.w;
+    particles[i].prev_u = particles[i].u;
+}
+
+//--------/// SEEDING ///---------//
+fn rotate(v: vec2f, a: f32) -> vec2f {
+    let c = cos(a);
+    let s = sin(a);
+    return vec2f(c * v.x - s * v.y, s * v.x + c * v.y);
+}
+
+// Mirrors `initial_velocity` in sim.rs
+fn initial_velocity(u: vec2f) -> vec2f {
+    let m = params.velocity_magnitude;
+    let a = params.velocity_angle;
+    switch params.velocity_pattern {
+        // Radial
+        case 0u: {
+            if (length(u) > 0.001) { return m * rotate(normalize(u), a); }
+            return m * dir(a);
+        }
+        // Tangential
+        case 1u: {
+            if (length(u) > 0.001) { return m * rotate(normalize(vec2f(-u.y, u.x)), a); }
+            return m * dir(a + pi / 2.0);
+        }
+        // Uniform
+        case 2u: { return m * dir(a); }
+        default: { return vec2f(0.0, 0.0); }
+    }
+}
+
+// World position of the pendulum at fraction `f` of the grid, for a canvas `scale`
+// world units wide before zooming, as `Params::seed_position`
+fn seed_position(f: vec2f, scale: f32) -> vec2f {
+    let center = vec2f(params.center_x, params.center_y);
+    let span = scale / params.zoom;
+    if (params.seed_coords == 1u) {
+        return center + f.y * 0.5 * span * dir(f.x * tau);
+    }
+    return center + (f - 0.5) * span;
+}
+
+// Mirrors `Params::mass_at`
+fn mass_at(u: vec2f, scale: f32) -> f32 {
+    return mix(params.mass_min, params.mass_max, min(length(u) / (0.5 * scale), 1.0));
+}
+
+// The pendulum at fraction `f` of the grid, as `Particle::new` seeds it
+fn seed_particle(f: vec2f, scale: f32) -> Particle {
+    var p: Particle;
+    p.u = seed_position(f, scale);
+    p.du = initial_velocity(p.u);
+    p.anchor = p.u;
+    p.drift = length(p.du);
+    p.prev_u = p.u;
+    p.mass = mass_at(p.u, scale);
+    return p;
+}
+
+// Seeds the grid like `GPUSim::create_particles` without position jitter
+@compute @workgroup_size(16, 16, 1)
+fn seed_main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x >= params.w || global_id.y >= params.h) { return; }
+    let size = vec2f(f32(params.w), f32(params.h));
+    particles[global_id.x + global_id.y * params.w] = seed_particle(vec2f(global_id.xy) / size, params.scale);
+}
+
+//--------/// REDUCTION ///---------//
+// Bins each pendulum by its nearest magnet and its number of near passes
+@compute @workgroup_size(16, 16, 1)
+fn orbit_histogram(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x >= params.w || global_id.y >= params.h) { return; }
+    let p = particles[global_id.x + global_id.y * params.w];
+    if (!is_finite(p) || (p.flags & INACTIVE) != 0u) { return; }
+    let bin = min(p.passes & ~NEAR_BIT, ORBIT_BINS - 1u);
+    atomicAdd(&stats.orbit_hist[nearest_magnet(p.u) * ORBIT_BINS + bin], 1u);
+}
+
+const REDUCE_MAX: u32 = 0u;
+const REDUCE_MIN: u32 = 1u;
+const REDUCE_SUM: u32 = 2u;
+
+var<workgroup> partial: array<f32, 256>;
+
+// Combines `value` over the 16x16 workgroup with `op`. Every invocation must call
+// it, and every one gets the result
+fn reduce_workgroup(lid: u32, value: f32, op: u32) -> f32 {
+    partial[lid] = value;
+    workgroupBarrier();
+    for (var stride: u32 = 128u; stride > 0u; stride >>= 1u) {
+        if (lid < stride) {
+            let a = partial[lid];
+            let b = partial[lid + stride];
+            switch op {
+                case REDUCE_MIN: { partial[lid] = min(a, b); }
+                case REDUCE_SUM: { partial[lid] = a + b; }
+                default: { partial[lid] = max(a, b); }
+            }
+        }
+        workgroupBarrier();
+    }
+    let result = partial[0];
+    // Before a following reduction overwrites it
+    workgroupBarrier();
+    return result;
+}
+
+// Fastest settle speed of the active, finite pendulums. A diverged one would make it
+// infinite or NaN for good, so the run could never count as settled
+@compute @workgroup_size(16, 16, 1)
+fn reduce_max_speed(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+) {
+    var speed = 0.0;
+    if (global_id.x < params.w && global_id.y < params.h) {
+        let p = particles[global_id.x + global_id.y * params.w];
+        if ((p.flags & INACTIVE) == 0u && is_finite(p)) {
+            speed = settle_speed(p);
+        }
+    }
+    let max_speed = reduce_workgroup(lid, speed, REDUCE_MAX);
+    if (lid == 0u) {
+        atomicMax(&stats.max_speed, bitcast<u32>(max_speed));
+    }
+}
+
+// Range of the color scalar over the active, finite pendulums, for auto-ranging the
+// colormap. A diverged one would stretch it to NaN or infinity
+@compute @workgroup_size(16, 16, 1)
+fn reduce_scalar_range(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+) {
+    let inside = global_id.x < params.w && global_id.y < params.h;
+    var lo = 3.4e38;
+    var hi = 0.0;
+    if (inside) {
+        let p = particles[global_id.x + global_id.y * params.w];
+        if ((p.flags & INACTIVE) == 0u && is_finite(p)) {
+            lo = color_scalar(p);
+            hi = lo;
+        }
+    }
+    let min_scalar = reduce_workgroup(lid, lo, REDUCE_MIN);
+    let max_scalar = reduce_workgroup(lid, hi, REDUCE_MAX);
+    if (lid == 0u) {
+        atomicMin(&stats.scalar_min, bitcast<u32>(min_scalar));
+        atomicMax(&stats.scalar_max, bitcast<u32>(max_scalar));
+    }
+}
+
+// Kinetic plus potential energy of the active, finite pendulums, one sum per
+// workgroup. Floats have no atomic add, so the CPU adds the workgroups up
+@compute @workgroup_size(16, 16, 1)
+fn reduce_energy(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    var energy = 0.0;
+    if (global_id.x < params.w && global_id.y < params.h) {
+        let p = particles[global_id.x + global_id.y * params.w];
+        if ((p.flags & INACTIVE) == 0u && is_finite(p)) {
+            energy = 0.5 * p.mass * length2(p.du) + potential(p.u);
+        }
+    }
+    let total = reduce_workgroup(lid, energy, REDUCE_SUM);
+    if (lid == 0u) {
+        stats.energy[group.x + group.y * groups.x] = total;
+    }
+}
+
+//--------/// TRACERS ///---------//
+struct TracerInfo {
+    count: u32,
+    trail_len: u32,
+    head: u32, // trail slot written by the latest step
+    scale: f32, // world units spanned by the canvas at the current zoom
+    steps: u32, // steps per frame, each appending one position
+    center: vec2f, // world position at the middle of the canvas
+}
+
+@group(0) @binding(5)
+var<storage, read_write> tracers: array<Particle>;
+
+// One ring of `trail_len` positions per tracer
+@group(0) @binding(6)
+var<storage, read_write> trail: array<vec2f>;
+
+@group(0) @binding(7)
+var<uniform> tracer_info: TracerInfo;
+
+@compute @workgroup_size(64)
+fn trace_main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let k = global_id.x;
+    if (k >= tracer_info.count) { return; }
+
+    // `head` already points at the slot of this frame's last step
+    var p = tracers[k];
+    for (var s: u32 = 0u; s < tracer_info.steps; s++) {
+        p = integrate(p, params.dt, params.integrator);
+        let back = (tracer_info.steps - 1u - s) % tracer_info.trail_len;
+        let slot = (tracer_info.head + tracer_info.trail_len - back) % tracer_info.trail_len;
+        trail[k * tracer_info.trail_len + slot] = p.u;
+    }
+    tracers[k] = p;
+}
+
 //--------/// VERTEX ///---------//
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
 }
 
+// Sub-rectangle of the texture that the quad shows
+struct TexWindow {
+    offset: vec2f,
+    scale: vec2f,
+}
+
+@group(0) @binding(3)
+var<uniform> tex_window: TexWindow;
+
 @vertex
 fn vs_main(
     @builtin(vertex_index) vid: u32,
@@ -75,7 +689,7 @@ fn vs_main(
     @location(1) tex_pos: vec2f,
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.tex_coords = tex_pos;
+    out.tex_coords = tex_window.offset + tex_pos * tex_window.scale;
     out.clip_position = vec4f(clip_pos, 0.0, 1.0);
     return out;
 }
@@ -85,8 +699,169 @@ fn vs_main(
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
+@group(0) @binding(2)
+var<uniform> view_params: Params;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let col = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    // egui renders into a non-sRGB target, so this output is quantized as-is
+    if (DITHER_AT_DISPLAY && (view_params.dither != 0u || view_params.temporal_dither != 0u)) {
+        return dither8(col, vec2u(in.clip_position.xy) + dither_shift(view_params));
+    }
+    return col;
+}
+
+
+//--------/// SURFACE ///---------//
+// The angle field drawn as a height field over a grid mesh. egui's render pass has no
+// depth buffer, so cells are drawn back to front: `order` says which way to walk the
+// index buffer's rows and columns for the current camera.
+struct Surface {
+    view_proj: mat4x4f,
+    height_scale: f32,
+    grid: u32, // vertices per side
+    order: u32, // bit 0 flips x, bit 1 flips y, bit 2 swaps the row and column axes
+    w: u32,
+    h: u32,
+}
+
+// Vertex stages can't bind read_write storage, so the particles get a read-only alias
+@group(0) @binding(1)
+var<storage, read> particles_ro: array<Particle>;
+@group(0) @binding(10)
+var<uniform> surface: Surface;
+@group(0) @binding(11)
+var surface_tex: texture_2d<f32>;
+@group(0) @binding(12)
+var surface_sampler: sampler;
+
+struct SurfaceOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2f,
+}
+
+@vertex
+fn vs_surface(@builtin(vertex_index) vid: u32) -> SurfaceOutput {
+    let last = surface.grid - 1u;
+    var ij = vec2u(vid % surface.grid, vid / surface.grid);
+    if ((surface.order & 4u) != 0u) { ij = ij.yx; }
+    if ((surface.order & 1u) != 0u) { ij.x = last - ij.x; }
+    if ((surface.order & 2u) != 0u) { ij.y = last - ij.y; }
+    let uv = vec2f(ij) / f32(last);
+
+    let px = min(vec2u(uv * vec2f(f32(surface.w), f32(surface.h))), vec2u(surface.w - 1u, surface.h - 1u));
+    let p = particles_ro[px.x + px.y * surface.w];
+    // The scalar the colors show, so the heights follow the colormap
+    let height = colormap_t(p) * surface.height_scale;
+
+    var out: SurfaceOutput;
+    // Image bottom (v = 0) faces the camera at yaw 0
+    out.clip_position = surface.view_proj * vec4f(uv.x * 2.0 - 1.0, height, 1.0 - uv.y * 2.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@fragment
+fn fs_surface(in: SurfaceOutput) -> @location(0) vec4<f32> {
+    return textureSample(surface_tex, surface_sampler, in.tex_coords);
+}
+
+//--------/// TRAILS ///---------//
+// Vertex stages can't bind read_write storage, so the trails get a read-only alias
+@group(0) @binding(6)
+var<storage, read> trail_ro: array<vec2f>;
+
+struct TrailOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) age: f32, // 0 at the oldest slot, 1 at the newest
+}
+
+// One line strip instance per tracer, running from the oldest position to the newest
+@vertex
+fn vs_trail(@builtin(vertex_index) vid: u32, @builtin(instance_index) k: u32) -> TrailOutput {
+    let slot = (tracer_info.head + 1u + vid) % tracer_info.trail_len;
+    let u = trail_ro[k * tracer_info.trail_len + slot];
+    var out: TrailOutput;
+    // Same mapping as the particle grid: the canvas spans scale around the centre
+    out.clip_position = vec4f(2.0 * (u - tracer_info.center) / tracer_info.scale, 0.0, 1.0);
+    out.age = f32(vid + 1u) / f32(tracer_info.trail_len);
+    return out;
+}
+
+@fragment
+fn fs_trail(in: TrailOutput) -> @location(0) vec4<f32> {
+    // Premultiplied white, fading out towards the tail
+    let a = 0.8 * in.age;
+    return vec4f(a, a, a, a);
+}
+
+//--------/// FRAGMENT-ONLY FALLBACK ///---------//
+// For backends without compute shaders (WebGL2). No state is kept between frames:
+// every pixel integrates its pendulum from the start for `steps` steps.
+struct FallbackInfo {
+    steps: u32,
+    scale: f32, // world units spanned by the canvas
+}
+
+@group(0) @binding(8)
+var colormap_tex: texture_2d<f32>; // one row per colormap
+
+@group(0) @binding(9)
+var<uniform> fallback_info: FallbackInfo;
+
+struct FallbackOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2f, // (0, 0) at the bottom left, like texture v = 0 in vs_main
+}
+
+// Quad over the whole viewport, drawn as a 4-vertex triangle strip
+@vertex
+fn vs_fallback(@builtin(vertex_index) vid: u32) -> FallbackOutput {
+    let uv = vec2f(f32(vid & 1u), f32(vid >> 1u));
+    var out: FallbackOutput;
+    out.clip_position = vec4f(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Same interpolation as sample_map, but read from a texture
+fn fallback_map(map: u32, t: f32) -> vec4f {
+    let last = COLORMAP_LEN - 1u;
+    let x = saturate(t) * f32(last);
+    let i = min(u32(x), last - 1u);
+    let lo = textureLoad(colormap_tex, vec2u(i, map), 0);
+    let hi = textureLoad(colormap_tex, vec2u(i + 1u, map), 0);
+    return mix(lo, hi, x - f32(i));
+}
+
+@fragment
+fn fs_fallback(in: FallbackOutput) -> @location(0) vec4<f32> {
+    // Start from the same grid point the compute path uses (without position jitter)
+    let size = vec2f(f32(params.w), f32(params.h));
+    let cell = min(floor(in.uv * size), size - 1.0);
+    var p = seed_particle(cell / size, fallback_info.scale);
+
+    var integrator = params.integrator;
+    if (params.compare_integrators != 0u) {
+        integrator = select(0u, 1u, cell.x >= params.compare_split * size.x);
+    }
+    for (var i: u32 = 0u; i < fallback_info.steps; i++) {
+        // No earlier frame is kept here, so activity covers the last step
+        if (i + 1u == fallback_info.steps) {
+            p.prev_u = p.u;
+        }
+        p = tick(integrate(p, params.dt, integrator), params.dt);
+    }
+
+    let t = colormap_t(p);
+    var mapped = mix(fallback_map(0u, t), fallback_map(1u, t), secondary_weight(t));
+    if (params.color_mode == 4u) {
+        mapped = magnets[nearest_magnet(p.u)].color;
+    }
+    let col = select(diverged_color(), mask_alpha(mapped, p), is_finite(p));
+    if (params.dither != 0u || params.temporal_dither != 0u) {
+        return dither8(col, vec2u(in.clip_position.xy) + dither_shift(params));
+    }
+    return col;
 }
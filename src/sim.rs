@@ -1,23 +1,120 @@
-use std::{f32::consts::PI, num::NonZeroU64};
+use std::{
+    f32::consts::PI,
+    fmt,
+    num::NonZeroU64,
+    ops::RangeInclusive,
+    path::Path,
+    sync::{atomic::{AtomicU8, Ordering}, mpsc, Arc},
+};
 
 use bytemuck::{Pod, Zeroable};
 use eframe::egui::*;
 use eframe::egui_wgpu::ScreenDescriptor;
 use eframe::wgpu;
-use glam::{vec2, Vec2};
-use wgpu::{include_wgsl, util::DeviceExt, TextureFormat};
+use glam::{vec2, vec3, Mat4, Vec2};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wgpu::{util::DeviceExt, TextureFormat};
 
-use crate::twilight::TWILIGHT_MAP;
+use crate::colormap::{self, Colormap, COLORMAP_LEN};
+use crate::export::{encode_png, ExportError, ImageMetadata};
+use crate::fallback::FallbackResources;
 
-// wgpu requires the structures to be padded to 16 bytes (4 floats)
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
-pub(crate) struct Particle {
+pub struct Particle {
     u: Vec2,
     du: Vec2,
+    passes: u32, // entries within d of a magnet, with NEAR_BIT set while inside
+    flags: u32,  // INACTIVE for pendulums left out by a mask
+    age: f32,    // simulated time since the start
+    settled_at: f32, // age when the pendulum last moved faster than SETTLED_SPEED
+    anchor: Vec2,    // position at the start of the current drift window
+    anchor_age: f32, // and the age then
+    drift: f32,      // net displacement over the last complete window per unit time
+    prev_u: Vec2,    // position at the start of the last frame, for the activity view
+    mass: f32,       // inertia the forces are divided by
+    _pad: f32,       // the shader rounds the struct up to a multiple of 8 bytes
 }
 
-#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+const INACTIVE: u32 = 1;
+const SETTLED_SPEED: f32 = 0.01; // the shader's, see `tick`
+
+impl Particle {
+    /// A pendulum of unit mass at `u` moving with velocity `du`, not yet near any magnet.
+    pub fn new(u: Vec2, du: Vec2) -> Self {
+        // Until a drift window completes, the drift is the speed
+        Self { u, du, anchor: u, drift: du.length(), prev_u: u, mass: 1.0, ..Self::zeroed() }
+    }
+
+    /// The same pendulum with mass `mass`.
+    pub fn with_mass(self, mass: f32) -> Self {
+        Self { mass, ..self }
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.u
+    }
+
+    pub fn velocity(&self) -> Vec2 {
+        self.du
+    }
+
+    pub fn mass(&self) -> f32 {
+        self.mass
+    }
+
+    fn is_active(&self) -> bool {
+        self.flags & INACTIVE == 0
+    }
+
+    fn is_finite(&self) -> bool {
+        self.u.is_finite() && self.du.is_finite()
+    }
+}
+
+/// Basin of pixels that have none in [`GPUSim::compute_basins`].
+pub const NO_BASIN: u32 = u32::MAX;
+
+/// Histogram bins of [`GPUSim::orbit_histogram`].
+pub const ORBIT_BINS: usize = 16;
+/// Most magnets the shader's magnet table holds.
+pub const MAX_MAGNETS: usize = 64;
+
+/// A magnet as the shader's magnet table holds it.
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct Magnet {
+    pub position: Vec2,
+    pub strength: f32, // multiplies the magnet's force
+    _pad: f32,         // the shader aligns color to 16 bytes
+    pub color: [f32; 4], // RGBA of the pixels that settle over it, in the magnet color mode
+}
+
+impl Magnet {
+    pub fn new(position: Vec2, strength: f32, color: [f32; 4]) -> Self {
+        Self { position, strength, _pad: 0.0, color }
+    }
+}
+
+/// Index of the magnet in `magnets` closest to `u`, as the shader's `nearest_magnet`.
+/// `magnets` is the table the shader reads, see [`GPUSim::magnets`].
+pub fn nearest_magnet(magnets: &[Magnet], u: Vec2) -> u32 {
+    (0..magnets.len() as u32)
+        .min_by(|&i, &j| {
+            let di = (magnets[i as usize].position - u).length_squared();
+            di.total_cmp(&(magnets[j as usize].position - u).length_squared())
+        })
+        .unwrap_or(0)
+}
+
+// The fragment-only path reads the magnet table from its params uniform buffer,
+// following the params at an offset any device accepts for a second binding. The
+// compute path has a storage buffer for it
+pub(crate) const MAGNET_OFFSET: u64 = (std::mem::size_of::<Params>() as u64).next_multiple_of(256);
+const MAGNET_TABLE_SIZE: u64 = (MAX_MAGNETS * std::mem::size_of::<Magnet>()) as u64;
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Params {
     pub n: u32,
@@ -26,14 +123,471 @@ pub struct Params {
     pub mu: f32, // coefficient of friction -> controls the complexity of the fractal (lower = more fancy)
     pub c: f32,
     pub dt: f32,
-    pub w: u32,
+    #[serde(skip)]
+    pub w: u32, // grid size, owned by the running sim rather than the parameter set
+    #[serde(skip)]
     pub h: u32,
     pub velocity_magnitude: f32, // magnitude of initial velocity
     pub velocity_angle: f32,     // angle offset for velocity direction (in radians)
     pub velocity_pattern: u32,   // 0=radial, 1=tangential, 2=uniform, 3=zero
-    _padding: u32,               // padding to maintain 16-byte alignment
+    pub dither: u32,             // 1 = ordered dither before 8-bit quantization, 0 = off
+    pub integrator: u32,         // 0=semi-implicit Euler, 1=RK4, 2=adaptive Dormand–Prince
+    pub compare_integrators: u32, // 1 = Euler left of `compare_split`, RK4 right of it
+    pub compare_split: f32,      // divider position as a fraction of the width
+    pub cutoff: f32,             // magnets further than this are ignored, 0 = no cutoff
+    pub jitter_pattern: u32,     // sub-pixel start offsets: 0=none, 1=white noise, 2=Halton, 3=interleaved gradient noise
+    pub jitter_sample: u32,      // which sample of the jitter sequence to seed with
+    pub seed: u32,               // all randomness derives from this
+    pub color_mode: u32,         // scalar shown: 0=final angle, 1=speed, 2=settling time, 3=activity, 4=nearest magnet's color
+    pub color_min: f32,          // scalar at the start of the colormap
+    pub color_max: f32,          // scalar at the end of the colormap
+    #[serde(default)]
+    pub invert_colormap: u32,    // 1 = colormap runs from its end to its start
+    #[serde(default)]
+    pub highlight_diverged: u32, // 1 = diverged pendulums drawn magenta, 0 = transparent
+    #[serde(default)]
+    pub banding: u32,            // colormap quantized to this many flat bands, below 2 = smooth
+    #[serde(default)]
+    pub spring_cubic: f32,       // spring force is c * (|u| + spring_cubic * |u|³), 0 = linear
+    #[serde(default)]
+    pub compose_colormaps: u32,  // 1 = secondary_colormap takes over above colormap_split
+    #[serde(default)]
+    pub secondary_colormap: u32, // index into Colormap::ALL
+    #[serde(default)]
+    pub colormap_split: f32,     // colormap position where the secondary map starts
+    #[serde(default)]
+    pub colormap_blend: f32,     // width of the crossfade around the split, 0 = hard edge
+    #[serde(default)]
+    pub anisotropic_spring: u32, // 1 = spring constants cx along x and cy along y, 0 = c for both
+    #[serde(default)]
+    pub cx: f32,
+    #[serde(default)]
+    pub cy: f32,
+    #[serde(default = "default_softness")]
+    pub softness: f32,           // magnet force falls off as 1 / (dist² + d²)^(softness / 2)
+    #[serde(default)]
+    pub convergence_metric: u32, // settledness measured by 0=speed, 1=drift over settle_window steps
+    #[serde(default = "default_settle_window")]
+    pub settle_window: u32,      // steps the drift is measured over
+    #[serde(default)]
+    pub temporal_dither: u32,    // 1 = the displayed dither pattern shifts every frame, exports keep it fixed
+    #[serde(default)]
+    pub seed_coords: u32,        // grid laid out as 0=Cartesian x and y, 1=polar angle and radius
+    #[serde(default = "default_mass")]
+    pub mass_min: f32,           // mass at the centre, or where a mass field is transparent
+    #[serde(default = "default_mass")]
+    pub mass_max: f32,           // mass at the edge of the view, or where a mass field is opaque
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f32,          // local error per adaptive substep, relative to the state's size
+    #[serde(default = "default_max_substeps")]
+    pub max_substeps: u32,       // adaptive substeps per dt at most, the last one taking what's left
+    #[serde(default)]
+    pub alpha_mask: u32,         // 1 = alpha is opaque where settled and transparent where still moving
+    #[serde(default)]
+    pub colormap: u32,           // primary map, index into Colormap::ALL
+    #[serde(default)]
+    pub center_x: f32,           // world position seeded at the middle of the canvas
+    #[serde(default)]
+    pub center_y: f32,
+    #[serde(default = "default_zoom")]
+    pub zoom: f32,               // magnification of the seeded region, 1 = `scale` world units wide
+    #[serde(skip)]
+    pub frame: u32,              // frames drawn, wrapping; set by the sim before each upload
+    #[serde(skip)]
+    pub scale: f32,              // world units the canvas spans; also set by the sim
+}
+
+fn default_tolerance() -> f32 {
+    1e-4
+}
+
+fn default_max_substeps() -> u32 {
+    8
 }
+
+fn default_mass() -> f32 {
+    1.0
+}
+
+fn default_zoom() -> f32 {
+    1.0
+}
+
+fn default_softness() -> f32 {
+    3.0 // the inverse square law of a point charge, softened by d
+}
+
+fn default_settle_window() -> u32 {
+    32
+}
+/// Below this friction coefficient energy is (nearly) conserved, so pendulums never
+/// settle and only a step budget can end a run.
+pub const CONSERVATIVE_MU: f32 = 1e-4;
+/// Smallest softening distance `d` that `Params::sanitize` lets through. At d = 0
+/// the magnet force is singular and the integration blows up.
+pub const MIN_D: f32 = 1e-3;
+/// Longest drift window, in steps, that `Params::sanitize` lets through.
+pub const MAX_SETTLE_WINDOW: u32 = 4096;
+/// Largest softness exponent that `Params::sanitize` lets through. Even at d = MIN_D the
+/// force stays far from f32 overflow.
+pub const MAX_SOFTNESS: f32 = 6.0;
+/// Tightest adaptive step tolerance that `Params::sanitize` lets through, a few f32
+/// epsilons; below it rounding error alone fails the error test.
+pub const MIN_TOLERANCE: f32 = 1e-6;
+/// Most adaptive substeps per step that `Params::sanitize` lets through, which bounds
+/// the GPU time of a dispatch at about MAX_SUBSTEPS RK4 steps.
+pub const MAX_SUBSTEPS: u32 = 64;
+/// Smallest pendulum mass that `Params::sanitize` lets through. The forces are
+/// divided by it, so a lighter pendulum needs a proportionally shorter time step.
+pub const MIN_MASS: f32 = 1e-2;
+/// Below this `d` the force near a magnet is steep enough that results depend on
+/// the time step.
+pub const UNSTABLE_D: f32 = 0.05;
+/// Zoom range that `Params::sanitize` lets through. At MAX_ZOOM neighbouring
+/// pendulums are still a few f32 epsilons apart near the magnets.
+pub const MIN_ZOOM: f32 = 0.1;
+pub const MAX_ZOOM: f32 = 1e4;
+
+/// Ranges of the core parameter sliders, which imported presets are clamped into.
+pub const N_RANGE: RangeInclusive<u32> = 3..=10;
+pub const R_RANGE: RangeInclusive<f32> = 1.0..=10.0;
+pub const D_RANGE: RangeInclusive<f32> = 0.1..=2.0;
+pub const MU_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+pub const C_RANGE: RangeInclusive<f32> = 0.0..=1.0; // also cx and cy
+pub const DT_RANGE: RangeInclusive<f32> = 0.001..=0.05;
+pub const VELOCITY_RANGE: RangeInclusive<f32> = 0.0..=10.0;
+
 impl Params {
+    pub fn is_conservative(&self) -> bool {
+        self.mu < CONSERVATIVE_MU
+    }
+
+    /// The scalar `params.color_mode` shows for a particle, as in the shader's
+    /// `color_scalar`.
+    pub fn color_scalar(&self, p: &Particle) -> f32 {
+        match self.color_mode {
+            1 => p.du.length(),
+            2 => p.settled_at,
+            3 => (p.u - p.prev_u).length(),
+            _ => ((p.u.y.atan2(p.u.x) + PI) / (2.0 * PI)).clamp(0.0, 1.0),
+        }
+    }
+
+    /// How fast a particle still moves by the measure `params.convergence_metric`
+    /// selects, as the shader's `settle_speed`: its speed, or its net displacement
+    /// over the last `settle_window` steps divided by their duration. The latter
+    /// ignores fast oscillation in place, and both compare against the same thresholds.
+    pub fn settle_speed(&self, p: &Particle) -> f32 {
+        if self.convergence_metric == 1 {
+            p.drift
+        } else {
+            p.du.length()
+        }
+    }
+
+    /// Kinetic plus potential energy of a particle among `magnets` (see
+    /// [`GPUSim::magnets`]), as summed by the shader's `reduce_energy`. The potential's
+    /// negative gradient is the magnet and spring force, except for a spring that is
+    /// both anisotropic and cubic, which has no potential.
+    pub fn energy(&self, p: &Particle, magnets: &[Magnet]) -> f32 {
+        let d2 = self.d * self.d;
+        let cutoff2 = self.cutoff * self.cutoff;
+        let s = self.softness;
+        let magnets: f32 = magnets
+            .iter()
+            .map(|magnet| (magnet.position - p.u, magnet.strength))
+            .filter(|(diff, _)| cutoff2 <= 0.0 || diff.length_squared() <= cutoff2)
+            .map(|(diff, strength)| {
+                let g = diff.length_squared() + d2;
+                if (s - 2.0).abs() < 1e-4 {
+                    strength * 0.5 * g.ln()
+                } else {
+                    -strength * g.powf(1.0 - 0.5 * s) / (s - 2.0)
+                }
+            })
+            .sum();
+        let spring = 0.5 * self.spring_constants().dot(p.u * p.u) * (1.0 + 0.5 * self.spring_cubic * p.u.length_squared());
+        0.5 * p.mass * p.du.length_squared() + magnets + spring
+    }
+
+    /// World position a pendulum starts from, given its place in the grid as fractions
+    /// of the width and height, bottom row first, for a canvas `scale` world units
+    /// wide before zooming. In polar coordinates columns are angles counterclockwise
+    /// from +x and rows radii from the view centre out to the middle of the canvas edge.
+    pub fn seed_position(&self, cell: Vec2, scale: f32) -> Vec2 {
+        let span = self.view_span(scale);
+        let offset = if self.seed_coords == 1 {
+            cell.y * 0.5 * span * Vec2::from_angle(cell.x * 2.0 * PI)
+        } else {
+            (cell - Vec2::splat(0.5)) * span
+        };
+        self.view_center() + offset
+    }
+
+    /// Where in the grid a world position is seeded, the inverse of
+    /// [`Self::seed_position`].
+    pub fn seed_cell(&self, u: Vec2, scale: f32) -> Vec2 {
+        let (u, span) = (u - self.view_center(), self.view_span(scale));
+        if self.seed_coords == 1 {
+            vec2(u.y.atan2(u.x).rem_euclid(2.0 * PI) / (2.0 * PI), 2.0 * u.length() / span)
+        } else {
+            u / span + Vec2::splat(0.5)
+        }
+    }
+
+    /// World position seeded at the middle of the canvas.
+    pub fn view_center(&self) -> Vec2 {
+        vec2(self.center_x, self.center_y)
+    }
+
+    /// World units the canvas spans at the current zoom, for `scale` at zoom 1.
+    pub fn view_span(&self, scale: f32) -> f32 {
+        scale / self.zoom
+    }
+
+    /// Mass of a pendulum seeded at `u`, as the fallback shader's `mass_at`: from
+    /// `mass_min` at the centre to `mass_max` at the middle of the canvas edge, for a
+    /// canvas `scale` world units wide, and `mass_max` beyond.
+    pub fn mass_at(&self, u: Vec2, scale: f32) -> f32 {
+        let t = (u.length() / (0.5 * scale)).min(1.0);
+        self.mass_min + (self.mass_max - self.mass_min) * t
+    }
+
+    /// Mass at weight `t` in [0, 1] of a mass field, see [`GPUSim::set_mass_field`].
+    pub fn mass_from_weight(&self, t: f32) -> f32 {
+        self.mass_min + (self.mass_max - self.mass_min) * t.clamp(0.0, 1.0)
+    }
+
+    /// Position of magnet `i`, as placed by the shader's `accel`.
+    pub fn magnet_position(&self, i: u32) -> Vec2 {
+        self.r * Vec2::from_angle(i as f32 * 2.0 * PI / self.n as f32)
+    }
+
+    /// Spring constants along x and y.
+    pub fn spring_constants(&self) -> Vec2 {
+        if self.anisotropic_spring != 0 {
+            vec2(self.cx, self.cy)
+        } else {
+            Vec2::splat(self.c)
+        }
+    }
+
+    /// Color of the pixels whose pendulums come to rest over magnet `i`, as RGBA in
+    /// [0, 1] encoded like the output texture.
+    pub fn magnet_color(&self, i: u32) -> [f32; 4] {
+        self.rest_color(self.magnet_position(i))
+    }
+
+    /// The ring of `n` magnets at radius `r`, each with unit strength and the color
+    /// its basin has in the final angle mode. The shader uses these unless
+    /// [`GPUSim::set_magnets`] replaced them.
+    pub fn ring_magnets(&self) -> Vec<Magnet> {
+        let angles = Params { color_mode: 0, ..*self };
+        (0..self.n).map(|i| Magnet::new(self.magnet_position(i), 1.0, angles.magnet_color(i))).collect()
+    }
+
+    /// Color of the pixels whose pendulums come to rest at `u`, as [`Self::magnet_color`]
+    /// for a magnet there. The magnet color mode shows the ring's colors.
+    pub fn rest_color(&self, u: Vec2) -> [f32; 4] {
+        if self.color_mode == 4 {
+            return Params { color_mode: 0, ..*self }.rest_color(u);
+        }
+        let at_rest = Particle { u, ..Particle::zeroed() };
+        let angle = Params { color_mode: 0, ..*self }.color_scalar(&at_rest);
+        // Only the angle mode tells basins apart; other modes use the whole map for it
+        let t = if self.color_mode == 0 {
+            (angle - self.color_min) / (self.color_max - self.color_min).max(1e-6)
+        } else {
+            angle
+        };
+        let t = self.band(t.clamp(0.0, 1.0));
+        let t = if self.invert_colormap != 0 { 1.0 - t } else { t };
+        let primary = colormap::sample(&Colormap::from_index(self.colormap).table(), t);
+        if self.compose_colormaps == 0 {
+            return primary;
+        }
+        let secondary = colormap::sample(&Colormap::from_index(self.secondary_colormap).table(), t);
+        let w = self.secondary_weight(t);
+        std::array::from_fn(|c| primary[c] * (1.0 - w) + secondary[c] * w)
+    }
+
+    // How much of the secondary colormap shows at t, as the shader's `secondary_weight`
+    fn secondary_weight(&self, t: f32) -> f32 {
+        let half = self.colormap_blend * 0.5;
+        if half <= 0.0 {
+            return if t >= self.colormap_split { 1.0 } else { 0.0 };
+        }
+        let x = ((t - self.colormap_split + half) / (2.0 * half)).clamp(0.0, 1.0);
+        x * x * (3.0 - 2.0 * x)
+    }
+
+    // Snaps t in [0, 1] to the start of its band, as the shader's `colormap_t`
+    fn band(&self, t: f32) -> f32 {
+        if self.banding < 2 {
+            return t;
+        }
+        let bands = self.banding as f32;
+        (t * bands).floor().min(bands - 1.0) / (bands - 1.0)
+    }
+
+    /// Rust statements assigning each saved field to `target`, such as
+    /// `self.sim.params`, for pasting into a preset. The grid size is left out like
+    /// in saved configs.
+    pub fn to_rust(&self, target: &str) -> String {
+        // Params serialize to a flat object of numbers in declaration order, and
+        // sanitized ones have no NaNs, which would turn into nulls
+        let mut params = *self;
+        params.sanitize();
+        let json = serde_json::to_string(&params).expect("params serialize");
+        json.trim_start_matches('{')
+            .trim_end_matches('}')
+            .split(',')
+            .filter_map(|field| field.split_once(':'))
+            .map(|(name, value)| format!("{target}.{} = {value};\n", name.trim_matches('"')))
+            .collect()
+    }
+
+    /// The params a fraction `t` of the way to `other`, for animating between
+    /// parameter sets. Floats are interpolated linearly, while counts and switches
+    /// such as `n` or `integrator` jump halfway. The grid size stays this one's.
+    pub fn lerp(&self, other: &Params, t: f32) -> Params {
+        let (Ok(Value::Object(from)), Ok(Value::Object(to))) = (serde_json::to_value(self), serde_json::to_value(other)) else {
+            return *self;
+        };
+        let blended = from
+            .into_iter()
+            .map(|(name, a)| {
+                let b = to.get(&name).cloned().unwrap_or(Value::Null);
+                let value = match (a.as_f64(), b.as_f64()) {
+                    (Some(x), Some(y)) if a.is_f64() => (x + (y - x) * t as f64).into(),
+                    _ if t < 0.5 => a,
+                    _ => b,
+                };
+                (name, value)
+            })
+            .collect();
+        serde_json::from_value(Value::Object(blended))
+            .map(|params| Params { w: self.w, h: self.h, frame: self.frame, scale: self.scale, ..params })
+            .unwrap_or(*self)
+    }
+
+    /// Default colormap range for a color mode: the whole circle of angles, speeds up
+    /// to the initial speed, settling times up to a typical run, or the distance
+    /// covered in a frame at the initial speed.
+    pub fn default_color_range(&self) -> (f32, f32) {
+        match self.color_mode {
+            1 => (0.0, self.velocity_magnitude.max(1.0)),
+            2 => (0.0, 20.0),
+            3 => (0.0, self.velocity_magnitude.max(1.0) * self.dt),
+            _ => (0.0, 1.0),
+        }
+    }
+
+    /// Replaces non-finite values with their defaults and clamps everything else into
+    /// range, logging a warning for each fix. Run where params are set or loaded; a NaN
+    /// in the uniform buffer would turn the whole frame to garbage.
+    pub fn sanitize(&mut self) {
+        self.fix_ranges(true);
+    }
+
+    // The same fixes without the warnings, for the copy made before every upload. The
+    // stored params were already sanitized, with warnings, where they were set, so
+    // anything left to fix here came from a per-step hook and would warn every frame
+    pub(crate) fn sanitize_quietly(&mut self) {
+        self.fix_ranges(false);
+    }
+
+    fn fix_ranges(&mut self, warn: bool) {
+        let defaults = Self::default(self.w, self.h);
+        let floats = [
+            ("r", &mut self.r, defaults.r, 0.0, f32::MAX),
+            ("d", &mut self.d, defaults.d, MIN_D, f32::MAX),
+            ("mu", &mut self.mu, defaults.mu, 0.0, f32::MAX),
+            ("c", &mut self.c, defaults.c, f32::MIN, f32::MAX),
+            ("cx", &mut self.cx, defaults.cx, f32::MIN, f32::MAX),
+            ("cy", &mut self.cy, defaults.cy, f32::MIN, f32::MAX),
+            ("softness", &mut self.softness, defaults.softness, 0.0, MAX_SOFTNESS),
+            ("spring_cubic", &mut self.spring_cubic, defaults.spring_cubic, f32::MIN, f32::MAX),
+            ("dt", &mut self.dt, defaults.dt, 0.0, f32::MAX),
+            ("velocity_magnitude", &mut self.velocity_magnitude, defaults.velocity_magnitude, f32::MIN, f32::MAX),
+            ("velocity_angle", &mut self.velocity_angle, defaults.velocity_angle, f32::MIN, f32::MAX),
+            ("compare_split", &mut self.compare_split, defaults.compare_split, 0.0, 1.0),
+            ("cutoff", &mut self.cutoff, defaults.cutoff, 0.0, f32::MAX),
+            ("color_min", &mut self.color_min, defaults.color_min, f32::MIN, f32::MAX),
+            ("color_max", &mut self.color_max, defaults.color_max, f32::MIN, f32::MAX),
+            ("colormap_split", &mut self.colormap_split, defaults.colormap_split, 0.0, 1.0),
+            ("colormap_blend", &mut self.colormap_blend, defaults.colormap_blend, 0.0, 1.0),
+            ("mass_min", &mut self.mass_min, defaults.mass_min, MIN_MASS, f32::MAX),
+            ("mass_max", &mut self.mass_max, defaults.mass_max, MIN_MASS, f32::MAX),
+            ("tolerance", &mut self.tolerance, defaults.tolerance, MIN_TOLERANCE, 1.0),
+            ("center_x", &mut self.center_x, defaults.center_x, f32::MIN, f32::MAX),
+            ("center_y", &mut self.center_y, defaults.center_y, f32::MIN, f32::MAX),
+            ("zoom", &mut self.zoom, defaults.zoom, MIN_ZOOM, MAX_ZOOM),
+        ];
+        for (name, value, default, min, max) in floats {
+            if !value.is_finite() {
+                if warn {
+                    log::warn!("Param {name} is {value}, resetting it to {default}");
+                }
+                *value = default;
+            } else if !(min..=max).contains(value) {
+                if warn {
+                    log::warn!("Param {name} = {value} is out of range, clamping it to [{min}, {max}]");
+                }
+                *value = value.clamp(min, max);
+            }
+        }
+
+        // Enumerations and counts; the shader divides by n
+        let ints = [
+            ("n", &mut self.n, 1, MAX_MAGNETS as u32),
+            ("velocity_pattern", &mut self.velocity_pattern, 0, 3),
+            ("integrator", &mut self.integrator, 0, 2),
+            ("jitter_pattern", &mut self.jitter_pattern, 0, 3),
+            ("color_mode", &mut self.color_mode, 0, 4),
+            ("colormap", &mut self.colormap, 0, Colormap::ALL.len() as u32 - 1),
+            ("secondary_colormap", &mut self.secondary_colormap, 0, Colormap::ALL.len() as u32 - 1),
+            ("convergence_metric", &mut self.convergence_metric, 0, 1),
+            ("seed_coords", &mut self.seed_coords, 0, 1),
+            ("settle_window", &mut self.settle_window, 1, MAX_SETTLE_WINDOW),
+            ("max_substeps", &mut self.max_substeps, 1, MAX_SUBSTEPS),
+        ];
+        for (name, value, min, max) in ints {
+            if !(min..=max).contains(value) {
+                if warn {
+                    log::warn!("Param {name} = {value} is out of range, clamping it to [{min}, {max}]");
+                }
+                *value = (*value).clamp(min, max);
+            }
+        }
+    }
+
+    /// Clamps the core parameters into their sliders' ranges, logging a warning for
+    /// each change. Stricter than [`Self::sanitize`], which only keeps the shader safe.
+    pub fn clamp_to_slider_ranges(&mut self) {
+        let floats = [
+            ("r", &mut self.r, R_RANGE),
+            ("d", &mut self.d, D_RANGE),
+            ("mu", &mut self.mu, MU_RANGE),
+            ("c", &mut self.c, C_RANGE),
+            ("cx", &mut self.cx, C_RANGE),
+            ("cy", &mut self.cy, C_RANGE),
+            ("dt", &mut self.dt, DT_RANGE),
+            ("velocity_magnitude", &mut self.velocity_magnitude, VELOCITY_RANGE),
+        ];
+        for (name, value, range) in floats {
+            // NaN fails the check but survives clamp; sanitize resets it
+            if !range.contains(value) && !value.is_nan() {
+                log::warn!("Param {name} = {value} is outside its slider, clamping it to {range:?}");
+                *value = value.clamp(*range.start(), *range.end());
+            }
+        }
+        if !N_RANGE.contains(&self.n) {
+            log::warn!("Param n = {} is outside its slider, clamping it to {N_RANGE:?}", self.n);
+            self.n = self.n.clamp(*N_RANGE.start(), *N_RANGE.end());
+        }
+    }
+
     pub fn default(width: u32, height: u32) -> Self {
         Self {
             n: 5,
@@ -47,57 +601,480 @@ impl Params {
             velocity_magnitude: 4.0,
             velocity_angle: 0.0,
             velocity_pattern: 1, // tangential by default
-            _padding: 0,
+            dither: 1,
+            integrator: 0,
+            compare_integrators: 0,
+            compare_split: 0.5,
+            cutoff: 0.0,
+            jitter_pattern: 0,
+            jitter_sample: 0,
+            seed: 0,
+            color_mode: 0,
+            color_min: 0.0,
+            color_max: 1.0,
+            invert_colormap: 0,
+            highlight_diverged: 0,
+            banding: 0,
+            spring_cubic: 0.0,
+            compose_colormaps: 0,
+            secondary_colormap: Colormap::Grayscale.index(),
+            colormap_split: 0.5,
+            colormap_blend: 0.0,
+            anisotropic_spring: 0,
+            cx: 0.2,
+            cy: 0.2,
+            softness: default_softness(),
+            convergence_metric: 0,
+            settle_window: default_settle_window(),
+            temporal_dither: 0,
+            seed_coords: 0,
+            mass_min: default_mass(),
+            mass_max: default_mass(),
+            tolerance: default_tolerance(),
+            max_substeps: default_max_substeps(),
+            alpha_mask: 0,
+            colormap: 0,
+            center_x: 0.0,
+            center_y: 0.0,
+            zoom: default_zoom(),
+            frame: 0,
+            scale: 0.0,
         }
     }
 }
 
+// Compact one-line form for bug reports and sharing, e.g.
+// `n=5 r=3.0 d=0.40 mu=0.20 c=0.20 dt=0.012 vmag=4.0 vang=0° vpat=1`
+impl std::fmt::Display for Params {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "n={} r={:.1} d={:.2} mu={:.2} c={:.2} dt={:.3} vmag={:.1} vang={:.0}° vpat={}",
+            self.n,
+            self.r,
+            self.d,
+            self.mu,
+            self.c,
+            self.dt,
+            self.velocity_magnitude,
+            self.velocity_angle.to_degrees(),
+            self.velocity_pattern,
+        )
+    }
+}
+
 struct GPUSimResources {
     vertex_buffer: wgpu::Buffer,
     param_buffer: wgpu::Buffer,
+    particle_buf: wgpu::Buffer,
+    colormap_buf: wgpu::Buffer,
+    stats_buf: wgpu::Buffer,
     compute_pipeline: wgpu::ComputePipeline,
+    reduce_pipeline: wgpu::ComputePipeline,
+    range_pipeline: wgpu::ComputePipeline,
+    orbit_pipeline: wgpu::ComputePipeline,
+    energy_pipeline: wgpu::ComputePipeline,
+    mark_pipeline: wgpu::ComputePipeline, // records frame start positions for the activity view
+    seed_pipeline: wgpu::ComputePipeline, // `create_particles` on the GPU, see `GPUSim::seed_on_gpu`
+    bg_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
     render_pipeline: wgpu::RenderPipeline,
+    blend_pipeline: wgpu::RenderPipeline, // the same, alpha blended
+    render_bg_layout: wgpu::BindGroupLayout,
     render_bg: wgpu::BindGroup,
+    nearest_bg: wgpu::BindGroup, // render_bg with nearest-neighbour sampling
+    full_window_buf: wgpu::Buffer,
+    magnifier_buf: wgpu::Buffer,
+    magnifier_bg: wgpu::BindGroup,
 
     _output_tex: (wgpu::Texture, wgpu::TextureView),
+
+    trace_pipeline: wgpu::ComputePipeline,
+    trail_pipeline: wgpu::RenderPipeline,
+    tracer_bg_layout: wgpu::BindGroupLayout,
+    trail_bg_layout: wgpu::BindGroupLayout,
+    tracers: Option<Tracers>, // None while no tracers are shown
+    surface_pipeline: wgpu::RenderPipeline,
+    surface_bg_layout: wgpu::BindGroupLayout,
+    surface_bg: wgpu::BindGroup,
+    surface_buf: wgpu::Buffer,
+    surface_index_buf: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    nearest_sampler: wgpu::Sampler,
+
+    timer: Option<ComputeTimer>, // None without timestamp query support
+
+    step: u64,                          // steps run since the last (re)start
+    frames: u32,                        // frames drawn, wrapping; keys the temporal dither
+    max_speed_cache: Option<(u64, f32)>, // (step, max speed) of the last readback
+    primary_colormap: Colormap, // map in the colormap buffer's first slot
+    secondary_colormap: Colormap, // and in its second
+    on_step: Option<StepHook>,
+    mask: Option<Vec<bool>>, // pendulums to simulate, see `GPUSim::set_mask`
+    mass_field: Option<Vec<f32>>, // mass weights, see `GPUSim::set_mass_field`
+    magnets: Option<Vec<Magnet>>, // replace the ring, see `GPUSim::set_magnets`
+    magnet_buf: wgpu::Buffer, // the magnet table, see `magnet_table`
+    magnet_table: Vec<Magnet>, // as last uploaded
+    pendulum_request: Option<PendulumRequest>, // see `GPUSim::request_pendulum`
+    diverged_readback: Option<Readback>, // see `GPUSim::poll_diverged_count`
+    speed_readback: Option<(u64, Readback)>, // step it was started at, see `GPUSim::request_max_speed`
+    scalar_readback: Option<Readback>, // see `GPUSim::request_scalars`
+    range_readback: Option<Readback>, // see `GPUSim::request_scalar_range`
+    drawn: Option<Params>, // params of the last compute pass with dt zeroed, None once the particles change
+}
+
+// A copy on its way back from the GPU. The map completes in the background, so
+// waiting for one never stalls a frame, and unlike `map_blocking` it also works on
+// WebGPU, which only resolves maps from the event loop
+struct Readback {
+    staging: wgpu::Buffer,
+    map_status: Arc<AtomicU8>, // MAP_PENDING, MAP_OK or MAP_FAILED
+}
+
+// One pendulum's copy on its way back from the GPU, so clicking on the canvas never
+// stalls a frame
+struct PendulumRequest {
+    readback: Readback,
+    cell: [u32; 2],
+}
+
+// A sparse set of pendulums integrated alongside the grid, whose recent positions
+// are kept in per-tracer ring buffers and drawn as line strips
+struct Tracers {
+    info: TracerInfo,
+    info_buf: wgpu::Buffer,
+    compute_bg: wgpu::BindGroup,
+    render_bg: wgpu::BindGroup,
+    bytes: u64, // size of the tracer buffers, which only the bind groups hold on to
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct TracerInfo {
+    count: u32,
+    trail_len: u32,
+    head: u32, // ring slot written by the latest step
+    scale: f32, // world units spanned by the canvas at the current zoom
+    steps: u32, // steps taken this frame
+    _padding: u32, // the shader aligns the vec2 to 8 bytes
+    center: [f32; 2], // world position at the middle of the canvas
+}
+
+/// Called once per frame, before that frame's simulation steps, with the params about
+/// to be uploaded and the number of steps run so far. See [`GPUSim::set_on_step`].
+pub type StepHook = Box<dyn FnMut(&mut Params, u64) + Send + Sync>;
+
+// Sub-rectangle of the output texture that a render quad samples
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct TexWindow {
+    offset: Vec2,
+    scale: Vec2,
+}
+
+// Targets of the reductions, holding the bits of non-negative f32s. The stats buffer
+// continues with one f32 per reduction workgroup, see `stats_size`
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct Stats {
+    max_speed: u32,
+    scalar_min: u32,
+    scalar_max: u32,
+    orbit_hist: [u32; MAX_MAGNETS * ORBIT_BINS], // plain counts, ORBIT_BINS per magnet
+    diverged: u32, // pendulums that went non-finite since the start, kept across reductions
+}
+
+// Bytes of the stats buffer for a grid, with room for the energy partial sums
+fn stats_size(width: u32, height: u32) -> u64 {
+    let groups = width.div_ceil(16) as u64 * height.div_ceil(16) as u64;
+    std::mem::size_of::<Stats>() as u64 + groups * std::mem::size_of::<f32>() as u64
+}
+
+// The colormap buffer holds the primary map, then the secondary one
+pub(crate) const COLORMAP_SLOTS: usize = 2;
+
+pub(crate) fn colormap_tables(params: &Params) -> Vec<[f32; 4]> {
+    let mut tables = colormap_slot(Colormap::from_index(params.colormap));
+    tables.extend(colormap_slot(Colormap::from_index(params.secondary_colormap)));
+    tables
+}
+
+// The table of one slot of the colormap buffer. A shorter one would leave the shader
+// reading the next slot or past the end
+pub(crate) fn colormap_slot(map: Colormap) -> Vec<[f32; 4]> {
+    let table = map.table();
+    assert_eq!(table.len(), COLORMAP_LEN, "colormap {} has the wrong length", map.name());
+    table
+}
+
+// The fragment-only path's params uniform buffer contents: the params, then the
+// magnet table
+pub(crate) fn param_buffer_contents(params: &Params) -> Vec<u8> {
+    let mut bytes = bytemuck::bytes_of(params).to_vec();
+    bytes.resize(MAGNET_OFFSET as usize, 0);
+    bytes.extend_from_slice(bytemuck::cast_slice(&magnet_table(params, None)));
+    bytes
+}
+
+// The magnets the shader reads: `custom` if set and the ring of `params` otherwise
+fn resolve_magnets(params: &Params, custom: Option<&[Magnet]>) -> Vec<Magnet> {
+    custom.map_or_else(|| params.ring_magnets(), <[Magnet]>::to_vec)
+}
+
+// All MAX_MAGNETS entries of the magnet table. Entries past the magnet count are
+// never read
+fn magnet_table(params: &Params, custom: Option<&[Magnet]>) -> Vec<Magnet> {
+    let mut table = resolve_magnets(params, custom);
+    table.resize(MAX_MAGNETS, Magnet::zeroed());
+    table
+}
+
+// Rewrites the magnet table at `offset` in `buffer` if it differs from `uploaded`,
+// and says whether it did. Custom magnets also set the magnet count in `params`, so
+// call this before uploading them
+pub(crate) fn upload_magnets(
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    offset: u64,
+    params: &mut Params,
+    custom: Option<&[Magnet]>,
+    uploaded: &mut Vec<Magnet>,
+) -> bool {
+    if let Some(magnets) = custom {
+        params.n = magnets.len().max(1) as u32;
+    }
+    let table = magnet_table(params, custom);
+    let changed = table != *uploaded;
+    if changed {
+        queue.write_buffer(buffer, offset, bytemuck::cast_slice(&table));
+        *uploaded = table;
+    }
+    changed
+}
+
+// The magnet table's range of the fragment-only path's params uniform buffer, for
+// binding 13
+pub(crate) fn magnet_binding(param_buffer: &wgpu::Buffer) -> wgpu::BindingResource<'_> {
+    wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+        buffer: param_buffer,
+        offset: MAGNET_OFFSET,
+        size: NonZeroU64::new(MAGNET_TABLE_SIZE),
+    })
+}
+
+const SURFACE_GRID: u32 = 256; // vertices per side of the surface mesh
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct SurfaceUniform {
+    view_proj: Mat4,
+    height_scale: f32,
+    grid: u32,
+    order: u32, // walk order of the mesh cells, see `vs_surface`
+    w: u32,
+    h: u32,
+    _padding: [u32; 3],
+}
+
+/// Orbit camera for the 3D surface view, where the scalar that picks each pixel's
+/// color is drawn as a height field over the color range.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceCamera {
+    pub yaw: f32,   // radians around the vertical axis, 0 = looking at the image bottom
+    pub pitch: f32, // radians above the ground plane
+    pub height_scale: f32, // height of the surface relative to its half-width
+}
+
+impl Default for SurfaceCamera {
+    fn default() -> Self {
+        Self { yaw: PI / 4.0, pitch: 0.6, height_scale: 0.3 }
+    }
+}
+
+impl SurfaceCamera {
+    fn uniform(&self, w: u32, h: u32) -> SurfaceUniform {
+        let eye = vec3(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.cos() * self.pitch.cos(),
+        );
+        let target = vec3(0.0, self.height_scale / 2.0, 0.0);
+        let view = Mat4::look_at_rh(target + 4.0 * eye, target, glam::Vec3::Y);
+        // The mesh spans [-1, 1]² on the ground, so this fits it at any yaw
+        let proj = Mat4::orthographic_rh(-1.5, 1.5, -1.5, 1.5, 0.1, 10.0);
+
+        // Draw cells back to front: rows along the axis the camera looks down most,
+        // starting from the side away from the camera (mesh z runs opposite to y)
+        let mut order = 0;
+        if eye.x < 0.0 {
+            order |= 1;
+        }
+        if eye.z > 0.0 {
+            order |= 2;
+        }
+        if eye.x.abs() > eye.z.abs() {
+            order |= 4;
+        }
+        SurfaceUniform {
+            view_proj: proj * view,
+            height_scale: self.height_scale,
+            grid: SURFACE_GRID,
+            order,
+            w,
+            h,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Storage format of the simulation's output texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Rgba8,       // 8-bit unorm, supported everywhere
+    Rgba16Float, // half floats: no 8-bit banding, and values outside [0, 1] survive
+}
+
+impl OutputFormat {
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            OutputFormat::Rgba8 => TextureFormat::Rgba8Unorm,
+            OutputFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        }
+    }
+
+    /// True if the adapter can write this format from a compute shader.
+    pub fn is_supported(self, adapter: &wgpu::Adapter) -> bool {
+        adapter
+            .get_texture_format_features(self.texture_format())
+            .allowed_usages
+            .contains(wgpu::TextureUsages::STORAGE_BINDING)
+    }
+
+    // Name of the format in WGSL
+    fn wgsl(self) -> &'static str {
+        match self {
+            OutputFormat::Rgba8 => "rgba8unorm",
+            OutputFormat::Rgba16Float => "rgba16float",
+        }
+    }
+}
+
+/// Outcome of [`GPUSim::render_converged`].
+#[derive(Debug, Clone)]
+pub struct RenderResult {
+    pub image: Vec<u8>, // RGBA8, top row first
+    pub steps: u64,
+    pub converged_frac: f32, // fraction of pendulums slower than the threshold
+    pub hit_cap: bool,       // stopped at max_steps before every pendulum settled
+}
+
+/// An image of the output on its way back from the GPU, see [`GPUSim::request_output`].
+pub struct OutputReadback {
+    readback: Readback,
+    float: bool, // Rgba16Float texels, quantized once they arrive
+    row_bytes: u32,
+    padded_row_bytes: u32,
+    dither: bool, // dither float output as it's quantized
 }
 
+impl OutputReadback {
+    /// The image, laid out like [`GPUSim::read_output`]'s, once the copy has mapped;
+    /// `Ready(None)` if the map failed. Never blocks, so call it each frame.
+    pub fn poll(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> std::task::Poll<Option<Vec<u8>>> {
+        self.readback.poll(&wgpu_render_state.device).map(|padded| padded.map(|padded| self.unpack(&padded)))
+    }
+
+    // Strips the row padding and flips the rows, quantizing float texels
+    fn unpack(&self, padded: &[u8]) -> Vec<u8> {
+        let rows = padded.chunks_exact(self.padded_row_bytes as usize).map(|row| &row[..self.row_bytes as usize]);
+        // The texture's first row is drawn at the bottom
+        if self.float {
+            // Float output is only dithered on screen, so this quantization dithers
+            // the same way, with the pattern the shader uses before any shift
+            let dither = self.dither;
+            let rows = rows.enumerate().rev().flat_map(|(y, row)| {
+                row.chunks_exact(8).enumerate().flat_map(move |(x, texel)| {
+                    let offset = if dither { bayer4(x as u32, y as u32) - 0.5 } else { 0.0 };
+                    texel.chunks_exact(2).enumerate().map(move |(c, bits)| {
+                        let v = f16_to_f32(u16::from_le_bytes([bits[0], bits[1]])).clamp(0.0, 1.0) * 255.0;
+                        // Alpha isn't dithered, as in dither8
+                        (if c < 3 { v + offset } else { v }).round().clamp(0.0, 255.0) as u8
+                    })
+                })
+            });
+            return rows.collect();
+        }
+        rows.rev().flatten().copied().collect()
+    }
+}
+
+/// A single pendulum read back by [`GPUSim::request_pendulum`].
+#[derive(Debug, Clone, Copy)]
+pub struct PendulumReadout {
+    pub cell: [u32; 2], // column, and row from the bottom
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub magnet: Option<u32>, // nearest magnet, None if masked out or diverged
+}
+
+/// Why [`GPUSim::load_particles`] rejected a particle buffer.
+#[derive(Debug)]
+pub enum LoadParticlesError {
+    WrongLength { expected: usize, found: usize }, // one particle per pixel is needed
+    FragmentOnly, // this path keeps no particle state
+}
+
+impl fmt::Display for LoadParticlesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadParticlesError::WrongLength { expected, found } => {
+                write!(f, "expected {expected} particles, got {found}")
+            }
+            LoadParticlesError::FragmentOnly => write!(f, "the fragment-only path has no particle buffer"),
+        }
+    }
+}
+
+impl std::error::Error for LoadParticlesError {}
+
+/// Handle to the magnetic pendulum simulation.
+///
+/// `new` stores the GPU resources in the egui-wgpu renderer, so the sim can be embedded
+/// in any eframe app running on the wgpu backend. Each frame, hand a copy to a paint
+/// callback: its `prepare` advances the simulation by one step of `params.dt` (nothing
+/// moves while `dt` is 0) and its `paint` draws the output texture into the rect.
+///
+/// ```no_run
+/// # fn show(ui: &mut eframe::egui::Ui, sim: &gpusim::sim::GPUSim) {
+/// let (rect, _) = ui.allocate_exact_size(eframe::egui::vec2(512.0, 512.0), eframe::egui::Sense::hover());
+/// ui.painter().add(eframe::egui_wgpu::Callback::new_paint_callback(rect, *sim));
+/// # }
+/// ```
 #[derive(Debug, Clone, Copy)]
 pub struct GPUSim {
     pub params: Params,
+    pub steps_per_frame: u32, // steps of `params.dt` taken by each `prepare`
     _scale: f32,
     _width: u32,
     _height: u32,
+    tracer_count: u32,
+    trail_len: u32,
+    fragment_only: bool, // no compute shaders, see `fallback.rs`
+    surface: Option<SurfaceCamera>,
+    blend: bool, // composite over what's already drawn instead of overwriting it
+    nearest: bool, // sample the image without interpolation
 }
 
 impl GPUSim {
     pub fn create_particles(width: u32, height: u32, scale: f32, params: &Params) -> Vec<Particle> {
         (0..width * height).map(|i| {
-            let u = (vec2(
-                (i % width) as f32 / width as f32,
-                (i / width) as f32 / height as f32,
-            ) - Vec2::splat(0.5)) * scale;
-            let du = match params.velocity_pattern {
-                // Radial pattern: velocity points away from center
-                0 => if u.length() > 0.001 {
-                    params.velocity_magnitude * u.normalize()
-                        .rotate(Vec2::from_angle(params.velocity_angle))
-                } else {
-                    Vec2::from_angle(params.velocity_angle) * params.velocity_magnitude
-                }
-                // Tangential pattern: velocity perpendicular to position
-                1 => if u.length() > 0.001 {
-                    params.velocity_magnitude * Vec2::new(-u.y, u.x).normalize()
-                        .rotate(Vec2::from_angle(params.velocity_angle))
-                } else {
-                    Vec2::from_angle(params.velocity_angle + PI / 2.0) * params.velocity_magnitude
-                }
-                // Uniform direction: all particles have same velocity direction
-                2 => Vec2::from_angle(params.velocity_angle) * params.velocity_magnitude,
-                _ => Vec2::ZERO
-            };
-            Particle { u, du }
+            let (x, y) = (i % width, i / width);
+            let offset = jitter_offset(params, x, y);
+            let cell = (vec2(x as f32, y as f32) + offset) / vec2(width as f32, height as f32);
+            let u = params.seed_position(cell, scale);
+            let du = initial_velocity(u, params);
+            Particle::new(u, du).with_mass(params.mass_at(u, scale))
         }).collect()
     }
 
@@ -106,81 +1083,93 @@ impl GPUSim {
         width: u32,
         height: u32,
         scale: f32,
+    ) -> Self {
+        Self::with_output_format(wgpu_render_state, width, height, scale, OutputFormat::Rgba8)
+    }
+
+    /// Like [`Self::new`], with the output texture in `format`. Formats the adapter
+    /// can't write from a compute shader fall back to [`OutputFormat::Rgba8`].
+    pub fn with_output_format(
+        wgpu_render_state: &eframe::egui_wgpu::RenderState,
+        width: u32,
+        height: u32,
+        scale: f32,
+        format: OutputFormat,
     ) -> Self {
         let params = Params::default(width, height);
         let (device, target_format) = (&wgpu_render_state.device, wgpu_render_state.target_format);
-        let particles = Self::create_particles(width, height, scale, &params);
+        let queue = &wgpu_render_state.queue;
+
+        // WebGL2 has no compute shaders, so render with the slower fragment-only path
+        let compute = wgpu_render_state.adapter.get_downlevel_capabilities().flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+        if !compute {
+            log::warn!("Compute shaders are unavailable, falling back to the fragment-only renderer");
+            wgpu_render_state
+                .renderer
+                .write()
+                .callback_resources
+                .insert(FallbackResources::new(device, queue, target_format, &params, scale));
+            return GPUSim {
+                params,
+                steps_per_frame: 1,
+                _scale: scale,
+                _width: width,
+                _height: height,
+                tracer_count: 0,
+                trail_len: 256,
+                fragment_only: true,
+                surface: None,
+                blend: false,
+                nearest: false,
+            };
+        }
 
         let param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("particles"),
-            contents: bytemuck::cast_slice(&[params]),
+            label: Some("params"),
+            contents: bytemuck::bytes_of(&params),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
-
-        let particle_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("particles"),
-            contents: bytemuck::cast_slice(&particles),
-            usage: wgpu::BufferUsages::STORAGE,
+        let magnet_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("magnets"),
+            contents: bytemuck::cast_slice(&magnet_table(&params, None)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
+        // Seeded by `seed_main` below, like every restart that doesn't need the CPU
+        let particle_buf = Self::create_particle_buffer(device, width, height);
+
         let colormap_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("colormap"),
-            contents: bytemuck::cast_slice(&TWILIGHT_MAP),
-            usage: wgpu::BufferUsages::STORAGE,
+            contents: bytemuck::cast_slice(&colormap_tables(&params)),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
-        let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Avaialable Buffers"),
-            entries: &[
-                // Simulation Parameters
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE
-                        | wgpu::ShaderStages::VERTEX
-                        | wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Old Particle data
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // The texture
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::WriteOnly,
-                        format: TextureFormat::Rgba8Unorm,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
+        let stats_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("stats"),
+            size: stats_size(width, height),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
 
-        let shader_module = device.create_shader_module(include_wgsl!("shader.wgsl"));
+        let format = if format.is_supported(&wgpu_render_state.adapter) {
+            format
+        } else {
+            log::warn!("{format:?} output is not supported on this adapter, using Rgba8");
+            OutputFormat::Rgba8
+        };
+        let bg_layout = Self::create_bg_layout(device, format.texture_format());
+
+        // The storage texture's format is part of its WGSL type, and decides whether
+        // the image is dithered as it's stored or as it's displayed
+        let source = include_str!("shader.wgsl")
+            .replace("texture_storage_2d<rgba8unorm, write>", &format!("texture_storage_2d<{}, write>", format.wgsl()))
+            .replace("var<uniform> magnets:", "var<storage, read> magnets:")
+            .replace("const DITHER_AT_DISPLAY = false;", &format!("const DITHER_AT_DISPLAY = {};", format == OutputFormat::Rgba16Float));
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("update layout"),
@@ -197,25 +1186,61 @@ impl GPUSim {
             cache: None,
         });
 
-        let tex = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("magpen texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                ..Default::default()
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+        let reduce_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Max speed reduction pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("reduce_max_speed"),
+            compilation_options: Default::default(),
+            cache: None,
         });
-        let texview = tex.create_view(&wgpu::TextureViewDescriptor {
-            label: Some("magpen texture id"),
-            ..Default::default()
+
+        let range_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Scalar range reduction pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("reduce_scalar_range"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let orbit_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Orbit histogram pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("orbit_histogram"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let energy_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Energy reduction pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("reduce_energy"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let mark_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Frame start pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("mark_frame"),
+            compilation_options: Default::default(),
+            cache: None,
         });
-        let out_tex = (tex, texview);
+
+        let seed_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Seed pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("seed_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let out_tex = Self::create_output_texture(device, width, height, format.texture_format());
 
         let render_bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Render Layout"),
@@ -236,6 +1261,27 @@ impl GPUSim {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Texture window
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
         let render_pipeline_layout =
@@ -249,13 +1295,13 @@ impl GPUSim {
             step_mode: wgpu::VertexStepMode::Vertex,
             attributes: &wgpu::vertex_attr_array![0=>Float32x2, 1=>Float32x2],
         };
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render pipeline"),
+        let quad_pipeline = |label, blend| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
             layout: Some(&render_pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader_module,
                 entry_point: Some("vs_main"),
-                buffers: &[vb_layout],
+                buffers: std::slice::from_ref(&vb_layout),
                 compilation_options: Default::default(),
             },
             fragment: Some(wgpu::FragmentState {
@@ -263,7 +1309,7 @@ impl GPUSim {
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
                     format: target_format,
-                    blend: Some(wgpu::BlendState::REPLACE),
+                    blend: Some(blend),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
                 compilation_options: Default::default(),
@@ -277,6 +1323,85 @@ impl GPUSim {
             multiview: None,
             cache: None,
         });
+        let render_pipeline = quad_pipeline("Render pipeline", wgpu::BlendState::REPLACE);
+        // egui's target holds premultiplied alpha
+        let blend_pipeline = quad_pipeline("Blended render pipeline", wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+        let tracer_bg_layout = Self::create_tracer_bg_layout(device);
+        let trace_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("tracer layout"),
+            bind_group_layouts: &[&tracer_bg_layout],
+            push_constant_ranges: &[],
+        });
+        let trace_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Tracer pipeline"),
+            layout: Some(&trace_pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("trace_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let trail_bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Trail Layout"),
+            entries: &[
+                // Trail positions
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Tracer info
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let trail_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("trail pipeline layout"),
+            bind_group_layouts: &[&trail_bg_layout],
+            push_constant_ranges: &[],
+        });
+        let trail_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Trail pipeline"),
+            layout: Some(&trail_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_trail"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_trail"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineStrip,
+                ..Default::default()
+            },
+            multisample: wgpu::MultisampleState::default(),
+            depth_stencil: None,
+            multiview: None,
+            cache: None,
+        });
+
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("sampler"),
             mag_filter: wgpu::FilterMode::Linear,
@@ -284,63 +1409,150 @@ impl GPUSim {
             mipmap_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
-        let render_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &render_bg_layout,
-            label: Some("Resources described by the render_bg_layout"),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&out_tex.1),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
+        let nearest_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("nearest sampler"),
+            ..Default::default()
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bg_layout,
-            label: Some("Resources described by the bind_group_layout"),
+        let surface_bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Surface Layout"),
             entries: &[
-                // params
-                wgpu::BindGroupEntry {
+                // Simulation parameters, for the color range the heights span
+                wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &param_buffer,
-                        offset: 0,
-                        // size: None,
-                        size: NonZeroU64::new(std::mem::size_of::<Params>() as u64),
-                    }),
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                // particles
-                wgpu::BindGroupEntry {
+                // Particles, for the heights
+                wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &particle_buf,
-                        offset: 0,
-                        size: NonZeroU64::new(
-                            (particles.len() * std::mem::size_of::<Particle>()) as u64,
-                        ),
-                    }),
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                // Current Particle data
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(&out_tex.1),
+                // Camera and mesh layout
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &colormap_buf,
-                        offset: 0,
-                        size: NonZeroU64::new(
-                            (TWILIGHT_MAP.len() * std::mem::size_of::<[f32; 4]>()) as u64,
-                        ),
-                    }),
+                // Output texture, for the colors
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
                 },
             ],
         });
+        let surface_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("surface pipeline layout"),
+            bind_group_layouts: &[&surface_bg_layout],
+            push_constant_ranges: &[],
+        });
+        let surface_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Surface pipeline"),
+            layout: Some(&surface_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_surface"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_surface"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            multisample: wgpu::MultisampleState::default(),
+            depth_stencil: None,
+            multiview: None,
+            cache: None,
+        });
+        let surface_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("surface camera"),
+            contents: bytemuck::bytes_of(&SurfaceCamera::default().uniform(width, height)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        // Two triangles per cell, row by row
+        let surface_indices: Vec<u32> = (0..SURFACE_GRID - 1)
+            .flat_map(|j| (0..SURFACE_GRID - 1).map(move |i| (i, j)))
+            .flat_map(|(i, j)| {
+                let v = j * SURFACE_GRID + i;
+                [v, v + 1, v + SURFACE_GRID, v + 1, v + SURFACE_GRID + 1, v + SURFACE_GRID]
+            })
+            .collect();
+        let surface_index_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("surface indices"),
+            contents: bytemuck::cast_slice(&surface_indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let surface_bg = Self::create_surface_bg(
+            device, &surface_bg_layout, &param_buffer, &particle_buf, &surface_buf, &out_tex.1, &sampler,
+        );
+        let full_window_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("full texture window"),
+            contents: bytemuck::bytes_of(&TexWindow { offset: Vec2::ZERO, scale: Vec2::ONE }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let magnifier_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("magnifier texture window"),
+            contents: bytemuck::bytes_of(&TexWindow { offset: Vec2::ZERO, scale: Vec2::ONE }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let render_bg = Self::create_render_bg(
+            device, &render_bg_layout, &out_tex.1, &sampler, &param_buffer, &full_window_buf,
+        );
+        let nearest_bg = Self::create_render_bg(
+            device, &render_bg_layout, &out_tex.1, &nearest_sampler, &param_buffer, &full_window_buf,
+        );
+        let magnifier_bg = Self::create_render_bg(
+            device, &render_bg_layout, &out_tex.1, &sampler, &param_buffer, &magnifier_buf,
+        );
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &bg_layout,
+            &param_buffer,
+            &particle_buf,
+            &out_tex.1,
+            &colormap_buf,
+            &stats_buf,
+            &magnet_buf,
+        );
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
@@ -359,24 +1571,131 @@ impl GPUSim {
             .callback_resources
             .insert(GPUSimResources {
                 bind_group,
+                bg_layout,
                 param_buffer,
+                particle_buf,
+                colormap_buf,
+                stats_buf,
                 compute_pipeline,
+                reduce_pipeline,
+                range_pipeline,
+                orbit_pipeline,
+                energy_pipeline,
+                mark_pipeline,
+                seed_pipeline,
+                render_bg_layout,
                 render_bg,
+                nearest_bg,
+                full_window_buf,
+                magnifier_buf,
+                magnifier_bg,
                 render_pipeline,
+                blend_pipeline,
                 vertex_buffer,
                 _output_tex: out_tex,
+                trace_pipeline,
+                trail_pipeline,
+                tracer_bg_layout,
+                trail_bg_layout,
+                tracers: None,
+                surface_pipeline,
+                surface_bg_layout,
+                surface_bg,
+                surface_buf,
+                surface_index_buf,
+                sampler,
+                nearest_sampler,
+                timer: device
+                    .features()
+                    .contains(wgpu::Features::TIMESTAMP_QUERY)
+                    .then(|| ComputeTimer::new(device, queue)),
+                step: 0,
+                frames: 0,
+                max_speed_cache: None,
+                primary_colormap: Colormap::from_index(params.colormap),
+                secondary_colormap: Colormap::from_index(params.secondary_colormap),
+                on_step: None,
+                mask: None,
+                mass_field: None,
+                magnets: None,
+                magnet_buf,
+                magnet_table: magnet_table(&params, None),
+                pendulum_request: None,
+                diverged_readback: None,
+                speed_readback: None,
+                scalar_readback: None,
+                range_readback: None,
+                drawn: None,
             });
 
-        GPUSim {
+        let mut sim = GPUSim {
             params,
+            steps_per_frame: 1,
             _scale: scale,
             _width: width,
             _height: height,
-        }
+            tracer_count: 0,
+            trail_len: 256,
+            fragment_only: false,
+            surface: None,
+            blend: false,
+            nearest: false,
+        };
+        sim.seed_on_gpu(wgpu_render_state);
+        sim
+    }
+
+    /// Returns a copy that paints the 3D surface view seen from `camera` instead of
+    /// the flat image, or the flat image again with `None`.
+    pub fn with_surface(mut self, camera: Option<SurfaceCamera>) -> Self {
+        self.surface = camera;
+        self
+    }
+
+    /// Returns a copy whose flat image is alpha blended over what's behind it, so
+    /// diverged pendulums (which are transparent) show the background. Not supported
+    /// on the fragment-only path.
+    pub fn with_blend(mut self, blend: bool) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Returns a copy that draws the flat image with nearest-neighbour sampling, which
+    /// keeps texels crisp when the canvas is a whole multiple of the grid size. The
+    /// fragment-only path always computes one pendulum per cell, so it is unaffected.
+    pub fn with_nearest(mut self, nearest: bool) -> Self {
+        self.nearest = nearest;
+        self
+    }
+
+    /// True when running without compute shaders (WebGL2). Tracers, the magnifier and
+    /// readbacks are unavailable then, and runs stop after a fixed number of steps.
+    pub fn is_fragment_only(&self) -> bool {
+        self.fragment_only
     }
 
     pub fn restart(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
-        let particles =
+        if self.fragment_only {
+            // Every frame starts from the initial positions anyway
+            if let Some(resources) = wgpu_render_state
+                .renderer
+                .write()
+                .callback_resources
+                .get_mut::<FallbackResources>()
+            {
+                resources.step = 0;
+            }
+            return;
+        }
+        // Seeding on the GPU skips building and uploading the grid, unless something
+        // only the CPU path applies is in use
+        let needs_cpu = self.params.jitter_pattern != 0
+            || wgpu_render_state.renderer.read().callback_resources.get::<GPUSimResources>()
+                .is_some_and(|res| res.mask.is_some() || res.mass_field.is_some());
+        if !needs_cpu {
+            return self.seed_on_gpu(wgpu_render_state);
+        }
+        let mut particles =
             Self::create_particles(self._width, self._height, self._scale, &self.params);
         let device = &wgpu_render_state.device;
 
@@ -387,109 +1706,1790 @@ impl GPUSim {
             .callback_resources
             .get_mut::<GPUSimResources>()
         {
-            // Create new particle buffer with reset particles
-            let new_particle_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("particles"),
-                contents: bytemuck::cast_slice(&particles),
-                usage: wgpu::BufferUsages::STORAGE,
-            });
+            // Masked out pendulums start at rest, so they never hold up convergence
+            if let Some(mask) = resources.mask.as_ref().filter(|mask| mask.len() == particles.len()) {
+                for (p, &active) in particles.iter_mut().zip(mask) {
+                    if !active {
+                        *p = Particle { flags: INACTIVE, ..Particle::new(p.u, Vec2::ZERO) };
+                    }
+                }
+            }
+            if let Some(weights) = resources.mass_field.as_ref().filter(|weights| weights.len() == particles.len()) {
+                for (p, &t) in particles.iter_mut().zip(weights) {
+                    p.mass = self.params.mass_from_weight(t);
+                }
+            }
 
-            // Recreate the bind group with the new particle buffer
-            let colormap_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("colormap"),
-                contents: bytemuck::cast_slice(&TWILIGHT_MAP),
-                usage: wgpu::BufferUsages::STORAGE,
-            });
+            self.upload_particles(device, &wgpu_render_state.queue, resources, &particles);
+        }
+    }
 
-            let bg_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Avaialable Buffers"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE
-                            | wgpu::ShaderStages::VERTEX
-                            | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::WriteOnly,
-                            format: TextureFormat::Rgba8Unorm,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: true },
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
+    /// Replaces the particle state with `particles`, in the grid order of
+    /// [`Self::read_particles`], and counts steps from 0 again. Together they checkpoint
+    /// and resume a run, or seed it from a state computed elsewhere.
+    pub fn load_particles(
+        &mut self,
+        wgpu_render_state: &eframe::egui_wgpu::RenderState,
+        particles: &[Particle],
+    ) -> Result<(), LoadParticlesError> {
+        if self.fragment_only {
+            return Err(LoadParticlesError::FragmentOnly);
+        }
+        let expected = (self._width * self._height) as usize;
+        if particles.len() != expected {
+            return Err(LoadParticlesError::WrongLength { expected, found: particles.len() });
+        }
+        if let Some(resources) = wgpu_render_state
+            .renderer
+            .write()
+            .callback_resources
+            .get_mut::<GPUSimResources>()
+        {
+            self.upload_particles(&wgpu_render_state.device, &wgpu_render_state.queue, resources, particles);
+        }
+        Ok(())
+    }
+
+    /// Restarts by seeding the particles with the shader's `seed_main` in place instead
+    /// of uploading [`Self::create_particles`]. Positions aren't jittered and masks and
+    /// mass fields don't apply, so [`Self::restart`] only takes this path with
+    /// `params.jitter_pattern` at 0 and neither loaded, where the two agree.
+    pub fn seed_on_gpu(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState) {
+        if self.fragment_only {
+            return self.restart(wgpu_render_state);
+        }
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let mut renderer = wgpu_render_state.renderer.write();
+        let Some(res) = renderer.callback_resources.get_mut::<GPUSimResources>() else {
+            return;
+        };
+        let mut params = Params { frame: 0, scale: self._scale, ..self.params };
+        params.sanitize_quietly();
+        queue.write_buffer(&res.param_buffer, 0, bytemuck::bytes_of(&params));
+        let mut encoder = device.create_command_encoder(&Default::default());
+        Self::clear_output(&mut encoder, &res._output_tex.1);
+        {
+            let mut cpass = encoder.begin_compute_pass(&Default::default());
+            cpass.set_bind_group(0, &res.bind_group, &[]);
+            cpass.set_pipeline(&res.seed_pipeline);
+            cpass.dispatch_workgroups(params.w.div_ceil(16), params.h.div_ceil(16), 1);
+        }
+        queue.write_buffer(&res.stats_buf, std::mem::offset_of!(Stats, diverged) as u64, bytemuck::bytes_of(&0u32));
+        queue.submit(Some(encoder.finish()));
+        res.tracers = self.create_tracers(device, res);
+        res.step = 0;
+        res.max_speed_cache = None;
+        res.drawn = None;
+        // Readings of the previous run would arrive after the restart
+        res.diverged_readback = None;
+        res.speed_readback = None;
+        res.range_readback = None;
+    }
+
+    // Swaps in a buffer holding `particles` and counts steps from 0
+    fn upload_particles(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resources: &mut GPUSimResources,
+        particles: &[Particle],
+    ) {
+        let new_particle_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("particles"),
+            contents: bytemuck::cast_slice(particles),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+        self.bind_particles(device, resources, new_particle_buf);
+        resources.step = 0;
+        resources.max_speed_cache = None;
+        resources.drawn = None;
+        resources.diverged_readback = None;
+        resources.speed_readback = None;
+        resources.range_readback = None;
+        let diverged = particles.iter().filter(|p| p.is_active() && !p.is_finite()).count() as u32;
+        queue.write_buffer(
+            &resources.stats_buf,
+            std::mem::offset_of!(Stats, diverged) as u64,
+            bytemuck::bytes_of(&diverged),
+        );
+        let mut encoder = device.create_command_encoder(&Default::default());
+        Self::clear_output(&mut encoder, &resources._output_tex.1);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    // A zeroed particle buffer for the grid, to be seeded by `seed_main`
+    fn create_particle_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particles"),
+            size: (width * height) as u64 * std::mem::size_of::<Particle>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    // Swaps in a new particle buffer and rebuilds everything bound to the old one or
+    // to the output texture
+    fn bind_particles(&self, device: &wgpu::Device, resources: &mut GPUSimResources, new_particle_buf: wgpu::Buffer) {
+        // A larger grid has more workgroups to keep energy sums for
+        let stats_size = stats_size(self._width, self._height);
+        if resources.stats_buf.size() < stats_size {
+            resources.stats_buf = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("stats"),
+                size: stats_size,
+                usage: resources.stats_buf.usage(),
+                mapped_at_creation: false,
+            });
+        }
+
+        resources.bind_group = Self::create_bind_group(
+            device,
+            &resources.bg_layout,
+            &resources.param_buffer,
+            &new_particle_buf,
+            &resources._output_tex.1,
+            &resources.colormap_buf,
+            &resources.stats_buf,
+            &resources.magnet_buf,
+        );
+        resources.surface_bg = Self::create_surface_bg(
+            device,
+            &resources.surface_bg_layout,
+            &resources.param_buffer,
+            &new_particle_buf,
+            &resources.surface_buf,
+            &resources._output_tex.1,
+            &resources.sampler,
+        );
+        resources.particle_buf = new_particle_buf;
+        resources.tracers = self.create_tracers(device, resources);
+    }
+
+    // Clears the output texture to transparent, so nothing of the previous run shows
+    // in pixels the new one hasn't drawn yet. A render pass clear works for every
+    // output format and, unlike `clear_texture`, needs no optional feature
+    fn clear_output(encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("clear output"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: wgpu::StoreOp::Store },
+            })],
+            ..Default::default()
+        });
+    }
+
+    /// Whether a `width`×`height` grid fits the device's limits on textures and
+    /// storage buffers. [`Self::resize`] to a grid that doesn't would fail.
+    pub fn fits_device(wgpu_render_state: &eframe::egui_wgpu::RenderState, width: u32, height: u32) -> bool {
+        let limits = wgpu_render_state.device.limits();
+        let particle_bytes = width as u64 * height as u64 * std::mem::size_of::<Particle>() as u64;
+        width.max(height) <= limits.max_texture_dimension_2d
+            && particle_bytes <= limits.max_storage_buffer_binding_size as u64
+            && particle_bytes <= limits.max_buffer_size
+    }
+
+    /// Changes the grid to `width`×`height` pendulums and restarts. Does nothing on the
+    /// fragment-only path, which always renders at the viewport's resolution.
+    pub fn resize(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState, width: u32, height: u32) {
+        if self.fragment_only {
+            return;
+        }
+        (self._width, self._height) = (width, height);
+        (self.params.w, self.params.h) = (width, height);
+        if let Some(res) = wgpu_render_state
+            .renderer
+            .write()
+            .callback_resources
+            .get_mut::<GPUSimResources>()
+        {
+            let device = &wgpu_render_state.device;
+            let format = res._output_tex.0.format();
+            res._output_tex = Self::create_output_texture(device, width, height, format);
+            let view = &res._output_tex.1;
+            res.render_bg = Self::create_render_bg(
+                device, &res.render_bg_layout, view, &res.sampler, &res.param_buffer, &res.full_window_buf,
+            );
+            res.nearest_bg = Self::create_render_bg(
+                device, &res.render_bg_layout, view, &res.nearest_sampler, &res.param_buffer, &res.full_window_buf,
+            );
+            res.magnifier_bg = Self::create_render_bg(
+                device, &res.render_bg_layout, view, &res.sampler, &res.param_buffer, &res.magnifier_buf,
+            );
+            self.bind_particles(device, res, Self::create_particle_buffer(device, width, height));
+        }
+        self.restart(wgpu_render_state);
+    }
+
+    /// Simulates only the pendulums where `mask` is true and leaves the rest as
+    /// transparent background, or all of them with `None`. The mask is in particle
+    /// order (bottom row first) and kept across restarts while its size matches the
+    /// grid. Restarts the run. Not supported on the fragment-only path.
+    pub fn set_mask(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState, mask: Option<Vec<bool>>) {
+        if let Some(resources) = wgpu_render_state
+            .renderer
+            .write()
+            .callback_resources
+            .get_mut::<GPUSimResources>()
+        {
+            resources.mask = mask;
+        }
+        self.restart(wgpu_render_state);
+    }
+
+    /// Replaces the ring of `params.n` magnets at radius `params.r` with `magnets`, or
+    /// with `None` goes back to the ring. While set, the list also gives `params.n`,
+    /// and the shader reads each magnet's strength and, in color mode 4, its color.
+    /// Takes effect from the next frame without a restart. [`Self::magnets`] returns
+    /// the table in use, which the CPU mirrors such as [`step_particle`] take.
+    pub fn set_magnets(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, magnets: Option<Vec<Magnet>>) {
+        let magnets = magnets.map(|mut magnets| {
+            magnets.truncate(MAX_MAGNETS);
+            magnets
+        });
+        let mut renderer = wgpu_render_state.renderer.write();
+        if self.fragment_only {
+            if let Some(resources) = renderer.callback_resources.get_mut::<FallbackResources>() {
+                resources.magnets = magnets;
+            }
+        } else if let Some(resources) = renderer.callback_resources.get_mut::<GPUSimResources>() {
+            resources.magnets = magnets;
+        }
+    }
+
+    /// The magnets the shader reads: those given to [`Self::set_magnets`], or else the
+    /// ring of [`Params::ring_magnets`].
+    pub fn magnets(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Vec<Magnet> {
+        let renderer = wgpu_render_state.renderer.read();
+        let custom = if self.fragment_only {
+            renderer.callback_resources.get::<FallbackResources>().and_then(|res| res.magnets.as_deref())
+        } else {
+            renderer.callback_resources.get::<GPUSimResources>().and_then(|res| res.magnets.as_deref())
+        };
+        resolve_magnets(&self.params, custom)
+    }
+
+    /// Seeds each pendulum's mass from its weight in `weights`, in particle order like
+    /// [`Self::set_mask`], with 0 giving `params.mass_min` and 1 `params.mass_max`,
+    /// instead of the radial gradient between them; `None` goes back to the gradient.
+    /// Takes effect on the next restart.
+    pub fn set_mass_field(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, weights: Option<Vec<f32>>) {
+        if let Some(resources) = wgpu_render_state
+            .renderer
+            .write()
+            .callback_resources
+            .get_mut::<GPUSimResources>()
+        {
+            resources.mass_field = weights;
+        }
+    }
+
+    /// Shows `count` tracer pendulums, spread evenly over the canvas, with trails of
+    /// their last `trail_len` positions drawn over the image. A count of 0 hides them,
+    /// and so does polar seeding, where the canvas isn't laid out in x and y.
+    /// The tracers start over from their initial positions.
+    pub fn set_tracers(&mut self, wgpu_render_state: &eframe::egui_wgpu::RenderState, count: u32, trail_len: u32) {
+        self.tracer_count = count;
+        self.trail_len = trail_len.max(2);
+        if let Some(resources) = wgpu_render_state
+            .renderer
+            .write()
+            .callback_resources
+            .get_mut::<GPUSimResources>()
+        {
+            resources.tracers = self.create_tracers(&wgpu_render_state.device, resources);
+        }
+    }
+
+    // Seeds the tracers on a square grid of cell centres, with every trail slot at
+    // the starting position so trails grow from a point
+    fn create_tracers(&self, device: &wgpu::Device, res: &GPUSimResources) -> Option<Tracers> {
+        // Trails are drawn at their x, y, which only lines up with a Cartesian canvas
+        if self.tracer_count == 0 || self.params.seed_coords == 1 {
+            return None;
+        }
+        let side = (self.tracer_count as f32).sqrt().ceil() as u32;
+        let tracers: Vec<Particle> = (0..self.tracer_count).map(|k| {
+            let cell = vec2((k % side) as f32 + 0.5, (k / side) as f32 + 0.5) / side as f32;
+            let u = self.params.view_center() + (cell - Vec2::splat(0.5)) * self.params.view_span(self._scale);
+            Particle::new(u, initial_velocity(u, &self.params)).with_mass(self.params.mass_at(u, self._scale))
+        }).collect();
+        let trail: Vec<Vec2> = tracers
+            .iter()
+            .flat_map(|p| std::iter::repeat_n(p.u, self.trail_len as usize))
+            .collect();
+        let info = TracerInfo {
+            count: self.tracer_count,
+            trail_len: self.trail_len,
+            head: 0,
+            scale: self.params.view_span(self._scale),
+            steps: 1,
+            _padding: 0,
+            center: self.params.view_center().to_array(),
+        };
+
+        let tracer_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tracers"),
+            contents: bytemuck::cast_slice(&tracers),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let trail_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tracer trails"),
+            contents: bytemuck::cast_slice(&trail),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let info_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("tracer info"),
+            contents: bytemuck::bytes_of(&info),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let compute_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &res.tracer_bg_layout,
+            label: Some("Resources described by the tracer_bg_layout"),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: res.param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: tracer_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: trail_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: info_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: res.magnet_buf.as_entire_binding(),
+                },
+            ],
+        });
+        let render_bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &res.trail_bg_layout,
+            label: Some("Resources described by the trail_bg_layout"),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: trail_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: info_buf.as_entire_binding(),
+                },
+            ],
+        });
+        let bytes = tracer_buf.size() + trail_buf.size() + info_buf.size();
+        Some(Tracers { info, info_buf, compute_bg, render_bg, bytes })
+    }
+
+    /// Installs (or with `None`, removes) a hook run in `prepare` before every frame's
+    /// steps.
+    ///
+    /// The hook edits the params for that frame's upload only; the next frame starts
+    /// again from the params it was submitted with, so derive any evolution from the
+    /// step count.
+    pub fn set_on_step(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, hook: Option<StepHook>) {
+        if let Some(resources) = wgpu_render_state
+            .renderer
+            .write()
+            .callback_resources
+            .get_mut::<GPUSimResources>()
+        {
+            resources.on_step = hook;
+        }
+    }
+
+    /// GPU memory held by the simulation, in bytes per kind of resource. Readback
+    /// staging buffers are short-lived and not counted.
+    pub fn memory_usage(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Vec<(&'static str, u64)> {
+        let renderer = wgpu_render_state.renderer.read();
+        if self.fragment_only {
+            return renderer.callback_resources.get::<FallbackResources>().map_or(vec![], |res| res.memory_usage());
+        }
+        let Some(res) = renderer.callback_resources.get::<GPUSimResources>() else {
+            return vec![];
+        };
+        let tex = &res._output_tex.0;
+        let texel_bytes = tex.format().block_copy_size(None).unwrap_or(0) as u64;
+        let uniforms = [&res.vertex_buffer, &res.param_buffer, &res.stats_buf, &res.magnifier_buf, &res.surface_buf];
+        vec![
+            ("Particles", res.particle_buf.size()),
+            ("Output texture", tex.width() as u64 * tex.height() as u64 * texel_bytes),
+            ("Colormap", res.colormap_buf.size()),
+            ("Magnet table", res.magnet_buf.size()),
+            ("Tracers", res.tracers.as_ref().map_or(0, |tracers| tracers.bytes)),
+            ("Surface mesh", res.surface_index_buf.size()),
+            ("Uniforms and stats", uniforms.iter().map(|buf| buf.size()).sum()),
+        ]
+    }
+
+    /// Format the output texture ended up in; `None` on the fragment-only path, which
+    /// draws straight to the screen.
+    pub fn output_format(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<OutputFormat> {
+        let renderer = wgpu_render_state.renderer.read();
+        let res = renderer.callback_resources.get::<GPUSimResources>()?;
+        match res._output_tex.0.format() {
+            TextureFormat::Rgba16Float => Some(OutputFormat::Rgba16Float),
+            _ => Some(OutputFormat::Rgba8),
+        }
+    }
+
+    /// Number of steps run since the simulation was created or restarted.
+    pub fn step_count(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> u64 {
+        let renderer = wgpu_render_state.renderer.read();
+        if self.fragment_only {
+            return renderer.callback_resources.get::<FallbackResources>().map_or(0, |res| res.step);
+        }
+        renderer.callback_resources.get::<GPUSimResources>().map_or(0, |res| res.step)
+    }
+
+    /// GPU time in milliseconds that the most recently measured frame spent in its
+    /// simulation passes. Each measurement is returned once; `None` if there is no new
+    /// one yet or the device lacks timestamp queries (see [`wgpu_configuration`]).
+    /// Measurements are read back asynchronously, so they lag a few frames behind.
+    pub fn take_compute_time(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<f32> {
+        wgpu_render_state
+            .renderer
+            .write()
+            .callback_resources
+            .get_mut::<GPUSimResources>()?
+            .timer
+            .as_mut()?
+            .last_ms
+            .take()
+    }
+
+    /// True if [`Self::take_compute_time`] can report anything on this device.
+    pub fn has_compute_timer(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> bool {
+        wgpu_render_state
+            .renderer
+            .read()
+            .callback_resources
+            .get::<GPUSimResources>()
+            .is_some_and(|res| res.timer.is_some())
+    }
+
+    /// Reads the output texture back as tightly packed RGBA8 rows at the simulation's
+    /// resolution, top row first as displayed. Float output is clamped to [0, 1] and
+    /// quantized, dithered if `params.dither` is set. Blocks until the GPU is done;
+    /// returns `None` on WebGPU, where [`Self::request_output`] works instead.
+    pub fn read_output(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<Vec<u8>> {
+        let pending = self.request_output(wgpu_render_state)?;
+        let padded = pending.readback.wait(&wgpu_render_state.device)?;
+        Some(pending.unpack(&padded))
+    }
+
+    /// Starts reading the output texture back without blocking, as it is after the
+    /// frames submitted so far. Poll the result with [`OutputReadback::poll`]; several
+    /// can be in flight at once. `None` on the fragment-only path.
+    pub fn request_output(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<OutputReadback> {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let renderer = wgpu_render_state.renderer.read();
+        let res = renderer.callback_resources.get::<GPUSimResources>()?;
+
+        // Rows in a texture-to-buffer copy must be padded to a multiple of 256 bytes
+        let float = res._output_tex.0.format() == TextureFormat::Rgba16Float;
+        let row_bytes = self._width * if float { 8 } else { 4 };
+        let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let size = (padded_row_bytes * self._height) as u64;
+        let readback = Readback::start(device, queue, "output readback", size, |encoder, staging| {
+            encoder.copy_texture_to_buffer(
+                res._output_tex.0.as_image_copy(),
+                wgpu::TexelCopyBufferInfo {
+                    buffer: staging,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_row_bytes),
+                        rows_per_image: None,
                     },
-                ],
+                },
+                res._output_tex.0.size(),
+            );
+        });
+        Some(OutputReadback { readback, float, row_bytes, padded_row_bytes, dither: self.params.dither != 0 })
+    }
+
+    /// What exported images of the current state embed to be reproducible.
+    pub fn image_metadata(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> ImageMetadata {
+        ImageMetadata {
+            params: self.params,
+            width: self._width,
+            height: self._height,
+            steps: self.step_count(wgpu_render_state),
+            colormap: Colormap::from_index(self.params.colormap).key(),
+            seed: self.params.seed as u64,
+        }
+    }
+
+    /// Writes the output texture to `path` as an RGBA8 PNG at the simulation's
+    /// resolution, independent of how large it is drawn. Blocks like [`Self::read_output`].
+    pub fn export_png(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, path: &Path) -> Result<(), ExportError> {
+        let rgba = self.read_output(wgpu_render_state).ok_or(ExportError::Readback)?;
+        std::fs::write(path, encode_png(&rgba, &self.image_metadata(wgpu_render_state))?)?;
+        Ok(())
+    }
+
+    /// Reads the particle state back in grid order: x fastest, bottom row first.
+    /// Blocks like [`Self::read_output`].
+    pub fn read_particles(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<Vec<Particle>> {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let renderer = wgpu_render_state.renderer.read();
+        let res = renderer.callback_resources.get::<GPUSimResources>()?;
+        let encoder = device.create_command_encoder(&Default::default());
+        let bytes = read_buffer(device, queue, encoder, &res.particle_buf)?;
+        Some(bytemuck::pod_collect_to_vec(&bytes))
+    }
+
+    /// Starts copying back the pendulum at column `x` and row `y` from the bottom,
+    /// without waiting for it; [`Self::poll_pendulum`] returns it once mapped. Replaces
+    /// any request still pending. False outside the grid and on the fragment-only path.
+    pub fn request_pendulum(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, x: u32, y: u32) -> bool {
+        if x >= self._width || y >= self._height {
+            return false;
+        }
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let mut renderer = wgpu_render_state.renderer.write();
+        let Some(res) = renderer.callback_resources.get_mut::<GPUSimResources>() else {
+            return false;
+        };
+        let size = std::mem::size_of::<Particle>() as u64;
+        let index = y as u64 * self._width as u64 + x as u64;
+        let readback = Readback::start(device, queue, "pendulum readback", size, |encoder, staging| {
+            encoder.copy_buffer_to_buffer(&res.particle_buf, index * size, staging, 0, size);
+        });
+        res.pendulum_request = Some(PendulumRequest { readback, cell: [x, y] });
+        true
+    }
+
+    /// The pendulum asked for with [`Self::request_pendulum`] once its copy has
+    /// mapped, and `None` until then. Never blocks, so call it each frame.
+    pub fn poll_pendulum(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<PendulumReadout> {
+        let mut renderer = wgpu_render_state.renderer.write();
+        let res = renderer.callback_resources.get_mut::<GPUSimResources>()?;
+        let request = res.pendulum_request.as_ref()?;
+        let std::task::Poll::Ready(bytes) = request.readback.poll(&wgpu_render_state.device) else {
+            return None;
+        };
+        let cell = request.cell;
+        res.pendulum_request = None;
+        let Some(bytes) = bytes else {
+            log::warn!("Failed to read back the pendulum at {cell:?}");
+            return None;
+        };
+        let p: Particle = bytemuck::pod_read_unaligned(&bytes);
+        let magnets = resolve_magnets(&self.params, res.magnets.as_deref());
+        let magnet = (p.is_active() && p.is_finite()).then(|| nearest_magnet(&magnets, p.u));
+        Some(PendulumReadout { cell, position: p.u, velocity: p.du, magnet })
+    }
+
+    /// Starts reading back the pendulums at `cells` (column, and row from the bottom)
+    /// without blocking, for [`Self::poll_scalars`]. Only copies those pendulums, so
+    /// it's cheap enough to repeat every few frames. Returns false if a cell is outside
+    /// the grid, a request is still in flight, or on the fragment-only path.
+    pub fn request_scalars(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, cells: &[[u32; 2]]) -> bool {
+        if cells.is_empty() || cells.iter().any(|&[x, y]| x >= self._width || y >= self._height) {
+            return false;
+        }
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let mut renderer = wgpu_render_state.renderer.write();
+        let Some(res) = renderer.callback_resources.get_mut::<GPUSimResources>() else {
+            return false;
+        };
+        if res.scalar_readback.is_some() {
+            return false;
+        }
+        let size = std::mem::size_of::<Particle>() as u64;
+        let readback = Readback::start(device, queue, "scalar readback", size * cells.len() as u64, |encoder, staging| {
+            for (i, &[x, y]) in cells.iter().enumerate() {
+                let index = y as u64 * self._width as u64 + x as u64;
+                encoder.copy_buffer_to_buffer(&res.particle_buf, index * size, staging, i as u64 * size, size);
+            }
+        });
+        res.scalar_readback = Some(readback);
+        true
+    }
+
+    /// The color scalars (see [`Params::color_scalar`]) of the pendulums asked for with
+    /// [`Self::request_scalars`], in the same order, once their copy has mapped, and
+    /// `None` until then. Never blocks, so call it each frame.
+    pub fn poll_scalars(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<Vec<f32>> {
+        let mut renderer = wgpu_render_state.renderer.write();
+        let res = renderer.callback_resources.get_mut::<GPUSimResources>()?;
+        let std::task::Poll::Ready(bytes) = res.scalar_readback.as_ref()?.poll(&wgpu_render_state.device) else {
+            return None;
+        };
+        res.scalar_readback = None;
+        let particles: Vec<Particle> = bytemuck::pod_collect_to_vec(&bytes?);
+        Some(particles.iter().map(|p| self.params.color_scalar(p)).collect())
+    }
+
+    /// The unquantized scalar behind the colors (see [`Params::color_scalar`]) per
+    /// pixel, top row first like [`Self::read_output`].
+    pub fn scalar_field(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<Vec<f32>> {
+        let particles = self.read_particles(wgpu_render_state)?;
+        Some(
+            particles
+                .chunks_exact(self._width as usize)
+                .rev()
+                .flatten()
+                .map(|p| self.params.color_scalar(p))
+                .collect(),
+        )
+    }
+
+    /// Runs `steps` more steps and classifies each pixel by the magnet its pendulum ends
+    /// up nearest to, top row first like [`Self::read_output`]. Masked out and diverged
+    /// pendulums are [`NO_BASIN`]. Blocks like [`Self::render_converged`].
+    pub fn compute_basins(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, steps: u32) -> Option<Vec<u32>> {
+        if self.is_fragment_only() {
+            return None;
+        }
+        let per_frame = self.steps_per_frame.max(1);
+        self.advance(wgpu_render_state, steps / per_frame);
+        let rest = steps % per_frame;
+        if rest > 0 {
+            GPUSim { steps_per_frame: rest, ..*self }.advance(wgpu_render_state, 1);
+        }
+        let particles = self.read_particles(wgpu_render_state)?;
+        let magnets = self.magnets(wgpu_render_state);
+        Some(
+            particles
+                .chunks_exact(self._width as usize)
+                .rev()
+                .flatten()
+                .map(|p| if p.is_active() && p.is_finite() { nearest_magnet(&magnets, p.u) } else { NO_BASIN })
+                .collect(),
+        )
+    }
+
+    /// Runs `frames` frames of [`Self::steps_per_frame`] steps without drawing them,
+    /// for headless use.
+    pub fn advance(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, frames: u32) {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let mut renderer = wgpu_render_state.renderer.write();
+        let Some(res) = renderer.callback_resources.get_mut::<GPUSimResources>() else {
+            return;
+        };
+        // One submit per frame, as the compute timer expects
+        for _ in 0..frames {
+            queue.submit([self.encode_frame(device, queue, res)]);
+        }
+    }
+
+    /// Runs until the run is finished (see [`Self::is_finished`]) and reads the image
+    /// back. The result says whether the run converged or stopped at `max_steps`.
+    /// Blocks throughout; returns `None` where readbacks aren't possible.
+    pub fn render_converged(
+        &self,
+        wgpu_render_state: &eframe::egui_wgpu::RenderState,
+        threshold: f32,
+        max_steps: u64,
+    ) -> Option<RenderResult> {
+        // The fragment-only path has no particle state to step or read back, and with
+        // dt = 0 the step count never reaches the budget
+        if self.is_fragment_only() || self.params.dt == 0.0 {
+            return None;
+        }
+        // Each convergence check is a readback, so check in batches
+        const CHECK_FRAMES: u64 = 50;
+        let steps_per_frame = self.steps_per_frame.max(1) as u64;
+        while !self.is_finished(wgpu_render_state, threshold, max_steps) {
+            let remaining = max_steps - self.step_count(wgpu_render_state);
+            self.advance(wgpu_render_state, remaining.div_ceil(steps_per_frame).min(CHECK_FRAMES) as u32);
+        }
+
+        let steps = self.step_count(wgpu_render_state);
+        let particles = self.read_particles(wgpu_render_state)?;
+        // Diverged pendulums never settle, and the max speed reduction leaves them out
+        let active = particles.iter().filter(|p| p.is_active() && p.is_finite()).count();
+        let settled = particles
+            .iter()
+            .filter(|p| p.is_active() && p.is_finite() && self.params.settle_speed(p) < threshold)
+            .count();
+        Some(RenderResult {
+            image: self.read_output(wgpu_render_state)?,
+            steps,
+            converged_frac: settled as f32 / active.max(1) as f32,
+            hit_cap: steps >= max_steps && settled < active,
+        })
+    }
+
+    /// Stopping criterion for a run: true once every active particle that hasn't diverged
+    /// is slower than `threshold` (see [`Params::settle_speed`]) or `max_steps` have been
+    /// run. In the conservative regime (see [`Params::is_conservative`]) nothing settles,
+    /// so only the budget applies.
+    pub fn is_finished(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, threshold: f32, max_steps: u64) -> bool {
+        self.step_count(wgpu_render_state) >= max_steps
+            || (!self.params.is_conservative() && self.is_steady(wgpu_render_state, threshold))
+    }
+
+    /// Returns true once the fastest particle moves slower than `threshold`.
+    ///
+    /// This reads the max-speed reduction back from the GPU, so it blocks until the
+    /// queued work has finished. The result is cached per step, so repeated calls
+    /// within a frame only pay for one readback.
+    pub fn is_steady(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, threshold: f32) -> bool {
+        self.max_speed(wgpu_render_state)
+            .is_some_and(|max_speed| max_speed < threshold)
+    }
+
+    /// Largest [`Params::settle_speed`] of the active pendulums that haven't diverged at
+    /// the current step, or `None` if the readback could not complete synchronously
+    /// (WebGPU only resolves maps from the event loop).
+    pub fn max_speed(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<f32> {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let mut renderer = wgpu_render_state.renderer.write();
+        let res = renderer.callback_resources.get_mut::<GPUSimResources>()?;
+        if let Some((step, max_speed)) = res.max_speed_cache {
+            if step == res.step {
+                return Some(max_speed);
+            }
+        }
+
+        let stats = self.reduce(device, queue, res, &res.reduce_pipeline, Stats::zeroed())?;
+        let max_speed = f32::from_bits(stats.max_speed);
+        res.max_speed_cache = Some((res.step, max_speed));
+        Some(max_speed)
+    }
+
+    /// Starts the [`Self::max_speed`] reduction without blocking, unless one is already
+    /// in flight. Its result arrives through [`Self::poll_max_speed`]. Returns false on
+    /// the fragment-only path.
+    pub fn request_max_speed(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> bool {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let mut renderer = wgpu_render_state.renderer.write();
+        let Some(res) = renderer.callback_resources.get_mut::<GPUSimResources>() else {
+            return false;
+        };
+        if res.speed_readback.is_none() {
+            let size = std::mem::size_of::<Stats>() as u64;
+            let readback = Readback::start(device, queue, "max speed readback", size, |encoder, staging| {
+                self.encode_reduction(queue, res, &res.reduce_pipeline, Stats::zeroed(), encoder);
+                encoder.copy_buffer_to_buffer(&res.stats_buf, 0, staging, 0, size);
+            });
+            res.speed_readback = Some((res.step, readback));
+        }
+        true
+    }
+
+    /// The step and max speed of the reduction started by [`Self::request_max_speed`]
+    /// once it has mapped, and `None` until then. Never blocks. A restart drops the
+    /// reduction in flight, since it measured the previous run.
+    pub fn poll_max_speed(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<(u64, f32)> {
+        let mut renderer = wgpu_render_state.renderer.write();
+        let res = renderer.callback_resources.get_mut::<GPUSimResources>()?;
+        let (step, readback) = res.speed_readback.as_ref()?;
+        let std::task::Poll::Ready(bytes) = readback.poll(&wgpu_render_state.device) else {
+            return None;
+        };
+        let step = *step;
+        res.speed_readback = None;
+        let stats: Stats = bytemuck::pod_read_unaligned(&bytes?);
+        let max_speed = f32::from_bits(stats.max_speed);
+        res.max_speed_cache = Some((step, max_speed));
+        Some((step, max_speed))
+    }
+
+    /// [`Self::is_finished`] without blocking, judged by the reduction started by
+    /// [`Self::request_max_speed`]: `None` until it has mapped.
+    pub fn poll_finished(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState, threshold: f32, max_steps: u64) -> Option<bool> {
+        let (step, max_speed) = self.poll_max_speed(wgpu_render_state)?;
+        Some(step >= max_steps || (!self.params.is_conservative() && max_speed < threshold))
+    }
+
+    /// Number of pendulums whose state has gone non-finite since the start of the run,
+    /// as of the last submitted frame. Blocks like [`Self::max_speed`]; `None` on the
+    /// fragment-only path, which keeps no state to count.
+    pub fn diverged_count(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<u32> {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let renderer = wgpu_render_state.renderer.read();
+        let res = renderer.callback_resources.get::<GPUSimResources>()?;
+        let encoder = device.create_command_encoder(&Default::default());
+        let bytes = read_buffer(device, queue, encoder, &res.stats_buf)?;
+        Some(bytemuck::pod_read_unaligned::<Stats>(&bytes[..std::mem::size_of::<Stats>()]).diverged)
+    }
+
+    /// [`Self::diverged_count`] without blocking, for calling every frame. Returns the
+    /// count once a readback has arrived, and otherwise `None` after starting one if
+    /// none is in flight. The count is as of the frame the readback started in.
+    pub fn poll_diverged_count(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<u32> {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let mut renderer = wgpu_render_state.renderer.write();
+        let res = renderer.callback_resources.get_mut::<GPUSimResources>()?;
+        let Some(readback) = &res.diverged_readback else {
+            let size = std::mem::size_of::<Stats>() as u64;
+            res.diverged_readback = Some(Readback::start(device, queue, "diverged readback", size, |encoder, staging| {
+                encoder.copy_buffer_to_buffer(&res.stats_buf, 0, staging, 0, size);
+            }));
+            return None;
+        };
+        let std::task::Poll::Ready(bytes) = readback.poll(device) else {
+            return None;
+        };
+        res.diverged_readback = None;
+        Some(bytemuck::pod_read_unaligned::<Stats>(&bytes?).diverged)
+    }
+
+    /// Smallest and largest value of the scalar selected by `params.color_mode` at the
+    /// current step over the pendulums that haven't diverged, for auto-ranging the
+    /// colormap. Blocks like [`Self::max_speed`].
+    pub fn scalar_range(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<(f32, f32)> {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let renderer = wgpu_render_state.renderer.read();
+        let res = renderer.callback_resources.get::<GPUSimResources>()?;
+        let init = Stats { scalar_min: f32::MAX.to_bits(), ..Stats::zeroed() };
+        let stats = self.reduce(device, queue, res, &res.range_pipeline, init)?;
+        Some((f32::from_bits(stats.scalar_min), f32::from_bits(stats.scalar_max)))
+    }
+
+    /// Starts the [`Self::scalar_range`] reduction without blocking, unless one is
+    /// already in flight. Its result arrives through [`Self::poll_scalar_range`].
+    /// Returns false on the fragment-only path.
+    pub fn request_scalar_range(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> bool {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let mut renderer = wgpu_render_state.renderer.write();
+        let Some(res) = renderer.callback_resources.get_mut::<GPUSimResources>() else {
+            return false;
+        };
+        if res.range_readback.is_none() {
+            let size = std::mem::size_of::<Stats>() as u64;
+            let init = Stats { scalar_min: f32::MAX.to_bits(), ..Stats::zeroed() };
+            let readback = Readback::start(device, queue, "scalar range readback", size, |encoder, staging| {
+                self.encode_reduction(queue, res, &res.range_pipeline, init, encoder);
+                encoder.copy_buffer_to_buffer(&res.stats_buf, 0, staging, 0, size);
+            });
+            res.range_readback = Some(readback);
+        }
+        true
+    }
+
+    /// The range of the reduction started by [`Self::request_scalar_range`] once it has
+    /// mapped, and `None` until then. Never blocks. A restart drops the reduction in
+    /// flight, like [`Self::poll_max_speed`].
+    pub fn poll_scalar_range(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<(f32, f32)> {
+        let mut renderer = wgpu_render_state.renderer.write();
+        let res = renderer.callback_resources.get_mut::<GPUSimResources>()?;
+        let std::task::Poll::Ready(bytes) = res.range_readback.as_ref()?.poll(&wgpu_render_state.device) else {
+            return None;
+        };
+        res.range_readback = None;
+        let stats: Stats = bytemuck::pod_read_unaligned(&bytes?);
+        Some((f32::from_bits(stats.scalar_min), f32::from_bits(stats.scalar_max)))
+    }
+
+    /// Total kinetic plus potential energy of the active pendulums at the current step
+    /// (see [`Params::energy`]), leaving out diverged ones. Without friction it stays
+    /// constant up to integrator error; with friction it only decreases. Blocks like
+    /// [`Self::max_speed`].
+    pub fn total_energy(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<f64> {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let renderer = wgpu_render_state.renderer.read();
+        let res = renderer.callback_resources.get::<GPUSimResources>()?;
+        let bytes = self.reduce_bytes(device, queue, res, &res.energy_pipeline, Stats::zeroed())?;
+        let groups = (stats_size(self._width, self._height) as usize - std::mem::size_of::<Stats>()) / 4;
+        let sums = bytes[std::mem::size_of::<Stats>()..].chunks_exact(4).take(groups);
+        Some(sums.map(|sum| f32::from_le_bytes(sum.try_into().unwrap()) as f64).sum())
+    }
+
+    // Records one frame of simulation steps, running the step hook and uploading the
+    // params and other per-frame uniforms
+    fn encode_frame(&self, device: &wgpu::Device, queue: &wgpu::Queue, res: &mut GPUSimResources) -> wgpu::CommandBuffer {
+        let mut params = self.params;
+        // While paused, one dt = 0 dispatch redraws the texture when needed
+        let steps = if params.dt != 0.0 { self.steps_per_frame.max(1) } else { 1 };
+        if params.dt != 0.0 {
+            if let Some(on_step) = &mut res.on_step {
+                on_step(&mut params, res.step);
+            }
+            res.step += steps as u64;
+        }
+        res.frames = res.frames.wrapping_add(1);
+        params.frame = res.frames;
+        params.scale = self._scale;
+        params.sanitize_quietly();
+        let magnets_changed = upload_magnets(queue, &res.magnet_buf, 0, &mut params, res.magnets.as_deref(), &mut res.magnet_table);
+        queue.write_buffer(&res.param_buffer, 0, bytemuck::cast_slice(&[params]));
+        // A paused frame skips the compute pass unless something it draws changed, so
+        // an idle sim leaves the GPU idle and the texture exactly as it was. Pausing
+        // itself only zeroes dt, which changes nothing drawn. The frame count only
+        // moves the temporal dither, which fs_main draws
+        let shown = Params { dt: 0.0, frame: 0, ..params };
+        let redraw = params.dt != 0.0
+            || magnets_changed
+            || res.drawn.is_none_or(|drawn| bytemuck::bytes_of(&drawn) != bytemuck::bytes_of(&shown));
+        res.drawn = Some(shown);
+        // Every slot has the same size, so switching maps is a write into the buffer
+        // the bind group already holds
+        let maps = [params.colormap, params.secondary_colormap].map(Colormap::from_index);
+        for (slot, (map, uploaded)) in maps.into_iter().zip([&mut res.primary_colormap, &mut res.secondary_colormap]).enumerate() {
+            if map != *uploaded {
+                let offset = (slot * COLORMAP_LEN * std::mem::size_of::<[f32; 4]>()) as u64;
+                queue.write_buffer(&res.colormap_buf, offset, bytemuck::cast_slice(&colormap_slot(map)));
+                *uploaded = map;
+            }
+        }
+        if let Some(camera) = &self.surface {
+            queue.write_buffer(&res.surface_buf, 0, bytemuck::bytes_of(&camera.uniform(params.w, params.h)));
+        }
+        if let Some(tracers) = &mut res.tracers {
+            if params.dt != 0.0 {
+                tracers.info.head = (tracers.info.head + steps) % tracers.info.trail_len;
+                tracers.info.steps = steps;
+                queue.write_buffer(&tracers.info_buf, 0, bytemuck::bytes_of(&tracers.info));
+            }
+        }
+
+        let timed = redraw && res.timer.as_mut().is_some_and(|timer| timer.poll());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        if redraw {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute pass"),
+                timestamp_writes: res.timer.as_ref().filter(|_| timed).map(|timer| {
+                    wgpu::ComputePassTimestampWrites {
+                        query_set: &timer.query_set,
+                        beginning_of_pass_write_index: Some(0),
+                        end_of_pass_write_index: Some(1),
+                    }
+                }),
             });
+            cpass.set_bind_group(0, &res.bind_group, &[]);
+            // Paused frames keep showing the last frame's activity
+            if params.color_mode == 3 && params.dt != 0.0 {
+                cpass.set_pipeline(&res.mark_pipeline);
+                cpass.dispatch_workgroups(params.w.div_ceil(16), params.h.div_ceil(16), 1);
+            }
+            cpass.set_pipeline(&res.compute_pipeline);
+            // Each dispatch sees the particle writes of the one before. comp_main
+            // runs 16×16 particles per group and bounds-checks the edge tiles
+            for _ in 0..steps {
+                cpass.dispatch_workgroups(params.w.div_ceil(16), params.h.div_ceil(16), 1);
+            }
+
+            if let Some(tracers) = &res.tracers {
+                if params.dt != 0.0 {
+                    cpass.set_pipeline(&res.trace_pipeline);
+                    cpass.set_bind_group(0, &tracers.compute_bg, &[]);
+                    cpass.dispatch_workgroups(tracers.info.count.div_ceil(64), 1, 1);
+                }
+            }
+        }
+        if let Some(timer) = res.timer.as_mut().filter(|_| timed) {
+            timer.resolve(&mut encoder);
+        }
+        encoder.finish()
+    }
+
+    // Runs a reduction over the particle grid into stats starting from `init`, and
+    // reads the result back
+    fn reduce(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        res: &GPUSimResources,
+        pipeline: &wgpu::ComputePipeline,
+        init: Stats,
+    ) -> Option<Stats> {
+        let bytes = self.reduce_bytes(device, queue, res, pipeline, init)?;
+        Some(bytemuck::pod_read_unaligned(&bytes[..std::mem::size_of::<Stats>()]))
+    }
 
-            let new_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &bg_layout,
-                label: Some("Resources described by the bind_group_layout"),
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                            buffer: &resources.param_buffer,
-                            offset: 0,
-                            size: NonZeroU64::new(std::mem::size_of::<Params>() as u64),
-                        }),
+    // As `reduce`, returning the whole stats buffer including the per-workgroup sums
+    fn reduce_bytes(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        res: &GPUSimResources,
+        pipeline: &wgpu::ComputePipeline,
+        init: Stats,
+    ) -> Option<Vec<u8>> {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        self.encode_reduction(queue, res, pipeline, init, &mut encoder);
+        read_buffer(device, queue, encoder, &res.stats_buf)
+    }
+
+    // Records a reduction into stats, starting from `init`
+    fn encode_reduction(
+        &self,
+        queue: &wgpu::Queue,
+        res: &GPUSimResources,
+        pipeline: &wgpu::ComputePipeline,
+        init: Stats,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let counters = std::mem::offset_of!(Stats, diverged);
+        queue.write_buffer(&res.stats_buf, 0, &bytemuck::bytes_of(&init)[..counters]);
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Reduction pass"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(pipeline);
+        cpass.set_bind_group(0, &res.bind_group, &[]);
+        cpass.dispatch_workgroups(self._width.div_ceil(16), self._height.div_ceil(16), 1);
+    }
+
+    /// Counts, per magnet, the pendulums nearest to it by how many times they have come
+    /// within `d` of any magnet; the last of the [`ORBIT_BINS`] bins holds that many
+    /// or more. Once settled this is the number of near passes up to and including
+    /// the capture: 1 for a direct capture, more for pendulums that orbited first.
+    /// Blocks like [`Self::max_speed`].
+    pub fn orbit_histogram(&self, wgpu_render_state: &eframe::egui_wgpu::RenderState) -> Option<Vec<[u32; ORBIT_BINS]>> {
+        let (device, queue) = (&wgpu_render_state.device, &wgpu_render_state.queue);
+        let renderer = wgpu_render_state.renderer.read();
+        let res = renderer.callback_resources.get::<GPUSimResources>()?;
+        let stats = self.reduce(device, queue, res, &res.orbit_pipeline, Stats::zeroed())?;
+        let hist = stats.orbit_hist.chunks_exact(ORBIT_BINS).take(self.params.n as usize);
+        Some(hist.map(|bins| bins.try_into().unwrap()).collect())
+    }
+
+    /// Paint callback for an inset that magnifies the output texture around
+    /// `center` (in texture coordinates) by `zoom` relative to a quad of
+    /// `inset_frac` times the main view's size.
+    pub fn magnifier(&self, center: Vec2, zoom: f32, inset_frac: f32) -> Magnifier {
+        let scale = Vec2::splat(inset_frac / zoom);
+        Magnifier {
+            window: TexWindow {
+                offset: center - scale / 2.0,
+                scale,
+            },
+        }
+    }
+
+    fn create_output_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let tex = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("magpen texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                ..Default::default()
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::RENDER_ATTACHMENT, // for `clear_output`
+            view_formats: &[format],
+        });
+        let texview = tex.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("magpen texture id"),
+            ..Default::default()
+        });
+        (tex, texview)
+    }
+
+    fn create_render_bg(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        tex_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        param_buffer: &wgpu::Buffer,
+        window_buf: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            label: Some("Resources described by the render_bg_layout"),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(tex_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: window_buf.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    fn create_surface_bg(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        param_buffer: &wgpu::Buffer,
+        particle_buf: &wgpu::Buffer,
+        surface_buf: &wgpu::Buffer,
+        tex_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            label: Some("Resources described by the surface layout"),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: surface_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: wgpu::BindingResource::TextureView(tex_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    fn create_bg_layout(device: &wgpu::Device, output_format: TextureFormat) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Avaialable Buffers"),
+            entries: &[
+                // Simulation Parameters
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE
+                        | wgpu::ShaderStages::VERTEX
+                        | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Old Particle data
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                            buffer: &new_particle_buf,
-                            offset: 0,
-                            size: NonZeroU64::new(
-                                (particles.len() * std::mem::size_of::<Particle>()) as u64,
-                            ),
-                        }),
+                    count: None,
+                },
+                // The texture
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: output_format,
+                        view_dimension: wgpu::TextureViewDimension::D2,
                     },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::TextureView(&resources._output_tex.1),
+                    count: None,
+                },
+                // Colormap
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Reduction results
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                            buffer: &colormap_buf,
-                            offset: 0,
-                            size: NonZeroU64::new(
-                                (TWILIGHT_MAP.len() * std::mem::size_of::<[f32; 4]>()) as u64,
-                            ),
-                        }),
+                    count: None,
+                },
+                // Magnet table
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
-                ],
-            });
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn create_tracer_bg_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tracer Buffers"),
+            entries: &[
+                uniform(0), // simulation parameters
+                storage(5, false), // tracer pendulums
+                storage(6, false), // trail rings
+                uniform(7), // tracer info
+                storage(13, true), // magnet table
+            ],
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_bind_group(
+        device: &wgpu::Device,
+        bg_layout: &wgpu::BindGroupLayout,
+        param_buffer: &wgpu::Buffer,
+        particle_buf: &wgpu::Buffer,
+        tex_view: &wgpu::TextureView,
+        colormap_buf: &wgpu::Buffer,
+        stats_buf: &wgpu::Buffer,
+        magnet_buf: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: bg_layout,
+            label: Some("Resources described by the bind_group_layout"),
+            entries: &[
+                // params
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: param_buffer,
+                        offset: 0,
+                        size: NonZeroU64::new(std::mem::size_of::<Params>() as u64),
+                    }),
+                },
+                // particles
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: particle_buf,
+                        offset: 0,
+                        size: NonZeroU64::new(particle_buf.size()),
+                    }),
+                },
+                // Current Particle data
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(tex_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: colormap_buf,
+                        offset: 0,
+                        size: NonZeroU64::new((COLORMAP_SLOTS * COLORMAP_LEN * std::mem::size_of::<[f32; 4]>()) as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: stats_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: magnet_buf.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
+
+// Mirrors `bayer4` in the shader: the 4x4 ordered dither threshold at a pixel, in [0, 1)
+fn bayer4(x: u32, y: u32) -> f32 {
+    const M: [u32; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+    M[((x & 3) + (y & 3) * 4) as usize] as f32 / 16.0
+}
+
+// Decodes an IEEE half float
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24), // subnormal
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// CPU reference for the shader's integrators: advances `p` by one step of
+/// `params.dt` among `magnets` (see [`GPUSim::magnets`]) with the integrator
+/// `params.integrator` selects. Near-pass counting is left to the GPU.
+pub fn step_particle(p: &Particle, params: &Params, magnets: &[Magnet]) -> Particle {
+    let dt = params.dt;
+    let mut q = *p;
+    if params.integrator == 2 {
+        q = step_adaptive(p, params, magnets);
+    } else if params.integrator == 1 {
+        let k1u = p.du;
+        let k1v = accel(p.u, p.du, p.mass, params, magnets);
+        let k2u = p.du + 0.5 * dt * k1v;
+        let k2v = accel(p.u + 0.5 * dt * k1u, k2u, p.mass, params, magnets);
+        let k3u = p.du + 0.5 * dt * k2v;
+        let k3v = accel(p.u + 0.5 * dt * k2u, k3u, p.mass, params, magnets);
+        let k4u = p.du + dt * k3v;
+        let k4v = accel(p.u + dt * k3u, k4u, p.mass, params, magnets);
+        q.u += dt / 6.0 * (k1u + 2.0 * k2u + 2.0 * k3u + k4u);
+        q.du += dt / 6.0 * (k1v + 2.0 * k2v + 2.0 * k3v + k4v);
+    } else {
+        q.du += accel(p.u, p.du, p.mass, params, magnets) * dt;
+        q.u += q.du * dt;
+    }
+    tick(q, params)
+}
+
+// Dormand–Prince 5(4) tableau: nodes are implied, rows of A below the diagonal
+const DOPRI_A: [[f32; 6]; 7] = [
+    [0.0; 6],
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0, 0.0, 0.0],
+    [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0, 0.0],
+    [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+// Fifth minus embedded fourth order weights, for the error estimate
+const DOPRI_E: [f32; 7] = [
+    71.0 / 57600.0, 0.0, -71.0 / 16695.0, 71.0 / 1920.0, -17253.0 / 339200.0, 22.0 / 525.0, -1.0 / 40.0,
+];
+
+// Mirrors `dopri_step` in the shader: the fifth order solution after h, and the
+// size of its local error relative to the state's
+fn dopri_step(u: Vec2, du: Vec2, mass: f32, h: f32, params: &Params, magnets: &[Magnet]) -> (Vec2, Vec2, f32) {
+    let mut ku = [Vec2::ZERO; 7];
+    let mut kv = [Vec2::ZERO; 7];
+    for (i, a) in DOPRI_A.iter().enumerate() {
+        let (su, sv) = (0..i).fold((u, du), |(su, sv), j| (su + h * a[j] * ku[j], sv + h * a[j] * kv[j]));
+        ku[i] = sv;
+        kv[i] = accel(su, sv, mass, params, magnets);
+    }
+    // The last stage is evaluated at the fifth order solution
+    let (u5, du5) = (u + h * (0..6).map(|j| DOPRI_A[6][j] * ku[j]).sum::<Vec2>(), ku[6]);
+    let eu = h * (0..7).map(|j| DOPRI_E[j] * ku[j]).sum::<Vec2>();
+    let ev = h * (0..7).map(|j| DOPRI_E[j] * kv[j]).sum::<Vec2>();
+    let err = eu.length().max(ev.length()) / (1.0 + u.length().max(du.length()));
+    (u5, du5, err)
+}
+
+// Mirrors `step_adaptive` in the shader
+fn step_adaptive(p: &Particle, params: &Params, magnets: &[Magnet]) -> Particle {
+    let mut q = *p;
+    let mut remaining = params.dt;
+    let mut h = params.dt;
+    for i in 0..params.max_substeps {
+        if remaining <= 0.0 {
+            break;
+        }
+        let last = i + 1 == params.max_substeps;
+        h = if last { remaining } else { h.min(remaining) };
+        let (u, du, err) = dopri_step(q.u, q.du, q.mass, h, params, magnets);
+        if err <= params.tolerance || last {
+            (q.u, q.du) = (u, du);
+            remaining -= h;
+        }
+        h *= (0.9 * (params.tolerance / err.max(1e-30)).powf(0.2)).clamp(0.2, 5.0);
+    }
+    q
+}
+
+// Mirrors `tick` in the shader
+fn tick(p: Particle, params: &Params) -> Particle {
+    let mut next = p;
+    next.age += params.dt;
+    let span = next.age - p.anchor_age;
+    if params.dt > 0.0 && span >= (params.settle_window as f32 - 0.5) * params.dt {
+        next.drift = (next.u - next.anchor).length() / span;
+        next.anchor = next.u;
+        next.anchor_age = next.age;
+    }
+    if params.settle_speed(&next) > SETTLED_SPEED {
+        next.settled_at = next.age;
+    }
+    next
+}
+
+// Mirrors `magnet_pull` in the shader, keeping its inverse-cube form at the default softness
+fn magnet_pull(diff: Vec2, d2: f32, softness: f32) -> Vec2 {
+    if softness == 3.0 {
+        let sq = (diff.length_squared() + d2).sqrt();
+        return diff / (sq * sq * sq);
+    }
+    diff * (diff.length_squared() + d2).powf(-0.5 * softness)
+}
+
+// Mirrors `accel` in the shader
+fn accel(u: Vec2, du: Vec2, mass: f32, params: &Params, magnets: &[Magnet]) -> Vec2 {
+    let d2 = params.d * params.d;
+    let cutoff2 = params.cutoff * params.cutoff;
+    let mut ddu = Vec2::ZERO;
+    for magnet in magnets {
+        let diff = magnet.position - u;
+        if cutoff2 > 0.0 && diff.length_squared() > cutoff2 {
+            continue;
+        }
+        if magnet.strength == 1.0 {
+            ddu += magnet_pull(diff, d2, params.softness);
+        } else {
+            ddu += magnet.strength * magnet_pull(diff, d2, params.softness);
+        }
+    }
+    ddu -= params.mu * du + params.spring_constants() * (1.0 + params.spring_cubic * u.length_squared()) * u;
+    if mass == 1.0 {
+        return ddu;
+    }
+    ddu / mass
+}
+
+// Starting velocity of the pendulum at `u` for the chosen velocity pattern
+fn initial_velocity(u: Vec2, params: &Params) -> Vec2 {
+    match params.velocity_pattern {
+        // Radial pattern: velocity points away from center
+        0 => if u.length() > 0.001 {
+            params.velocity_magnitude * u.normalize()
+                .rotate(Vec2::from_angle(params.velocity_angle))
+        } else {
+            Vec2::from_angle(params.velocity_angle) * params.velocity_magnitude
+        }
+        // Tangential pattern: velocity perpendicular to position
+        1 => if u.length() > 0.001 {
+            params.velocity_magnitude * Vec2::new(-u.y, u.x).normalize()
+                .rotate(Vec2::from_angle(params.velocity_angle))
+        } else {
+            Vec2::from_angle(params.velocity_angle + PI / 2.0) * params.velocity_magnitude
+        }
+        // Uniform direction: all particles have same velocity direction
+        2 => Vec2::from_angle(params.velocity_angle) * params.velocity_magnitude,
+        _ => Vec2::ZERO
+    }
+}
+
+// Sub-pixel offset in [0, 1)² of a pendulum's starting point within its pixel.
+// Each pattern moves to a new offset per `jitter_sample`, so averaging successive
+// samples supersamples the image.
+fn jitter_offset(params: &Params, x: u32, y: u32) -> Vec2 {
+    let sample = params.jitter_sample;
+    match params.jitter_pattern {
+        // White noise: independent per pixel and sample
+        1 => hash2(x, y, sample, params.seed),
+        // Halton (2, 3) sequence over samples, decorrelated between pixels by a
+        // random per-pixel rotation
+        2 => {
+            let halton = vec2(radical_inverse(sample + 1, 2), radical_inverse(sample + 1, 3));
+            (halton + hash2(x, y, 0, params.seed)).fract()
+        }
+        // Interleaved gradient noise (Jimenez 2014), stepped along the R2 sequence per
+        // sample. A closed-form stand-in for a blue-noise texture: its neighbouring
+        // offsets differ a lot, but its spectrum isn't truly blue
+        3 => {
+            let (fx, fy) = (x as f32, y as f32);
+            let ign = |px: f32, py: f32| (52.982_918 * (0.067_110_56 * px + 0.005_837_15 * py).fract()).fract();
+            let noise = vec2(ign(fx, fy), ign(fx + 5.588_238, fy + 5.588_238));
+            (noise + sample as f32 * vec2(0.754_877_7, 0.569_840_3)).fract()
+        }
+        _ => Vec2::ZERO,
+    }
+}
+
+// Van der Corput radical inverse of `i` in `base`, the components of the Halton sequence
+fn radical_inverse(mut i: u32, base: u32) -> f32 {
+    let (mut result, mut f) = (0.0, 1.0 / base as f32);
+    while i > 0 {
+        result += f * (i % base) as f32;
+        i /= base;
+        f /= base as f32;
+    }
+    result
+}
+
+// Two uniform floats in [0, 1) hashed from the inputs (PCG output permutation)
+fn hash2(x: u32, y: u32, sample: u32, seed: u32) -> Vec2 {
+    fn pcg(v: u32) -> u32 {
+        let state = v.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+        let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277_803_737);
+        (word >> 22) ^ word
+    }
+    let h1 = pcg(x ^ pcg(y ^ pcg(sample ^ pcg(seed))));
+    let h2 = pcg(h1);
+    vec2((h1 >> 8) as f32, (h2 >> 8) as f32) / (1 << 24) as f32
+}
+
+/// eframe wgpu setup that also requests timestamp queries where the adapter supports
+/// them, so [`GPUSim::take_compute_time`] can measure the simulation passes.
+pub fn wgpu_configuration() -> eframe::egui_wgpu::WgpuConfiguration {
+    let setup = eframe::egui_wgpu::WgpuSetupCreateNew::default();
+    let base = setup.device_descriptor.clone();
+    eframe::egui_wgpu::WgpuConfiguration {
+        wgpu_setup: eframe::egui_wgpu::WgpuSetupCreateNew {
+            device_descriptor: Arc::new(move |adapter| {
+                let mut descriptor = base(adapter);
+                descriptor.required_features |= adapter.features() & wgpu::Features::TIMESTAMP_QUERY;
+                // Large grids need more than the default limits; see `GPUSim::fits_device`
+                let limits = adapter.limits();
+                descriptor.required_limits.max_texture_dimension_2d = limits.max_texture_dimension_2d;
+                descriptor.required_limits.max_storage_buffer_binding_size = limits.max_storage_buffer_binding_size;
+                descriptor.required_limits.max_buffer_size = limits.max_buffer_size;
+                descriptor
+            }),
+            ..setup
+        }
+        .into(),
+        ..Default::default()
+    }
+}
+
+/// A render state on the default adapter with no window or surface, for batch
+/// rendering and tests. `None` when no adapter or device is available.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn headless_render_state() -> Option<eframe::egui_wgpu::RenderState> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).ok()?;
+    let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+        required_limits: adapter.limits(),
+        ..Default::default()
+    }))
+    .ok()?;
+    let target_format = TextureFormat::Rgba8Unorm;
+    let renderer = eframe::egui_wgpu::Renderer::new(&device, target_format, None, 1, false);
+    Some(eframe::egui_wgpu::RenderState {
+        adapter,
+        available_adapters: vec![],
+        device,
+        queue,
+        target_format,
+        renderer: Arc::new(mutex::RwLock::new(renderer)),
+    })
+}
+
+// wgpu resolves adapter and device requests immediately on native backends
+#[cfg(not(target_arch = "wasm32"))]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let std::task::Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+            return value;
+        }
+    }
+}
+
+// Times a frame's compute pass with timestamp queries. The results are mapped
+// asynchronously (blocking would stall the frame, and WebGPU can't block at all), so
+// each measurement goes resolve → map → read over the following frames, and no new
+// one starts until the previous has been read.
+struct ComputeTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buf: wgpu::Buffer,
+    readback_buf: wgpu::Buffer,
+    state: TimerState,
+    map_status: Arc<AtomicU8>, // MAP_PENDING, MAP_OK or MAP_FAILED
+    period_ns: f32,            // nanoseconds per timestamp tick
+    last_ms: Option<f32>,
+}
+
+enum TimerState {
+    Idle,
+    Resolved, // copy to the readback buffer recorded, not yet mapped
+    Mapping,
+}
+
+const MAP_PENDING: u8 = 0;
+const MAP_OK: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+impl ComputeTimer {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = 2 * std::mem::size_of::<u64>() as u64;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("compute timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve_buf: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("timestamp resolve"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buf: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("timestamp readback"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            state: TimerState::Idle,
+            map_status: Arc::new(AtomicU8::new(MAP_PENDING)),
+            period_ns: queue.get_timestamp_period(),
+            last_ms: None,
+        }
+    }
+
+    // Advances the readback of the previous measurement. Returns true if this frame's
+    // pass can be timed. Only call once the frame that resolved has been submitted.
+    fn poll(&mut self) -> bool {
+        match self.state {
+            TimerState::Idle => true,
+            TimerState::Resolved => {
+                let status = self.map_status.clone();
+                status.store(MAP_PENDING, Ordering::Release);
+                self.readback_buf.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                    status.store(if result.is_ok() { MAP_OK } else { MAP_FAILED }, Ordering::Release);
+                });
+                self.state = TimerState::Mapping;
+                false
+            }
+            TimerState::Mapping => match self.map_status.load(Ordering::Acquire) {
+                MAP_PENDING => false,
+                status => {
+                    if status == MAP_OK {
+                        let ticks: [u64; 2] = bytemuck::pod_read_unaligned(&self.readback_buf.slice(..).get_mapped_range());
+                        self.readback_buf.unmap();
+                        self.last_ms = Some(ticks[1].wrapping_sub(ticks[0]) as f32 * self.period_ns / 1e6);
+                    }
+                    self.state = TimerState::Idle;
+                    true
+                }
+            },
+        }
+    }
+
+    fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buf, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buf, 0, &self.readback_buf, 0, self.resolve_buf.size());
+        self.state = TimerState::Resolved;
+    }
+}
+
+impl Readback {
+    // Records `copy` from the GPU into a new `size`-byte staging buffer, submits it and
+    // starts mapping the result
+    fn start(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+        size: u64,
+        copy: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::Buffer),
+    ) -> Self {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        copy(&mut encoder, &staging);
+        queue.submit([encoder.finish()]);
 
-            resources.bind_group = new_bind_group;
+        let map_status = Arc::new(AtomicU8::new(MAP_PENDING));
+        let status = map_status.clone();
+        staging.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            status.store(if result.is_ok() { MAP_OK } else { MAP_FAILED }, Ordering::Release);
+        });
+        Self { staging, map_status }
+    }
+
+    // The copied bytes once mapped, or `None` inside `Ready` if the map failed. Never
+    // blocks
+    fn poll(&self, device: &wgpu::Device) -> std::task::Poll<Option<Vec<u8>>> {
+        // Native backends only run map callbacks from a device poll
+        let _ = device.poll(wgpu::PollType::Poll);
+        match self.map_status.load(Ordering::Acquire) {
+            MAP_PENDING => std::task::Poll::Pending,
+            MAP_OK => {
+                let bytes = self.staging.slice(..).get_mapped_range().to_vec();
+                self.staging.unmap();
+                std::task::Poll::Ready(Some(bytes))
+            }
+            _ => std::task::Poll::Ready(None),
+        }
+    }
+
+    // Like `poll`, but waits for the map. WebGPU can't block, so there the map never
+    // completes in time and this returns None
+    fn wait(&self, device: &wgpu::Device) -> Option<Vec<u8>> {
+        if let Err(e) = device.poll(wgpu::PollType::Wait) {
+            log::warn!("Failed to wait for readback: {e}");
+        }
+        match self.poll(device) {
+            std::task::Poll::Ready(bytes) => bytes,
+            std::task::Poll::Pending => None,
+        }
+    }
+}
+
+// Copies `src` into a staging buffer and waits for it to map.
+fn read_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mut encoder: wgpu::CommandEncoder,
+    src: &wgpu::Buffer,
+) -> Option<Vec<u8>> {
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback staging"),
+        size: src.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    encoder.copy_buffer_to_buffer(src, 0, &staging, 0, src.size());
+    queue.submit(Some(encoder.finish()));
+    map_blocking(device, &staging)
+}
+
+// Waits for `staging` to map and copies it out. WebGPU can't block on the map, so
+// there the callback never fires in time and this returns None.
+fn map_blocking(device: &wgpu::Device, staging: &wgpu::Buffer) -> Option<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    staging.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+    if let Err(e) = device.poll(wgpu::PollType::Wait) {
+        log::warn!("Failed to wait for readback: {e}");
+    }
+    match rx.try_recv() {
+        Ok(Ok(())) => {
+            let bytes = staging.slice(..).get_mapped_range().to_vec();
+            staging.unmap();
+            Some(bytes)
         }
+        Ok(Err(e)) => {
+            log::warn!("Failed to map readback buffer: {e}");
+            None
+        }
+        Err(_) => None,
     }
 }
 
@@ -502,20 +3502,80 @@ impl eframe::egui_wgpu::CallbackTrait for GPUSim {
         _egui_encoder: &mut wgpu::CommandEncoder,
         callback_resources: &mut eframe::egui_wgpu::CallbackResources,
     ) -> Vec<wgpu::CommandBuffer> {
+        if self.fragment_only {
+            let res: &mut FallbackResources = callback_resources.get_mut().unwrap();
+            let mut params = self.params;
+            params.sanitize_quietly();
+            res.prepare(queue, &params, self.steps_per_frame.max(1));
+            return Vec::new();
+        }
+
+        // Synchronization with `paint`: eframe submits the command buffers returned here
+        // in the same `Queue::submit` as egui's render pass and ahead of it, and the
+        // uniform writes in `encode_frame` land before either. wgpu tracks the output
+        // texture's switch from storage write to sampled read across the two and
+        // inserts the barrier itself, so the fragment shader always samples this
+        // frame's compute output. A second GPUSim callback in one frame would break
+        // this, as both would run with whichever params were written last
+        let res: &mut GPUSimResources = callback_resources.get_mut().unwrap();
+        vec![self.encode_frame(device, queue, res)]
+    }
+
+    fn paint<'a, 'b, 'c>(
+        &'a self,
+        _info: PaintCallbackInfo,
+        render_pass: &mut wgpu::RenderPass<'b>,
+        callback_resources: &'c eframe::egui_wgpu::CallbackResources,
+    ) {
+        if self.fragment_only {
+            let res: &FallbackResources = callback_resources.get().unwrap();
+            res.paint(render_pass);
+            return;
+        }
+
         let res: &GPUSimResources = callback_resources.get().unwrap();
-        queue.write_buffer(&res.param_buffer, 0, bytemuck::cast_slice(&[self.params]));
-        let mut encoder = device.create_command_encoder(&Default::default());
-        {
-            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Compute pass"),
-                timestamp_writes: None,
-            });
-            cpass.set_pipeline(&res.compute_pipeline);
-            cpass.set_bind_group(0, &res.bind_group, &[]);
 
-            cpass.dispatch_workgroups(self.params.w, self.params.h, 1);
+        if self.surface.is_some() {
+            render_pass.set_pipeline(&res.surface_pipeline);
+            render_pass.set_bind_group(0, &res.surface_bg, &[]);
+            render_pass.set_index_buffer(res.surface_index_buf.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..6 * (SURFACE_GRID - 1).pow(2), 0, 0..1);
+            return;
+        }
+
+        render_pass.set_pipeline(if self.blend { &res.blend_pipeline } else { &res.render_pipeline });
+        render_pass.set_vertex_buffer(0, res.vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, if self.nearest { &res.nearest_bg } else { &res.render_bg }, &[]);
+        render_pass.draw(0..4, 0..1);
+
+        if let Some(tracers) = &res.tracers {
+            render_pass.set_pipeline(&res.trail_pipeline);
+            render_pass.set_bind_group(0, &tracers.render_bg, &[]);
+            render_pass.draw(0..tracers.info.trail_len, 0..tracers.info.count);
+        }
+    }
+}
+
+// Draws a zoomed-in window of the output texture without advancing the simulation
+#[derive(Debug, Clone, Copy)]
+pub struct Magnifier {
+    window: TexWindow,
+}
+
+impl eframe::egui_wgpu::CallbackTrait for Magnifier {
+    fn prepare(
+        &self,
+        _device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _screen_descriptor: &ScreenDescriptor,
+        _egui_encoder: &mut wgpu::CommandEncoder,
+        callback_resources: &mut eframe::egui_wgpu::CallbackResources,
+    ) -> Vec<wgpu::CommandBuffer> {
+        // Not available on the fragment-only path
+        if let Some(res) = callback_resources.get::<GPUSimResources>() {
+            queue.write_buffer(&res.magnifier_buf, 0, bytemuck::bytes_of(&self.window));
         }
-        vec![encoder.finish()]
+        Vec::new()
     }
 
     fn paint<'a, 'b, 'c>(
@@ -524,11 +3584,283 @@ impl eframe::egui_wgpu::CallbackTrait for GPUSim {
         render_pass: &mut wgpu::RenderPass<'b>,
         callback_resources: &'c eframe::egui_wgpu::CallbackResources,
     ) {
-        let res: &GPUSimResources = callback_resources.get().unwrap();
+        let Some(res) = callback_resources.get::<GPUSimResources>() else {
+            return;
+        };
 
         render_pass.set_pipeline(&res.render_pipeline);
         render_pass.set_vertex_buffer(0, res.vertex_buffer.slice(..));
-        render_pass.set_bind_group(0, &res.render_bg, &[]);
+        render_pass.set_bind_group(0, &res.magnifier_bg, &[]);
         render_pass.draw(0..4, 0..1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALE: f32 = 25.0;
+
+    fn particles(width: u32, height: u32, velocity_pattern: u32) -> Vec<Particle> {
+        let params = Params { velocity_pattern, ..Params::default(width, height) };
+        GPUSim::create_particles(width, height, SCALE, &params)
+    }
+
+    #[test]
+    fn half_floats_decode() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn corner_pixels_map_to_the_canvas_edges() {
+        let (w, h) = (8, 4);
+        let ps = particles(w, h, 3);
+        assert_eq!(ps.len(), (w * h) as usize);
+
+        // Pixel (0, 0) starts at the bottom-left corner of the world square
+        assert_eq!(ps[0].u, vec2(-SCALE / 2.0, -SCALE / 2.0));
+        // The last pixel starts one pixel short of the top-right corner
+        let last = ps[(w * h - 1) as usize].u;
+        let expected = vec2((w - 1) as f32 / w as f32 - 0.5, (h - 1) as f32 / h as f32 - 0.5) * SCALE;
+        assert!((last - expected).length() < 1e-5, "{last} != {expected}");
+        // Pixels run along x first
+        assert!(ps[1].u.x > ps[0].u.x && ps[1].u.y == ps[0].u.y);
+        assert!(ps[w as usize].u.y > ps[0].u.y && ps[w as usize].u.x == ps[0].u.x);
+    }
+
+    #[test]
+    fn velocity_patterns_point_the_expected_way() {
+        let magnitude = Params::default(1, 1).velocity_magnitude;
+        let (w, h) = (5, 5);
+
+        for p in particles(w, h, 0).iter().filter(|p| p.u.length() > 0.001) {
+            // Radial: outward along the position
+            assert!((p.du.length() - magnitude).abs() < 1e-4);
+            assert!(p.du.normalize().dot(p.u.normalize()) > 0.9999, "{p:?}");
+        }
+        for p in particles(w, h, 1).iter().filter(|p| p.u.length() > 0.001) {
+            // Tangential: perpendicular, turning counterclockwise
+            assert!((p.du.length() - magnitude).abs() < 1e-4);
+            assert!(p.du.dot(p.u).abs() < 1e-3, "{p:?}");
+            assert!(p.u.perp_dot(p.du) > 0.0, "{p:?}");
+        }
+        let uniform = particles(w, h, 2);
+        assert!(uniform.iter().all(|p| p.du == uniform[0].du));
+        assert!((uniform[0].du - vec2(magnitude, 0.0)).length() < 1e-5);
+        assert!(particles(w, h, 3).iter().all(|p| p.du == Vec2::ZERO));
+    }
+
+    #[test]
+    fn default_euler_steps_match_the_original_update_exactly() {
+        // The update before softness, per-magnet strengths and masses existed
+        let params = Params { dt: 0.02, ..Params::default(1, 1) };
+        let original = |u: Vec2, du: Vec2| {
+            let mut ddu = Vec2::ZERO;
+            for i in 0..params.n {
+                let diff = params.magnet_position(i) - u;
+                let sq = (diff.length_squared() + params.d * params.d).sqrt();
+                ddu += diff / (sq * sq * sq);
+            }
+            ddu -= params.mu * du + params.c * u;
+            let du = du + ddu * params.dt;
+            (u + du * params.dt, du)
+        };
+        let (mut u, mut du) = (vec2(2.0, -1.0), vec2(0.0, 1.5));
+        let (mut p, magnets) = (Particle::new(u, du), params.ring_magnets());
+        for _ in 0..500 {
+            (u, du) = original(u, du);
+            p = step_particle(&p, &params, &magnets);
+            assert_eq!((p.position(), p.velocity()), (u, du));
+        }
+    }
+
+    #[test]
+    fn jitter_patterns_seed_the_expected_offsets() {
+        let (w, h) = (16, 16);
+        // Sub-pixel offset of every particle's start, recovered from its position
+        let offsets = |jitter_pattern, jitter_sample, seed| -> Vec<Vec2> {
+            let params = Params { jitter_pattern, jitter_sample, seed, ..Params::default(w, h) };
+            GPUSim::create_particles(w, h, SCALE, &params).iter().enumerate().map(|(i, p)| {
+                let pixel = vec2((i as u32 % w) as f32, (i as u32 / w) as f32);
+                (p.u / SCALE + 0.5) * vec2(w as f32, h as f32) - pixel
+            }).collect()
+        };
+        // Offsets wrap around the pixel, so compare them modulo 1
+        let close = |a: Vec2, b: Vec2| {
+            ((a - b + 0.5).rem_euclid(Vec2::ONE) - 0.5).abs().max_element() < 1e-3
+        };
+
+        assert!(offsets(0, 5, 0).iter().all(|o| o.abs().max_element() < 1e-3), "no jitter starts at pixel corners");
+        for pattern in 1..4 {
+            let first = offsets(pattern, 0, 0);
+            assert!(first.iter().all(|o| o.min_element() > -1e-3 && o.max_element() < 1.0 + 1e-3), "pattern {pattern}");
+            assert!(first.iter().zip(&offsets(pattern, 1, 0)).all(|(a, b)| !close(*a, *b)), "pattern {pattern} didn't move between samples");
+        }
+
+        // White noise depends on the seed
+        assert!(offsets(1, 0, 0).iter().zip(&offsets(1, 0, 1)).any(|(a, b)| !close(*a, *b)));
+        // Halton keeps a per-pixel rotation and steps every pixel along the (2, 3) sequence
+        let halton = |i| vec2(radical_inverse(i, 2), radical_inverse(i, 3));
+        let (start, fourth) = (offsets(2, 0, 0), offsets(2, 3, 0));
+        for (a, b) in start.iter().zip(&fourth) {
+            assert!(close(*b - *a, halton(4) - halton(1)), "{a} {b}");
+        }
+        // Interleaved gradient noise ignores the seed and steps along R2
+        let (start, next) = (offsets(3, 0, 0), offsets(3, 1, 0));
+        assert!(start.iter().zip(&offsets(3, 0, 7)).all(|(a, b)| close(*a, *b)));
+        for (a, b) in start.iter().zip(&next) {
+            assert!(close(*b - *a, vec2(0.754_877_7, 0.569_840_3)), "{a} {b}");
+        }
+    }
+
+    #[test]
+    fn energy_is_conserved_without_friction_and_decays_with_it() {
+        for softness in [2.0, 3.0] {
+            let params = Params { mu: 0.0, integrator: 1, dt: 0.005, d: 0.5, softness, spring_cubic: 0.05, ..Params::default(1, 1) };
+            let (start, magnets) = (Particle::new(vec2(2.0, -1.0), vec2(0.0, 1.5)), params.ring_magnets());
+            let e0 = params.energy(&start, &magnets);
+            let end = (0..2000).fold(start, |p, _| step_particle(&p, &params, &magnets));
+            let e1 = params.energy(&end, &magnets);
+            assert!((e1 - e0).abs() < 1e-3 * e0.abs().max(1.0), "softness {softness}: {e0} -> {e1}");
+        }
+
+        let params = Params { mu: 0.1, integrator: 1, dt: 0.005, d: 0.5, ..Params::default(1, 1) };
+        let (mut p, magnets) = (Particle::new(vec2(2.0, -1.0), vec2(0.0, 1.5)), params.ring_magnets());
+        let mut energy = params.energy(&p, &magnets);
+        for _ in 0..500 {
+            p = step_particle(&p, &params, &magnets);
+            let next = params.energy(&p, &magnets);
+            assert!(next <= energy + 1e-5, "{energy} -> {next}");
+            energy = next;
+        }
+    }
+
+    #[test]
+    fn rust_export_assigns_saved_fields_in_order() {
+        let params = Params { n: 3, c: 0.1, ..Params::default(64, 32) };
+        let rust = params.to_rust("p");
+        assert!(rust.starts_with("p.n = 3;\np.r = "), "{rust}");
+        assert!(rust.contains("p.c = 0.1;\n"), "{rust}");
+        assert!(!rust.contains("p.w ") && !rust.contains("p.h "), "{rust}");
+        assert_eq!(rust.lines().count(), serde_json::to_value(params).unwrap().as_object().unwrap().len());
+    }
+
+    #[test]
+    fn lerp_blends_floats_and_switches_counts_halfway() {
+        let a = Params { n: 3, mu: 0.1, dt: 0.01, integrator: 0, ..Params::default(64, 64) };
+        let b = Params { n: 6, mu: 0.3, dt: 0.02, integrator: 1, ..Params::default(8, 8) };
+        let quarter = a.lerp(&b, 0.25);
+        assert!((quarter.mu - 0.15).abs() < 1e-6 && (quarter.dt - 0.0125).abs() < 1e-6);
+        assert_eq!((quarter.n, quarter.integrator, quarter.w), (3, 0, 64));
+        let three_quarters = a.lerp(&b, 0.75);
+        assert_eq!((three_quarters.n, three_quarters.integrator, three_quarters.w), (6, 1, 64));
+        assert_eq!(a.lerp(&b, 1.0).mu, b.mu);
+    }
+
+    #[test]
+    fn polar_rows_are_radii_and_columns_angles() {
+        let params = Params { seed_coords: 1, velocity_pattern: 3, ..Params::default(8, 4) };
+        let ps = GPUSim::create_particles(8, 4, SCALE, &params);
+        for (i, p) in ps.iter().enumerate() {
+            let (x, y) = (i % 8, i / 8);
+            assert!((p.u.length() - y as f32 / 4.0 * SCALE / 2.0).abs() < 1e-4, "{p:?}");
+            if y > 0 {
+                let angle = p.u.y.atan2(p.u.x).rem_euclid(2.0 * PI);
+                assert!((angle - x as f32 / 8.0 * 2.0 * PI).abs() < 1e-4, "{p:?}");
+            }
+        }
+        for seed_coords in [0, 1] {
+            let params = Params { seed_coords, ..params };
+            let cell = vec2(0.3, 0.7);
+            let back = params.seed_cell(params.seed_position(cell, SCALE), SCALE);
+            assert!((back - cell).length() < 1e-5, "{seed_coords}: {back}");
+        }
+    }
+
+    #[test]
+    fn zoom_and_pan_reseed_a_smaller_region() {
+        let params = Params { velocity_pattern: 3, center_x: 1.5, center_y: -0.5, zoom: 10.0, ..Params::default(4, 4) };
+        let ps = GPUSim::create_particles(4, 4, SCALE, &params);
+        // Cartesian cells are a tenth as far apart, around the new centre
+        assert!((ps[1].u.x - ps[0].u.x - SCALE / 40.0).abs() < 1e-5);
+        assert!((ps[0].u - vec2(1.5, -0.5) + Vec2::splat(SCALE / 20.0)).length() < 1e-5, "{:?}", ps[0].u);
+        // Masses follow world position, so zooming doesn't change the physics
+        assert_eq!(ps[0].mass, params.mass_at(ps[0].u, SCALE));
+        for seed_coords in [0, 1] {
+            let params = Params { seed_coords, ..params };
+            let cell = vec2(0.3, 0.7);
+            let back = params.seed_cell(params.seed_position(cell, SCALE), SCALE);
+            assert!((back - cell).length() < 1e-5, "{seed_coords}: {back}");
+        }
+    }
+
+    #[test]
+    fn mass_scales_the_response_to_forces() {
+        let params = Params { mu: 0.2, dt: 0.01, ..Params::default(1, 1) };
+        let light = Particle::new(vec2(2.0, -1.0), vec2(0.5, 1.5));
+        let heavy = light.with_mass(4.0);
+        let magnets = params.ring_magnets();
+        let dv_light = step_particle(&light, &params, &magnets).du - light.du;
+        let dv_heavy = step_particle(&heavy, &params, &magnets).du - heavy.du;
+        assert!((dv_light - 4.0 * dv_heavy).length() < 1e-5, "{dv_light} vs {dv_heavy}");
+
+        let graded = Params { mass_min: 0.5, mass_max: 2.0, ..params };
+        assert_eq!(graded.mass_at(Vec2::ZERO, SCALE), 0.5);
+        let ps = GPUSim::create_particles(4, 4, SCALE, &graded);
+        assert_eq!(ps[0].mass, 2.0);
+    }
+
+    #[test]
+    fn sanitize_keeps_d_off_zero() {
+        for d in [0.0, -1.0, MIN_D / 2.0] {
+            let mut params = Params { d, ..Params::default(1, 1) };
+            params.sanitize();
+            assert_eq!(params.d, MIN_D);
+        }
+        let mut params = Params { d: f32::NAN, ..Params::default(1, 1) };
+        params.sanitize();
+        assert_eq!(params.d, Params::default(1, 1).d);
+    }
+
+    #[test]
+    fn magnet_table_holds_the_ring_unless_replaced() {
+        let params = Params { n: 5, r: 1.5, ..Params::default(1, 1) };
+        let table = magnet_table(&params, None);
+        assert_eq!(table.len(), MAX_MAGNETS);
+        for (i, magnet) in (0..5).zip(&table) {
+            assert_eq!(magnet.position, params.magnet_position(i));
+            assert_eq!(magnet.strength, 1.0);
+            assert_eq!(magnet.color, params.magnet_color(i));
+        }
+        assert_eq!(table[5], Magnet::zeroed());
+
+        let custom = [Magnet::new(vec2(0.5, -2.0), 3.0, [1.0, 0.0, 0.0, 1.0])];
+        let table = magnet_table(&params, Some(&custom));
+        assert_eq!(table[0], custom[0]);
+        assert_eq!(table[1], Magnet::zeroed());
+    }
+
+    #[test]
+    fn cpu_mirrors_follow_the_magnet_table() {
+        // Without friction or the spring only the magnets act on a pendulum at rest
+        let params = Params { mu: 0.0, c: 0.0, dt: 0.01, ..Params::default(1, 1) };
+        let at = |strength| [Magnet::new(vec2(1.0, 2.0), strength, [0.0; 4]), Magnet::new(vec2(-3.0, 0.0), 1.0, [0.0; 4])];
+        let p = Particle::new(vec2(0.5, 1.5), Vec2::ZERO);
+        let (unit, strong) = (at(1.0), at(3.0));
+        assert_eq!(nearest_magnet(&unit, p.u), 0);
+        assert_eq!(nearest_magnet(&unit, vec2(-2.0, 0.5)), 1);
+
+        // Tripling the near magnet triples its share of the pull and of the potential
+        let far = [unit[1]];
+        let dv = |magnets: &[Magnet]| step_particle(&p, &params, magnets).du;
+        assert!((dv(&strong) - dv(&far) - 3.0 * (dv(&unit) - dv(&far))).length() < 1e-5);
+        let v = |magnets: &[Magnet]| params.energy(&p, magnets);
+        assert!((v(&strong) - v(&far) - 3.0 * (v(&unit) - v(&far))).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,95 @@
+// The non-blocking readbacks the app polls every frame must report what the blocking
+// ones do, once their copy has mapped.
+
+mod common;
+
+use common::require_gpu;
+use glam::Vec2;
+use gpusim::sim::{GPUSim, Particle};
+
+#[test]
+fn poll_diverged_count_matches_the_blocking_count() {
+    let rs = require_gpu!();
+    let mut sim = GPUSim::new(&rs, 16, 16, 25.0);
+    // A step this large throws some pendulums out to infinity
+    (sim.params.dt, sim.params.d) = (50.0, 0.05);
+    sim.advance(&rs, 20);
+    let expected = sim.diverged_count(&rs).unwrap();
+    assert!(expected > 0, "nothing diverged, so the test shows nothing");
+
+    assert_eq!(sim.poll_diverged_count(&rs), None, "the first poll only starts the readback");
+    let count = (0..10_000).find_map(|_| sim.poll_diverged_count(&rs)).expect("the readback never mapped");
+    assert_eq!(count, expected);
+}
+
+#[test]
+fn poll_max_speed_matches_the_blocking_reduction() {
+    let rs = require_gpu!();
+    let mut sim = GPUSim::new(&rs, 16, 16, 25.0);
+    sim.steps_per_frame = 10;
+    sim.advance(&rs, 3);
+    let expected = sim.max_speed(&rs).unwrap();
+
+    assert!(sim.request_max_speed(&rs));
+    let (step, speed) = (0..10_000).find_map(|_| sim.poll_max_speed(&rs)).expect("the readback never mapped");
+    assert_eq!((step, speed), (30, expected));
+    assert_eq!(sim.poll_finished(&rs, f32::MAX, u64::MAX), None, "a result is returned once");
+}
+
+#[test]
+fn poll_scalar_range_matches_the_blocking_range() {
+    let rs = require_gpu!();
+    let mut sim = GPUSim::new(&rs, 16, 16, 25.0);
+    sim.params.color_mode = 1; // speed
+    sim.steps_per_frame = 10;
+    sim.advance(&rs, 3);
+    // One pendulum flung out to infinity, which must not stretch the range
+    let mut particles = sim.read_particles(&rs).unwrap();
+    particles[5] = Particle::new(particles[5].position(), Vec2::splat(f32::INFINITY));
+    sim.load_particles(&rs, &particles).unwrap();
+    let expected = sim.scalar_range(&rs).unwrap();
+    assert!(expected.0.is_finite() && expected.1.is_finite(), "range {expected:?}");
+
+    assert!(sim.request_scalar_range(&rs));
+    let range = (0..10_000).find_map(|_| sim.poll_scalar_range(&rs)).expect("the readback never mapped");
+    assert_eq!(range, expected);
+    assert_eq!(sim.poll_scalar_range(&rs), None, "a range is returned once");
+}
+
+#[test]
+fn request_output_matches_read_output() {
+    let rs = require_gpu!();
+    let (width, height) = (72, 40); // rows of 288 bytes, so the readback is padded
+    let sim = GPUSim::new(&rs, width, height, 25.0);
+    sim.advance(&rs, 5);
+
+    let pending = sim.request_output(&rs).unwrap();
+    let image = (0..10_000)
+        .find_map(|_| match pending.poll(&rs) {
+            std::task::Poll::Ready(image) => Some(image.expect("the map failed")),
+            std::task::Poll::Pending => None,
+        })
+        .expect("the readback never mapped");
+    assert!(image == sim.read_output(&rs).unwrap());
+}
+
+#[test]
+fn poll_scalars_reads_back_only_the_requested_cells() {
+    let rs = require_gpu!();
+    let (width, height) = (24, 16);
+    let mut sim = GPUSim::new(&rs, width, height, 25.0);
+    sim.steps_per_frame = 10;
+    sim.advance(&rs, 5);
+
+    assert!(!sim.request_scalars(&rs, &[[width, 0]]));
+    let cells = [[3, 0], [7, 11], [23, 15], [3, 0]];
+    assert!(sim.request_scalars(&rs, &cells));
+    assert!(!sim.request_scalars(&rs, &cells), "a second request started while one is in flight");
+    let scalars = (0..10_000).find_map(|_| sim.poll_scalars(&rs)).expect("the readback never mapped");
+    assert!(sim.poll_scalars(&rs).is_none(), "scalars are returned once");
+
+    let particles = sim.read_particles(&rs).unwrap();
+    let expected: Vec<f32> =
+        cells.iter().map(|&[x, y]| sim.params.color_scalar(&particles[(y * width + x) as usize])).collect();
+    assert_eq!(scalars, expected);
+}
@@ -0,0 +1,40 @@
+// Headless device setup and helpers shared by the GPU tests. Not every test binary
+// uses every helper.
+#![allow(dead_code)]
+
+use eframe::egui_wgpu::{self, CallbackTrait, RenderState};
+use gpusim::sim::GPUSim;
+
+pub fn render_state() -> Option<RenderState> {
+    gpusim::sim::headless_render_state()
+}
+
+// The headless render state, or returns from the test when there's no GPU adapter, so
+// GPU tests pass vacuously without one
+macro_rules! require_gpu {
+    () => {
+        match common::render_state() {
+            Some(rs) => rs,
+            None => {
+                eprintln!("no GPU adapter, skipping");
+                return;
+            }
+        }
+    };
+}
+pub(crate) use require_gpu;
+
+// Runs the sim's per-frame work without drawing it anywhere
+pub fn run_frames(rs: &RenderState, sim: &GPUSim, frames: usize) {
+    // The sim doesn't draw to the screen in `prepare`, so any size will do
+    let screen = egui_wgpu::ScreenDescriptor { size_in_pixels: [1, 1], pixels_per_point: 1.0 };
+    for _ in 0..frames {
+        let mut encoder = rs.device.create_command_encoder(&Default::default());
+        let mut buffers = {
+            let mut renderer = rs.renderer.write();
+            sim.prepare(&rs.device, &rs.queue, &screen, &mut encoder, &mut renderer.callback_resources)
+        };
+        buffers.push(encoder.finish());
+        rs.queue.submit(buffers);
+    }
+}
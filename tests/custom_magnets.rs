@@ -0,0 +1,39 @@
+// A custom magnet layout replaces the ring for the shader and the CPU mirrors alike:
+// the GPU steps every pendulum as `step_particle` does among `GPUSim::magnets`, and
+// readouts name the nearest magnet of the layout.
+
+mod common;
+
+use common::require_gpu;
+use glam::vec2;
+use gpusim::sim::{nearest_magnet, step_particle, GPUSim, Magnet};
+
+#[test]
+fn gpu_steps_match_the_cpu_mirror_among_custom_magnets() {
+    let rs = require_gpu!();
+    let (width, height) = (16, 16);
+    let sim = GPUSim::new(&rs, width, height, 25.0);
+    let custom = vec![
+        Magnet::new(vec2(3.0, 1.0), 2.0, [1.0, 0.0, 0.0, 1.0]),
+        Magnet::new(vec2(-2.5, 2.0), 2.0, [0.0, 1.0, 0.0, 1.0]),
+        Magnet::new(vec2(0.5, -3.0), 0.5, [0.0, 0.0, 1.0, 1.0]),
+    ];
+    sim.set_magnets(&rs, Some(custom.clone()));
+    assert_eq!(sim.magnets(&rs), custom);
+
+    // The first frame uploads the layout, so compare the steps after it
+    sim.advance(&rs, 1);
+    let before = sim.read_particles(&rs).unwrap();
+    sim.advance(&rs, 1);
+    let after = sim.read_particles(&rs).unwrap();
+    for (p, gpu) in before.iter().zip(&after) {
+        let cpu = step_particle(p, &sim.params, &custom);
+        assert!((cpu.position() - gpu.position()).length() < 1e-4, "{:?} vs {:?}", cpu.position(), gpu.position());
+        assert!((cpu.velocity() - gpu.velocity()).length() < 1e-3, "{:?} vs {:?}", cpu.velocity(), gpu.velocity());
+    }
+
+    let (x, y) = (3, 12);
+    assert!(sim.request_pendulum(&rs, x, y));
+    let readout = (0..10_000).find_map(|_| sim.poll_pendulum(&rs)).expect("the readback never mapped");
+    assert_eq!(readout.magnet, Some(nearest_magnet(&custom, readout.position)));
+}
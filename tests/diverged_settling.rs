@@ -0,0 +1,40 @@
+// Diverged pendulums never slow down, so the settle check has to leave them out, or a
+// run with one would only ever stop at its step budget.
+
+mod common;
+
+use common::require_gpu;
+use glam::Vec2;
+use gpusim::sim::{GPUSim, Particle};
+
+const THRESHOLD: f32 = 0.01;
+// The reductions run on 16×16 tiles. max() drops a NaN unless its whole tile is NaN,
+// so the test diverges the left tile of a grid two tiles wide
+const TILE: u32 = 16;
+
+#[test]
+fn a_run_with_diverged_pendulums_still_settles() {
+    let rs = require_gpu!();
+    let mut sim = GPUSim::new(&rs, 2 * TILE, TILE, 25.0);
+    let mut particles = sim.read_particles(&rs).unwrap();
+    for (i, p) in particles
+        .iter_mut()
+        .enumerate()
+        .filter(|(i, _)| (*i as u32 % (2 * TILE)) < TILE)
+    {
+        *p = Particle::new(p.position(), Vec2::splat([f32::INFINITY, f32::NAN][i % 2]));
+    }
+    sim.load_particles(&rs, &particles).unwrap();
+    let diverged = TILE * TILE;
+    assert_eq!(sim.diverged_count(&rs), Some(diverged));
+
+    // Strong friction brings the rest to rest
+    sim.params.mu = 2.0;
+    sim.steps_per_frame = 100;
+    let settled = (0..100).any(|_| {
+        sim.advance(&rs, 1);
+        sim.is_steady(&rs, THRESHOLD)
+    });
+    assert!(settled, "still moving at {:?}", sim.max_speed(&rs));
+    assert_eq!(sim.diverged_count(&rs), Some(diverged));
+}
@@ -0,0 +1,26 @@
+// Exports must come out at the simulation's resolution with the params embedded,
+// whatever size the canvas is drawn at.
+
+mod common;
+
+use common::require_gpu;
+use gpusim::export::{read_metadata, read_rgba};
+use gpusim::sim::GPUSim;
+
+#[test]
+fn export_png_writes_the_native_resolution() {
+    let rs = require_gpu!();
+    let (width, height) = (72, 40); // rows of 288 bytes, so the readback is padded
+    let sim = GPUSim::new(&rs, width, height, 25.0);
+    let path = std::env::temp_dir().join(format!("gpusim-export-{}.png", std::process::id()));
+    sim.export_png(&rs, &path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    let (w, h, rgba) = read_rgba(&bytes).unwrap();
+    assert_eq!((w, h), (width, height));
+    assert_eq!(rgba, sim.read_output(&rs).unwrap());
+    let metadata = read_metadata(&bytes).unwrap();
+    assert_eq!((metadata.width, metadata.height), (width, height));
+    assert_eq!(metadata.colormap, "twilight");
+}
@@ -0,0 +1,57 @@
+// The integrators converge at their claimed order: halving dt shrinks the error at a
+// fixed simulated time by 2 for semi-implicit Euler and by 16 for RK4. The adaptive
+// integrator instead holds its error down at a step where RK4 is inaccurate.
+
+use glam::vec2;
+use gpusim::sim::{step_particle, Params, Particle};
+
+const DURATION: f32 = 2.0;
+
+// State after DURATION of simulated time, stepping with dt
+fn integrate(integrator: u32, dt: f32) -> Particle {
+    let params = Params { integrator, dt, d: 1.0, ..Params::default(1, 1) };
+    let (steps, magnets) = ((DURATION / dt).round() as u32, params.ring_magnets());
+    (0..steps).fold(Particle::new(vec2(2.0, -1.0), vec2(0.0, 1.5)), |p, _| step_particle(&p, &params, &magnets))
+}
+
+fn error(p: &Particle, reference: &Particle) -> f32 {
+    ((p.position() - reference.position()).length_squared()
+        + (p.velocity() - reference.velocity()).length_squared())
+    .sqrt()
+}
+
+// Slope of log(error) against log(dt) between successive halvings of dt, with the
+// errors themselves for the failure message
+fn observed_orders(integrator: u32, dts: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    let reference = integrate(1, dts[dts.len() - 1] / 8.0);
+    let errors: Vec<f32> = dts.iter().map(|&dt| error(&integrate(integrator, dt), &reference)).collect();
+    (errors.windows(2).map(|e| (e[0] / e[1]).log2()).collect(), errors)
+}
+
+#[test]
+fn euler_is_first_order() {
+    let (orders, errors) = observed_orders(0, &[0.02, 0.01, 0.005]);
+    for order in orders {
+        assert!((order - 1.0).abs() < 0.25, "observed order {order}, errors {errors:?}");
+    }
+}
+
+#[test]
+fn rk4_is_fourth_order() {
+    let (orders, errors) = observed_orders(1, &[0.4, 0.2, 0.1]);
+    for order in orders {
+        assert!((order - 4.0).abs() < 0.5, "observed order {order}, errors {errors:?}");
+    }
+}
+
+#[test]
+fn adaptive_meets_its_tolerance_where_rk4_does_not() {
+    let reference = integrate(1, 0.1 / 8.0);
+    let dt = 0.4;
+    let rk4 = error(&integrate(1, dt), &reference);
+    let params = Params { integrator: 2, dt, d: 1.0, tolerance: 1e-6, max_substeps: 64, ..Params::default(1, 1) };
+    let (steps, magnets) = ((DURATION / dt).round() as u32, params.ring_magnets());
+    let adaptive = (0..steps).fold(Particle::new(vec2(2.0, -1.0), vec2(0.0, 1.5)), |p, _| step_particle(&p, &params, &magnets));
+    let adaptive = error(&adaptive, &reference);
+    assert!(adaptive < 1e-3 && adaptive < rk4 / 10.0, "adaptive {adaptive}, rk4 {rk4}");
+}
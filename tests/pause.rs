@@ -0,0 +1,30 @@
+// A paused sim skips its compute pass, so the image must stay exactly as it was when
+// the run paused, yet still redraw when a display setting changes.
+
+mod common;
+
+use common::{require_gpu, run_frames};
+use gpusim::sim::GPUSim;
+
+const SIZE: u32 = 32;
+
+#[test]
+fn paused_frames_keep_the_image_until_the_display_changes() {
+    let rs = require_gpu!();
+    let mut sim = GPUSim::new(&rs, SIZE, SIZE, 25.0);
+    sim.steps_per_frame = 4;
+    run_frames(&rs, &sim, 5);
+    let running = sim.read_output(&rs).unwrap();
+    let steps = sim.step_count(&rs);
+
+    // The app pauses by drawing a copy with dt = 0
+    let mut paused = sim;
+    paused.params.dt = 0.0;
+    run_frames(&rs, &paused, 3);
+    assert_eq!(sim.step_count(&rs), steps);
+    assert!(sim.read_output(&rs).unwrap() == running, "pausing changed the image");
+
+    paused.params.color_max *= 0.5;
+    run_frames(&rs, &paused, 1);
+    assert!(sim.read_output(&rs).unwrap() != running, "a new color range wasn't drawn while paused");
+}
@@ -0,0 +1,29 @@
+// Clicking the canvas reads back one pendulum without blocking. It has to be the
+// pendulum at that grid cell, as the full particle readback holds it.
+
+mod common;
+
+use common::require_gpu;
+use gpusim::sim::{nearest_magnet, GPUSim};
+
+#[test]
+fn request_pendulum_reads_back_that_cell() {
+    let rs = require_gpu!();
+    let (width, height) = (24, 16);
+    let mut sim = GPUSim::new(&rs, width, height, 25.0);
+    sim.steps_per_frame = 10;
+    sim.advance(&rs, 5);
+
+    assert!(!sim.request_pendulum(&rs, width, 0));
+    assert!(!sim.request_pendulum(&rs, 0, height));
+    let (x, y) = (7, 11);
+    assert!(sim.request_pendulum(&rs, x, y));
+    let readout = (0..10_000).find_map(|_| sim.poll_pendulum(&rs)).expect("the readback never mapped");
+    assert!(sim.poll_pendulum(&rs).is_none(), "a readout is returned once");
+
+    let expected = sim.read_particles(&rs).unwrap()[(y * width + x) as usize];
+    assert_eq!(readout.cell, [x, y]);
+    assert_eq!(readout.position, expected.position());
+    assert_eq!(readout.velocity, expected.velocity());
+    assert_eq!(readout.magnet, Some(nearest_magnet(&sim.magnets(&rs), expected.position())));
+}